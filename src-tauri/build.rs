@@ -1,3 +1,6 @@
 fn main() {
-    tauri_build::build()
+    // Headless builds (--no-default-features) have no Tauri app to generate
+    if std::env::var_os("CARGO_FEATURE_GUI").is_some() {
+        tauri_build::build()
+    }
 }
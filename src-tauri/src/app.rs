@@ -0,0 +1,355 @@
+use tauri::{Emitter, Manager, State, path::BaseDirectory};
+use std::sync::{Arc, Mutex};
+
+use crate::capture::{NetworkInterface, ReplayMode};
+use crate::classifier::{ClassifierConfig, ShadowReport, Thresholds};
+use crate::config::{LaytonConfig, CONFIG_FILE_NAME};
+use crate::export::{CsvExporter, ExportLabel};
+use crate::pipeline::{EventSink, PacketSource, Pipeline, PipelineOptions};
+use crate::processor::{EngineConfig, FlowConfig};
+use crate::queue::QueuesConfig;
+use crate::store::{FlowPage, FlowQuery, FlowStore, STORE_FILE_NAME};
+use crate::types::{ClassifiedFlowEvent, HealthEvent, NetworkStats};
+
+
+pub struct AppState {
+    pub pipeline: Arc<Mutex<Option<Pipeline>>>,
+    pub selected_interface: Arc<Mutex<Option<String>>>,
+    // Timeouts used by the next session, from layton.json or set by the frontend
+    pub flow_config: Arc<Mutex<FlowConfig>>,
+    // Number of flow engine workers for the next session
+    pub engine_config: Arc<Mutex<EngineConfig>>,
+    // Inference batching for the next session
+    pub classifier_config: Arc<Mutex<ClassifierConfig>>,
+    // Decision thresholds, also pushed to a running session
+    pub thresholds: Arc<Mutex<Thresholds>>,
+    // Stage queue sizes and overload policies for the next session
+    pub queue_config: Arc<Mutex<QueuesConfig>>,
+    // CSV dataset export, independent from the capture session
+    pub exporter: Arc<Mutex<Option<CsvExporter>>>,
+    // History of classified flows, None when disabled or it couldn't be opened
+    pub store: Arc<Mutex<Option<FlowStore>>>,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            pipeline: Arc::new(Mutex::new(None)),
+            selected_interface: Arc::new(Mutex::new(None)),
+            flow_config: Arc::new(Mutex::new(FlowConfig::default())),
+            engine_config: Arc::new(Mutex::new(EngineConfig::default())),
+            classifier_config: Arc::new(Mutex::new(ClassifierConfig::default())),
+            thresholds: Arc::new(Mutex::new(Thresholds::default())),
+            queue_config: Arc::new(Mutex::new(QueuesConfig::default())),
+            exporter: Arc::new(Mutex::new(None)),
+            store: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+// Forwards the pipeline events to the frontend
+struct TauriSink {
+    app: tauri::AppHandle,
+}
+
+impl EventSink for TauriSink {
+    fn network_stats(&self, stats: &NetworkStats) {
+        let _ = self.app.emit("network-stats", stats);
+    }
+
+    fn flow_classified(&self, event: &ClassifiedFlowEvent) {
+        // Nombre del evento Tauri para el frontend:
+        let _ = self.app.emit("flow_classified", event);
+    }
+
+    fn health(&self, event: &HealthEvent) {
+        let _ = self.app.emit("pipeline-health", event);
+    }
+
+    fn shadow_stats(&self, report: &ShadowReport) {
+        let _ = self.app.emit("shadow-stats", report);
+    }
+}
+
+
+#[tauri::command]
+async fn list_network_devices() -> Result<Vec<NetworkInterface>, String> {
+    let devices = pcap::Device::list().map_err(|e| e.to_string())?;
+
+    let want_prefixes = ["en", "eth", "wl", "br-", "docker", "veth", "virbr", "vboxnet"];
+
+    let filtered: Vec<NetworkInterface> = devices
+        .into_iter()
+        .filter(|d| {
+            let n = d.name.as_str();
+            // keep common NICs, docker bridges, and virt adapters; drop loopback
+            (want_prefixes.iter().any(|p| n.starts_with(p))) && !d.flags.is_loopback()
+        })
+        .map(|d| {
+            let description = d.desc.unwrap_or_else(|| {
+                if d.name.starts_with("br-") || d.name == "docker0" { "Docker Bridge".into() }
+                else if d.name.starts_with("en") || d.name.starts_with("eth") { "Ethernet Interface".into() }
+                else if d.name.starts_with("wl") { "Wi-Fi Interface".into() }
+                else { "Network Interface".into() }
+            });
+            NetworkInterface { name: d.name, description, is_up: d.flags.is_up() }
+        })
+        .collect();
+    Ok(filtered)
+}
+
+
+#[tauri::command]
+fn get_selected_interface_info(interface_name: String) -> Result<NetworkInterface, String>{
+    let devices = pcap::Device::list().map_err(|e| e.to_string())?;
+    for device in devices{
+        if device.name == interface_name{
+            return Ok(NetworkInterface {
+                name: device.name,
+                description: device.desc.unwrap_or_else(|| "No description".to_string()),
+                is_up:device.flags.is_up(),
+            });
+        }
+    }
+    Err(format!("Interface '{}' not found", interface_name))
+}
+
+#[tauri::command]
+fn start_system(interface: &str, state: State<AppState>, app_handle: tauri::AppHandle) -> Result<(), String>{
+    launch_pipeline(state, app_handle, PacketSource::Interface(interface.to_string()))?;
+    println!("Sniffer started succesfully");
+    Ok(())
+}
+
+// Runs a saved pcap/pcapng file through the same pipeline as a live interface
+#[tauri::command]
+fn start_replay(path: &str, mode: ReplayMode, state: State<AppState>, app_handle: tauri::AppHandle) -> Result<(), String>{
+    launch_pipeline(state, app_handle, PacketSource::Replay { path: path.to_string(), mode })?;
+    println!("Replay of {path} started succesfully");
+    Ok(())
+}
+
+fn launch_pipeline(state: State<AppState>, app_handle: tauri::AppHandle, source: PacketSource) -> Result<(), String> {
+    let mut pipeline = state.pipeline.lock().map_err(|_| "Failed to lock pipeline state")?;
+    if pipeline.is_some() {
+        return Err("System is already running".into());
+    }
+
+    let models_dir = app_handle.path().resolve("classifier-models", BaseDirectory::Resource)
+        .map_err(|e| format!("Could not resolve model resource path: {e}"))?;
+    let options = PipelineOptions {
+        flow: state.flow_config.lock().map_err(|_| "Failed to lock flow config")?.clone(),
+        engine: state.engine_config.lock().map_err(|_| "Failed to lock engine config")?.clone(),
+        classifier: state.classifier_config.lock().map_err(|_| "Failed to lock classifier config")?.clone(),
+        thresholds: state.thresholds.lock().map_err(|_| "Failed to lock thresholds")?.clone(),
+        queues: state.queue_config.lock().map_err(|_| "Failed to lock queue config")?.clone(),
+        models: models_dir,
+        exporter: state.exporter.clone(),
+        store: state.store.clone(),
+    };
+
+    *pipeline = Some(Pipeline::start(&source, options, Arc::new(TauriSink { app: app_handle }))?);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_flow_config(state: State<AppState>) -> Result<FlowConfig, String> {
+    Ok(state.flow_config.lock().map_err(|_| "Failed to lock flow config")?.clone())
+}
+
+// Takes effect on the next start_system/start_replay, a running session keeps its timeouts
+#[tauri::command]
+fn set_flow_config(config: FlowConfig, state: State<AppState>) -> Result<(), String> {
+    config.validate()?;
+    *state.flow_config.lock().map_err(|_| "Failed to lock flow config")? = config;
+    Ok(())
+}
+
+#[tauri::command]
+fn get_engine_config(state: State<AppState>) -> Result<EngineConfig, String> {
+    Ok(state.engine_config.lock().map_err(|_| "Failed to lock engine config")?.clone())
+}
+
+// Also applies from the next start_system/start_replay
+#[tauri::command]
+fn set_engine_config(config: EngineConfig, state: State<AppState>) -> Result<(), String> {
+    config.validate()?;
+    *state.engine_config.lock().map_err(|_| "Failed to lock engine config")? = config;
+    Ok(())
+}
+
+#[tauri::command]
+fn get_classifier_config(state: State<AppState>) -> Result<ClassifierConfig, String> {
+    Ok(state.classifier_config.lock().map_err(|_| "Failed to lock classifier config")?.clone())
+}
+
+// Also applies from the next start_system/start_replay
+#[tauri::command]
+fn set_classifier_config(config: ClassifierConfig, state: State<AppState>) -> Result<(), String> {
+    config.validate()?;
+    *state.classifier_config.lock().map_err(|_| "Failed to lock classifier config")? = config;
+    Ok(())
+}
+
+#[tauri::command]
+fn get_thresholds(state: State<AppState>) -> Result<Thresholds, String> {
+    Ok(state.thresholds.lock().map_err(|_| "Failed to lock thresholds")?.clone())
+}
+
+// Unlike the other settings this one reaches the running session too, from its next batch
+#[tauri::command]
+fn set_thresholds(thresholds: Thresholds, state: State<AppState>) -> Result<(), String> {
+    thresholds.validate()?;
+    let pipeline = state.pipeline.lock().map_err(|_| "Failed to lock pipeline state")?;
+    if let Some(pipeline) = pipeline.as_ref() {
+        pipeline.set_thresholds(&thresholds)?;
+    }
+    *state.thresholds.lock().map_err(|_| "Failed to lock thresholds")? = thresholds;
+    Ok(())
+}
+
+// Swaps in the models on disk without stopping the capture, a bundle that fails to load
+// leaves the running models in place. Returns the new model generation.
+// The bundle loads on a blocking thread with the pipeline unlocked, so the other commands
+// and stop_system don't wait for the sessions
+#[tauri::command]
+async fn reload_models(state: State<'_, AppState>) -> Result<u64, String> {
+    let reloader = state.pipeline.lock()
+        .map_err(|_| "Failed to lock pipeline state")?
+        .as_ref()
+        .map(Pipeline::model_reloader)
+        .ok_or("System is not running, start_system loads the models from disk")?;
+    tauri::async_runtime::spawn_blocking(move || reloader.reload())
+        .await
+        .map_err(|e| format!("Model reload failed: {e}"))?
+}
+
+// Running agreement of the shadow models with the ones in use, None when no shadow bundle runs.
+// The same report comes as the `shadow-stats` event every few seconds
+#[tauri::command]
+fn get_shadow_stats(state: State<AppState>) -> Result<Option<ShadowReport>, String> {
+    let pipeline = state.pipeline.lock().map_err(|_| "Failed to lock pipeline state")?;
+    Ok(pipeline.as_ref().and_then(|p| p.shadow_report()))
+}
+
+#[tauri::command]
+fn get_queue_config(state: State<AppState>) -> Result<QueuesConfig, String> {
+    Ok(state.queue_config.lock().map_err(|_| "Failed to lock queue config")?.clone())
+}
+
+// Also applies from the next start_system/start_replay
+#[tauri::command]
+fn set_queue_config(config: QueuesConfig, state: State<AppState>) -> Result<(), String> {
+    config.validate()?;
+    *state.queue_config.lock().map_err(|_| "Failed to lock queue config")? = config;
+    Ok(())
+}
+
+// Every flow classified from now on is also written to `path` as a CICFlowMeter CSV row
+#[tauri::command]
+fn start_export(path: &str, label: ExportLabel, state: State<AppState>) -> Result<(), String> {
+    let mut exporter = state.exporter.lock().map_err(|_| "Failed to lock exporter state")?;
+    if exporter.is_some() {
+        return Err("An export is already running".into());
+    }
+    *exporter = Some(CsvExporter::create(path, label).map_err(|e| format!("Could not create {path}: {e}"))?);
+    println!("Exporting flows to {path}");
+    Ok(())
+}
+
+// Returns how many rows were written
+#[tauri::command]
+fn stop_export(state: State<AppState>) -> Result<u64, String> {
+    let mut exporter = state.exporter.lock().map_err(|_| "Failed to lock exporter state")?;
+    let mut exporter = exporter.take().ok_or("No export running")?;
+    exporter.flush().map_err(|e| format!("Error flushing export: {e}"))?;
+    println!("Export stopped, {} ICMP flows left out", exporter.skipped());
+    Ok(exporter.rows())
+}
+
+// Stored flows matching the filters, one page at a time
+#[tauri::command]
+fn query_flows(query: FlowQuery, state: State<AppState>) -> Result<FlowPage, String> {
+    let store = state.store.lock().map_err(|_| "Failed to lock flow store")?;
+    let store = store.as_ref().ok_or("Flow store is disabled")?;
+    store.query(&query).map_err(|e| format!("Flow query failed: {e}"))
+}
+
+// Returns how many in-flight flows were flushed through the classifier on the way out
+#[tauri::command]
+fn stop_system(state: State<AppState>) -> Result<usize, String> {
+    let pipeline = state.pipeline.lock()
+        .map_err(|_| "Failed to lock pipeline state")?
+        .take();
+
+    let flushed = match pipeline {
+        Some(pipeline) => pipeline.stop()?,
+        None => 0,
+    };
+
+    println!("System stopped successfully, {flushed} active flows flushed");
+    Ok(flushed)
+}
+
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .manage(AppState::default())
+        .setup(|app| {
+            // Optional settings file in the app config dir, defaults are used when it's missing
+            let path = app.path().app_config_dir()?.join(CONFIG_FILE_NAME);
+            let config = LaytonConfig::load_or_default(&path).unwrap_or_else(|e| {
+                eprintln!("Ignoring config file: {e}");
+                LaytonConfig::default()
+            });
+            let state = app.state::<AppState>();
+            *state.flow_config.lock().unwrap() = config.flow;
+            *state.engine_config.lock().unwrap() = config.engine;
+            *state.classifier_config.lock().unwrap() = config.classifier;
+            *state.thresholds.lock().unwrap() = config.thresholds;
+            *state.queue_config.lock().unwrap() = config.queues;
+
+            if config.store.enabled {
+                let db_path = match &config.store.path {
+                    Some(p) => p.into(),
+                    None => {
+                        let dir = app.path().app_data_dir()?;
+                        std::fs::create_dir_all(&dir)?;
+                        dir.join(STORE_FILE_NAME)
+                    }
+                };
+                // The app still works without history
+                match FlowStore::open(&db_path, config.store) {
+                    Ok(store) => *state.store.lock().unwrap() = Some(store),
+                    Err(e) => eprintln!("Could not open flow store {}: {e}", db_path.display()),
+                }
+            }
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            list_network_devices,
+            get_selected_interface_info,
+            start_system,
+            start_replay,
+            stop_system,
+            get_flow_config,
+            set_flow_config,
+            get_engine_config,
+            set_engine_config,
+            get_classifier_config,
+            set_classifier_config,
+            get_thresholds,
+            set_thresholds,
+            reload_models,
+            get_shadow_stats,
+            get_queue_config,
+            set_queue_config,
+            start_export,
+            stop_export,
+            query_flows,
+            ])
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+}
@@ -0,0 +1,24 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Packets lost or rejected before they reach the flow engine. Written by the sniffer
+/// thread, read by the stats publisher for every snapshot it emits. Queue drops are counted by the queue
+#[derive(Debug, Default)]
+pub struct CaptureCounters {
+    pub kernel_drops: AtomicU64,            // pcap ps_drop, no room in the capture buffer
+    pub interface_drops: AtomicU64,         // pcap ps_ifdrop, dropped by the NIC or driver
+    pub not_ip: AtomicU64,                  // Parse failures by reason
+    pub unsupported_transport: AtomicU64,
+    pub malformed: AtomicU64,
+}
+
+impl CaptureCounters {
+    #[inline]
+    pub fn bump(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn get(counter: &AtomicU64) -> i64 {
+        counter.load(Ordering::Relaxed) as i64
+    }
+}
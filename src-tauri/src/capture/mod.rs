@@ -1,4 +1,10 @@
+pub mod counters;
 pub mod sniffer;
+pub use counters::CaptureCounters;
 pub use sniffer::PacketSniffer;
+pub use sniffer::LinkLayer;
 pub use sniffer::NetworkInterface;
-pub use sniffer::ParsedPacket;
\ No newline at end of file
+pub use sniffer::ParseError;
+pub use sniffer::ParsedPacket;
+pub use sniffer::ReplayMode;
+pub use sniffer::TransportInfo;
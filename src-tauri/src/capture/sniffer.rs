@@ -1,12 +1,17 @@
-use pcap::{Active, Capture, PacketHeader};
-use serde::{Serialize};
+use pcap::{Active, Capture, Linktype, Offline, PacketHeader};
+use serde::{Deserialize, Serialize};
 use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
 use std::thread::{self, JoinHandle};
-use crossbeam_channel::Sender;
-use etherparse::{NetHeaders, PacketHeaders, TransportHeader};
+use std::time::{Duration, Instant};
+use etherparse::{EtherType, NetHeaders, PacketHeaders, TransportHeader};
 
-use crate::processor::FlowKey;
+use super::counters::CaptureCounters;
+use crate::pipeline::EventSink;
+use crate::processor::{is_icmp_query, FlowKey, PacketRouter};
+use crate::types::{Component, HealthEvent, Severity};
 
 #[derive(Debug, Clone, Serialize)]
 pub struct NetworkInterface {
@@ -18,28 +23,130 @@ pub struct NetworkInterface {
 #[derive(Debug, Clone)]
 pub struct ParsedPacket {
     pub timestamp: u64,
-    pub flow_key: FlowKey,
+    pub flow_key: FlowKey,      // Normalized, both directions share it
+    pub src_ip: IpAddr,         // Addresses and ports as seen on the wire
+    pub dst_ip: IpAddr,
+    pub src_port: u16,
+    pub dst_port: u16,
     pub packet_len: u32,
     pub payload_len: u32,
-    pub tcp_flags: u8,
-    pub window_size: u16,
-    pub header_len: u32,
+    pub transport: TransportInfo,
+    pub header_len: u32,        // Transport header only, CICFlowMeter's Header Length
+}
+
+/// Transport specific fields the flow engine needs
+#[derive(Debug, Clone, Copy)]
+pub enum TransportInfo {
+    Tcp { flags: u8, window_size: u16, seq: u32, ack: u32 },
+    Udp,
+    Icmp { icmp_type: u8, code: u8, identifier: u16 },
+}
+
+/// Why a captured frame didn't become a ParsedPacket, counted separately in the stats
+#[derive(Debug)]
+pub enum ParseError {
+    Malformed(String),          // Truncated or invalid headers
+    NotIp,                      // ARP, LLDP, ...
+    UnsupportedTransport,       // Not TCP, UDP or ICMP, or an IPv4/IPv6 fragment
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Malformed(e) => write!(f, "Failed to parse packet: {e}"),
+            ParseError::NotIp => write!(f, "Not an IP packet"),
+            ParseError::UnsupportedTransport => write!(f, "No transport header"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Framing in front of the IP header, read from the capture's datalink type when it is opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkLayer {
+    Ethernet,
+    /// Bare IPv4/IPv6 packets, tun/wireguard interfaces and many exported captures
+    RawIp,
+    /// Linux cooked capture, what capturing on "any" writes
+    LinuxSll,
+    LinuxSll2,
+    /// BSD loopback, a 4 byte address family then the IP packet
+    Loopback,
+}
+
+impl LinkLayer {
+    pub fn from_datalink(datalink: Linktype) -> Result<Self, String> {
+        match datalink {
+            Linktype::ETHERNET => Ok(LinkLayer::Ethernet),
+            // DLT_RAW is 12 or 14 on some platforms, 101 in capture files
+            Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 | Linktype(12) | Linktype(14) => Ok(LinkLayer::RawIp),
+            Linktype::LINUX_SLL => Ok(LinkLayer::LinuxSll),
+            Linktype(276) => Ok(LinkLayer::LinuxSll2),
+            Linktype::NULL | Linktype::LOOP => Ok(LinkLayer::Loopback),
+            Linktype(other) => Err(format!(
+                "Unsupported link type {other}, only Ethernet, raw IP, Linux cooked (SLL/SLL2) and loopback captures can be read"
+            )),
+        }
+    }
+
+    /// The frame's headers past the link layer
+    fn headers(self, data: &[u8]) -> Result<PacketHeaders<'_>, ParseError> {
+        let truncated = || ParseError::Malformed(format!("Frame shorter than its {self:?} header"));
+        let ether_type = |bytes: &[u8]| EtherType(u16::from_be_bytes([bytes[0], bytes[1]]));
+        let headers = match self {
+            LinkLayer::Ethernet => PacketHeaders::from_ethernet_slice(data),
+            LinkLayer::RawIp => PacketHeaders::from_ip_slice(data),
+            // 16 byte header, the protocol in its last two bytes
+            LinkLayer::LinuxSll => {
+                let rest = data.get(16..).ok_or_else(truncated)?;
+                PacketHeaders::from_ether_type(ether_type(&data[14..16]), rest)
+            }
+            // 20 byte header starting with the protocol
+            LinkLayer::LinuxSll2 => {
+                let rest = data.get(20..).ok_or_else(truncated)?;
+                PacketHeaders::from_ether_type(ether_type(&data[..2]), rest)
+            }
+            // The address family is in the writer's byte order, the IP version says as much
+            LinkLayer::Loopback => PacketHeaders::from_ip_slice(data.get(4..).ok_or_else(truncated)?),
+        };
+        headers.map_err(|e| ParseError::Malformed(e.to_string()))
+    }
+}
+
+/// How packets read from a capture file are fed to the engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayMode {
+    /// Push packets as fast as they can be read from disk
+    AsFastAsPossible,
+    /// Sleep between packets to reproduce the original inter-arrival times
+    OriginalTimestamps,
+}
+
+enum CaptureSource {
+    Live(Capture<Active>, LinkLayer),
+    Offline(Capture<Offline>, LinkLayer, ReplayMode),
 }
 
 pub struct PacketSniffer {
     sniffer_running: Arc<AtomicBool>,
     sniffer_thread: Option<JoinHandle<()>>,
-    capture: Option<Capture<Active>>,      // owned until start, then moved into thread
-    packet_sender: Sender<ParsedPacket>,
+    capture: Option<CaptureSource>,        // owned until start, then moved into thread
+    packet_sender: PacketRouter,
+    counters: Arc<CaptureCounters>,
+    events: Arc<dyn EventSink>,
 }
 
 impl PacketSniffer {
-    pub fn new_with_sender(sender: Sender<ParsedPacket>) -> Self {
+    pub fn new_with_sender(sender: PacketRouter, counters: Arc<CaptureCounters>, events: Arc<dyn EventSink>) -> Self {
         Self {
             sniffer_running: Arc::new(AtomicBool::new(false)),
             sniffer_thread: None,
             capture: None,
             packet_sender: sender,
+            counters,
+            events,
         }
     }
 
@@ -49,52 +156,147 @@ impl PacketSniffer {
             .immediate_mode(true)
             .timeout(10)
             .open()?;
+        let link = LinkLayer::from_datalink(cap.get_datalink())
+            .map_err(|e| format!("Interface {interface}: {e}"))?;
 
         cap.filter(filter, true)?;
-        println!("Interface: {interface} successfully opened");
+        println!("Interface: {interface} successfully opened ({link:?})");
         println!("Filter: {filter} applied");
 
-        self.capture = Some(cap);
+        self.capture = Some(CaptureSource::Live(cap, link));
+        Ok(())
+    }
+
+    /// Opens a pcap/pcapng file instead of a live interface
+    pub fn init_replay(&mut self, path: &str, filter: &str, mode: ReplayMode) -> Result<(), Box<dyn Error>> {
+        let mut cap = Capture::from_file(path)?;
+        // Refused up front, a frame parsed with the wrong framing is garbage, not malformed
+        let link = LinkLayer::from_datalink(cap.get_datalink())
+            .map_err(|e| format!("Capture file {path}: {e}"))?;
+
+        cap.filter(filter, true)?;
+        println!("Capture file: {path} successfully opened ({link:?}, {mode:?})");
+        println!("Filter: {filter} applied");
+
+        self.capture = Some(CaptureSource::Offline(cap, link, mode));
         Ok(())
     }
 
     pub fn start_sniffer(&mut self) -> Result<(), Box<dyn Error>> {
-        let cap = self
+        let source = self
             .capture
             .take()
-            .ok_or("Capture is not initialized. Call init_sniffer first")?;
+            .ok_or("Capture is not initialized. Call init_sniffer or init_replay first")?;
 
         if self.sniffer_running.swap(true, Ordering::Relaxed) {
             return Err("Sniffer is already running".into());
         }
 
-        let mut cap = cap.setnonblock()?;
-
         let running = self.sniffer_running.clone();
         let sender = self.packet_sender.clone();
+        let counters = self.counters.clone();
+        let events = self.events.clone();
 
-        self.sniffer_thread = Some(thread::spawn(move || {
-            println!("Sniffer thread started");
-            while running.load(Ordering::Relaxed) {
-                match cap.next_packet() {
-                    Ok(packet) => PacketSniffer::packet_handler(&packet.header, &packet.data, &sender),
-                    Err(pcap::Error::TimeoutExpired) => {
-                        std::thread::sleep(std::time::Duration::from_millis(1));
+        let handle = match source {
+            CaptureSource::Live(cap, link) => {
+                let cap = match cap.setnonblock() {
+                    Ok(cap) => cap,
+                    Err(e) => {
+                        self.sniffer_running.store(false, Ordering::Relaxed);
+                        return Err(e.into());
                     }
-                    Err(e) => { eprintln!("Error capturing packet: {e}"); break; }
-                }
+                };
+                thread::spawn(move || Self::live_loop(cap, link, running, sender, counters, events))
             }
-            println!("Sniffer thread exiting");
-            // cap drops here
-        }));
+            CaptureSource::Offline(cap, link, mode) => {
+                thread::spawn(move || Self::replay_loop(cap, link, mode, running, sender, counters, events))
+            }
+        };
 
+        self.sniffer_thread = Some(handle);
         Ok(())
     }
 
+    /// False once the sniffer was stopped or a replay reached the end of its file
+    pub fn is_running(&self) -> bool {
+        self.sniffer_running.load(Ordering::Relaxed)
+    }
+
+    fn live_loop(mut cap: Capture<Active>, link: LinkLayer, running: Arc<AtomicBool>, sender: PacketRouter, counters: Arc<CaptureCounters>, events: Arc<dyn EventSink>) {
+        println!("Sniffer thread started");
+        let mut last_stats = Instant::now();
+        while running.load(Ordering::Relaxed) {
+            // pcap keeps cumulative drop totals since the capture was opened
+            if last_stats.elapsed() >= Duration::from_secs(1) {
+                if let Ok(stat) = cap.stats() {
+                    counters.kernel_drops.store(stat.dropped as u64, Ordering::Relaxed);
+                    counters.interface_drops.store(stat.if_dropped as u64, Ordering::Relaxed);
+                }
+                last_stats = Instant::now();
+            }
+
+            match cap.next_packet() {
+                Ok(packet) => PacketSniffer::packet_handler(link, packet.header, packet.data, &sender, &counters, false),
+                Err(pcap::Error::TimeoutExpired) => {
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                }
+                Err(e) => {
+                    eprintln!("Error capturing packet: {e}");
+                    events.health(&HealthEvent::new(Component::Sniffer, Severity::Error, format!("Capture stopped: {e}")));
+                    // Nothing is captured anymore, is_running has to say so
+                    running.store(false, Ordering::Relaxed);
+                    break;
+                }
+            }
+        }
+        println!("Sniffer thread exiting");
+        // cap drops here
+    }
+
+    fn replay_loop(mut cap: Capture<Offline>, link: LinkLayer, mode: ReplayMode, running: Arc<AtomicBool>, sender: PacketRouter, counters: Arc<CaptureCounters>, events: Arc<dyn EventSink>) {
+        println!("Replay thread started");
+        // Timestamp of the first packet and the instant it was replayed
+        let mut origin: Option<(u64, Instant)> = None;
+        let mut replayed: u64 = 0;
+
+        while running.load(Ordering::Relaxed) {
+            match cap.next_packet() {
+                Ok(packet) => {
+                    if mode == ReplayMode::OriginalTimestamps {
+                        let ts = Self::header_timestamp(packet.header);
+                        let (first_ts, started) = *origin.get_or_insert((ts, Instant::now()));
+                        let due = Duration::from_micros(ts.saturating_sub(first_ts));
+                        // Sleep in short slices so stop_sniffer isn't stuck behind a long gap
+                        while running.load(Ordering::Relaxed) && started.elapsed() < due {
+                            thread::sleep((due - started.elapsed()).min(Duration::from_millis(50)));
+                        }
+                    }
+                    // Offline packets must not be lost, so block instead of dropping
+                    PacketSniffer::packet_handler(link, packet.header, packet.data, &sender, &counters, true);
+                    replayed += 1;
+                }
+                Err(pcap::Error::NoMorePackets) => {
+                    events.health(&HealthEvent::new(Component::Sniffer, Severity::Info, format!("Replay finished after {replayed} packets")));
+                    break;
+                }
+                Err(e) => {
+                    eprintln!("Error reading capture file: {e}");
+                    events.health(&HealthEvent::new(Component::Sniffer, Severity::Error, format!("Replay stopped after {replayed} packets: {e}")));
+                    break;
+                }
+            }
+        }
+        // Let is_running report the end of the file
+        running.store(false, Ordering::Relaxed);
+        println!("Replay thread exiting after {replayed} packets");
+    }
+
 
 
     pub fn stop_sniffer(&mut self) -> Result<(), Box<dyn Error>> {
-        if !self.sniffer_running.swap(false, Ordering::Relaxed) {
+        // A finished replay already cleared the flag but its thread still has to be joined
+        let was_running = self.sniffer_running.swap(false, Ordering::Relaxed);
+        if !was_running && self.sniffer_thread.is_none() {
             return Ok(());
         }
 
@@ -110,34 +312,65 @@ impl PacketSniffer {
 
 
 
-    fn packet_handler(header: &PacketHeader, packet_data: &[u8], sender: &Sender<ParsedPacket>) {
-        match Self::parse_packet(header, packet_data) {
+    fn packet_handler(link: LinkLayer, header: &PacketHeader, packet_data: &[u8], sender: &PacketRouter, counters: &CaptureCounters, blocking: bool) {
+        match Self::parse_frame(link, header, packet_data) {
             Ok(parsed_packet) => {
                 // If can parse the packet we send it to the engine
-                let _ = sender.try_send(parsed_packet);
-            }
-            Err(_) => {
+                // A full queue is handled and counted by its overload policy
+                if blocking {
+                    let _ = sender.push_blocking(parsed_packet);
+                } else {
+                    let _ = sender.push(parsed_packet);
+                }
             }
+            Err(ParseError::Malformed(_)) => CaptureCounters::bump(&counters.malformed),
+            Err(ParseError::NotIp) => CaptureCounters::bump(&counters.not_ip),
+            Err(ParseError::UnsupportedTransport) => CaptureCounters::bump(&counters.unsupported_transport),
         }
     }
 
-    
+    #[inline]
+    fn header_timestamp(header: &PacketHeader) -> u64 {
+        (header.ts.tv_sec as u64 * 1_000_000) + header.ts.tv_usec as u64
+    }
 
-    fn parse_packet(header: &PacketHeader, data: &[u8]) -> Result<ParsedPacket, Box<dyn Error>> {
-        let timestamp = (header.ts.tv_sec as u64 * 1_000_000) + header.ts.tv_usec as u64;
+    // Type and code are the first two bytes of every ICMP/ICMPv6 header, query messages
+    // carry their identifier right after the checksum
+    fn icmp_info(protocol: u8, header: &[u8]) -> TransportInfo {
+        let (icmp_type, code) = (header[0], header[1]);
+        let identifier = if is_icmp_query(protocol, icmp_type) && header.len() >= 6 {
+            u16::from_be_bytes([header[4], header[5]])
+        } else {
+            0
+        };
+        TransportInfo::Icmp { icmp_type, code, identifier }
+    }
 
-        let parsed = PacketHeaders::from_ethernet_slice(data)
-            .map_err(|e| format!("Failed to parse packet: {e}"))?;
+    /// Parses an Ethernet frame
+    pub fn parse_packet(header: &PacketHeader, data: &[u8]) -> Result<ParsedPacket, ParseError> {
+        Self::parse_frame(LinkLayer::Ethernet, header, data)
+    }
 
+    pub fn parse_frame(link: LinkLayer, header: &PacketHeader, data: &[u8]) -> Result<ParsedPacket, ParseError> {
+        let timestamp = Self::header_timestamp(header);
+
+        let parsed = link.headers(data)?;
+
+        // etherparse already walks the IPv6 extension chain (hop-by-hop, routing, fragment,
+        // destination options, auth) so `transport` points at the real upper layer header
         let (src_ip, dst_ip) = match &parsed.net {
             Some(NetHeaders::Ipv4(ipv4, _)) => (
-                u32::from_be_bytes(ipv4.source),
-                u32::from_be_bytes(ipv4.destination),
+                IpAddr::V4(Ipv4Addr::from(ipv4.source)),
+                IpAddr::V4(Ipv4Addr::from(ipv4.destination)),
             ),
-            _ => return Err("Not an IPv4 packet".into()),
+            Some(NetHeaders::Ipv6(ipv6, _)) => (
+                IpAddr::V6(Ipv6Addr::from(ipv6.source)),
+                IpAddr::V6(Ipv6Addr::from(ipv6.destination)),
+            ),
+            _ => return Err(ParseError::NotIp),
         };
 
-        let (src_port, dst_port, tcp_flags, window_size, tcp_header_len, protocol) = match &parsed.transport {
+        let (src_port, dst_port, transport, transport_header_len, protocol) = match &parsed.transport {
             Some(TransportHeader::Tcp(tcp)) => {
                 let header_len = tcp.data_offset() as u32 * 4;
                 let flags = (tcp.cwr as u8) << 7
@@ -151,34 +384,63 @@ impl PacketSniffer {
                 (
                     tcp.source_port,
                     tcp.destination_port,
-                    flags,
-                    tcp.window_size,
+                    TransportInfo::Tcp {
+                        flags,
+                        window_size: tcp.window_size,
+                        seq: tcp.sequence_number,
+                        ack: tcp.acknowledgment_number,
+                    },
                     header_len,
                     6,
                 )
             }
-            _ => return Err("Not a TCP packet".into()),
+            Some(TransportHeader::Udp(udp)) => (
+                udp.source_port,
+                udp.destination_port,
+                TransportInfo::Udp,
+                etherparse::UdpHeader::LEN as u32,
+                17,
+            ),
+            // ICMP has no ports, the pseudo-flow is keyed by type/code and identifier instead
+            Some(TransportHeader::Icmpv4(icmp)) => {
+                let transport = Self::icmp_info(1, &icmp.to_bytes());
+                (0, 0, transport, icmp.header_len() as u32, 1)
+            }
+            Some(TransportHeader::Icmpv6(icmp)) => {
+                let transport = Self::icmp_info(58, &icmp.to_bytes());
+                (0, 0, transport, icmp.header_len() as u32, 58)
+            }
+            None => return Err(ParseError::UnsupportedTransport),
         };
 
-        
+        let flow_key = match transport {
+            TransportInfo::Icmp { icmp_type, code, identifier } => FlowKey::icmp(src_ip, dst_ip, icmp_type, code, identifier, protocol),
+            _ => FlowKey::new(src_ip, dst_ip, src_port, dst_port, protocol),
+        };
 
-        let flow_key = FlowKey::new(src_ip, dst_ip, src_port, dst_port, protocol);
+        // Includes IPv4 options and every IPv6 extension header in front of the transport
+        let ip_header_len = parsed.net.as_ref().map_or(0, |ip| ip.header_len() as u32);
 
-        let eth_header_len = 14;
-        let ip_header_len = parsed.net.map_or(0, |ip| match ip {
-            etherparse::NetHeaders::Ipv4(ipv4, _) => ipv4.header_len() as u32,
+        // Payload from the IP length fields, the frame length also counts Ethernet padding
+        // which would shift the sequence number a FIN is acknowledged with
+        let ip_payload_len = match &parsed.net {
+            Some(NetHeaders::Ipv4(ipv4, _)) => (ipv4.total_len as u32).saturating_sub(ip_header_len),
+            Some(NetHeaders::Ipv6(ipv6, exts)) => (ipv6.payload_length as u32).saturating_sub(exts.header_len() as u32),
             _ => 0,
-        });
-        let total_header_len = eth_header_len + ip_header_len + tcp_header_len;
+        };
+        let payload_len = ip_payload_len.saturating_sub(transport_header_len);
 
         Ok(ParsedPacket {
             timestamp,
             flow_key,
+            src_ip,
+            dst_ip,
+            src_port,
+            dst_port,
             packet_len: header.len,
-            payload_len: (header.len as u32).saturating_sub(total_header_len),
-            tcp_flags,
-            window_size,
-            header_len: total_header_len,
+            payload_len,
+            transport,
+            header_len: transport_header_len,
         })
     }
 }
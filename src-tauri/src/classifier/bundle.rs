@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::processor::FeatureVector;
+use super::thresholds::ATTACK_THRESHOLD;
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// One ONNX model of the bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelSpec {
+    pub path: String,               // Relative to the bundle folder
+    pub features: Vec<String>,      // Input columns in the order the model was trained on
+    #[serde(default)]
+    pub input: Option<String>,      // Feature tensor, the model's only input when missing
+    #[serde(default)]
+    pub output: Option<String>,     // Probabilities tensor, the first float output when missing
+}
+
+/// `manifest.json` of a models folder: what each model expects and how to read its output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelBundle {
+    pub binary: ModelSpec,
+    pub multiclass: ModelSpec,
+    pub class_map: String,          // Multiclass labels generated in training
+    #[serde(default = "default_threshold")]
+    pub threshold: f32,             // P(attack) the binary model was tuned for, used unless overridden
+    #[serde(skip)]
+    pub dir: PathBuf,
+    #[serde(skip)]
+    pub labels: Vec<String>,
+}
+
+fn default_threshold() -> f32 { ATTACK_THRESHOLD }
+
+#[derive(Debug, Deserialize)]
+struct ClassMap {
+    num_classes: usize,
+    id_to_label: HashMap<String, String>,
+}
+
+impl ModelBundle {
+    /// Reads the manifest and class map of `dir` and checks every feature name against the
+    /// registry. A folder without manifest is taken as the original two-model layout
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, String> {
+        let dir = dir.as_ref();
+        let manifest = dir.join(MANIFEST_FILE_NAME);
+        let mut bundle = if manifest.exists() {
+            let s = fs::read_to_string(&manifest).map_err(|e| format!("read {}: {e}", manifest.display()))?;
+            serde_json::from_str::<Self>(&s).map_err(|e| format!("parse {}: {e}", manifest.display()))?
+        } else {
+            eprintln!("No {MANIFEST_FILE_NAME} in {}, assuming the original model layout", dir.display());
+            Self::legacy()
+        };
+        bundle.dir = dir.to_path_buf();
+
+        if !(0.0..=1.0).contains(&bundle.threshold) {
+            return Err(format!("{MANIFEST_FILE_NAME}: threshold must be between 0 and 1"));
+        }
+        check_features("binary", &bundle.binary)?;
+        check_features("multiclass", &bundle.multiclass)?;
+        bundle.labels = load_label_vector(dir.join(&bundle.class_map))?;
+        Ok(bundle)
+    }
+
+    pub fn binary_path(&self) -> PathBuf { self.dir.join(&self.binary.path) }
+
+    pub fn multiclass_path(&self) -> PathBuf { self.dir.join(&self.multiclass.path) }
+
+    // l1_model.onnx, l2_multiclass.onnx and class_map.json, the features in extraction order
+    fn legacy() -> Self {
+        let spec = |path: &str, features: &[&str]| ModelSpec {
+            path: path.into(),
+            features: features.iter().map(|f| f.to_string()).collect(),
+            input: None,
+            output: None,
+        };
+        Self {
+            binary: spec("l1_model.onnx", &L1_FEATURES),
+            multiclass: spec("l2_multiclass.onnx", &L2_FEATURES),
+            class_map: "class_map.json".into(),
+            threshold: ATTACK_THRESHOLD,
+            dir: PathBuf::new(),
+            labels: Vec::new(),
+        }
+    }
+}
+
+// Every name must be a feature Layton extracts, all unknown ones are reported at once
+fn check_features(model: &str, spec: &ModelSpec) -> Result<(), String> {
+    if spec.features.is_empty() {
+        return Err(format!("{MANIFEST_FILE_NAME}: {model} model lists no features"));
+    }
+    FeatureVector::from_names(&spec.features)
+        .map(|_| ())
+        .map_err(|e| format!("{MANIFEST_FILE_NAME}: {model} model: {e}"))
+}
+
+// Maps the json generated in training for the multiclass model giving a vector of the categories
+fn load_label_vector<P: AsRef<Path>>(path: P) -> Result<Vec<String>, String> {
+    let path = path.as_ref();
+    let s = fs::read_to_string(path).map_err(|e| format!("read {}: {e}", path.display()))?;
+    let m: ClassMap = serde_json::from_str(&s).map_err(|e| format!("parse {}: {e}", path.display()))?;
+    let mut labels = vec!["Unknown".to_string(); m.num_classes.max(1)];
+    for (k, v) in m.id_to_label {
+        if let Ok(idx) = k.parse::<usize>() {
+            if idx < labels.len() { labels[idx] = v; }
+        }
+    }
+    Ok(labels)
+}
+
+/// Inputs of the original binary model, in training order
+pub const L1_FEATURES: [&str; 48] = [
+    "Flow Duration", "Total Length of Fwd Packet", "Total Length of Bwd Packet",
+    "Fwd Packet Length Min", "Fwd Packet Length Std", "Bwd Packet Length Max", "Bwd Packet Length Min",
+    "Flow Bytes/s", "Flow Packets/s", "Flow IAT Mean", "Flow IAT Std",
+    "Fwd IAT Total", "Fwd IAT Mean", "Fwd IAT Std", "Fwd IAT Max", "Fwd IAT Min",
+    "Bwd IAT Total", "Bwd IAT Mean", "Bwd IAT Std",
+    "Fwd PSH Flags", "Fwd URG Flags", "Bwd Header Length", "Bwd Packets/s",
+    "Packet Length Min", "Packet Length Max", "Packet Length Mean",
+    "FIN Flag Count", "SYN Flag Count", "RST Flag Count", "PSH Flag Count", "URG Flag Count", "CWR Flag Count", "ECE Flag Count",
+    "Down/Up Ratio", "Bwd Bytes/Bulk Avg", "Bwd Packet/Bulk Avg", "Bwd Bulk Rate Avg",
+    "Subflow Fwd Packets", "Subflow Fwd Bytes", "Subflow Bwd Packets",
+    "FWD Init Win Bytes", "Bwd Init Win Bytes", "Fwd Act Data Pkts", "Fwd Seg Size Min",
+    "Active Mean", "Active Std", "Idle Std", "Idle Min",
+];
+
+/// Inputs of the original multiclass model, in training order
+pub const L2_FEATURES: [&str; 52] = [
+    "Flow Duration", "Total Fwd Packet", "Fwd Packet Length Max", "Fwd Packet Length Min",
+    "Bwd Packet Length Min", "Bwd Packet Length Mean", "Bwd Packet Length Std",
+    "Flow Bytes/s", "Flow Packets/s", "Flow IAT Mean", "Flow IAT Std", "Flow IAT Max", "Flow IAT Min",
+    "Fwd IAT Mean", "Fwd IAT Std", "Fwd IAT Min",
+    "Bwd IAT Total", "Bwd IAT Mean", "Bwd IAT Std", "Bwd IAT Max", "Bwd IAT Min",
+    "Fwd PSH Flags", "Fwd URG Flags", "Bwd Packets/s",
+    "Packet Length Min", "Packet Length Max", "Packet Length Variance",
+    "FIN Flag Count", "SYN Flag Count", "RST Flag Count", "PSH Flag Count", "ACK Flag Count", "URG Flag Count", "CWR Flag Count", "ECE Flag Count",
+    "Down/Up Ratio", "Average Packet Size", "Fwd Segment Size Avg",
+    "Bwd Bytes/Bulk Avg", "Bwd Packet/Bulk Avg", "Bwd Bulk Rate Avg",
+    "Subflow Fwd Packets", "Subflow Fwd Bytes", "Subflow Bwd Packets", "Subflow Bwd Bytes",
+    "FWD Init Win Bytes", "Bwd Init Win Bytes", "Fwd Act Data Pkts", "Fwd Seg Size Min",
+    "Active Std", "Active Max", "Idle Std",
+];
@@ -1,46 +1,164 @@
 use anyhow::{anyhow, Context, Result};
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::{bounded, Receiver};
 use ort::{
     Environment, Session, SessionBuilder, Value,
     GraphOptimizationLevel, LoggingLevel,
+    tensor::TensorElementDataType,
 };
 use ndarray::{Array2, CowArray, IxDyn};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Instant;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use crate::processor::FlowRecord;
+use crate::pipeline::{ErrorThrottle, EventSink};
+use crate::processor::{FeatureVector, FlowRecord};
+use crate::queue::{self, QueueMonitor, QueueTx, QueuesConfig};
+use crate::types::{Component, HealthEvent, Severity};
+use super::bundle::{ModelBundle, ModelSpec};
+use super::reload::{FlowTx, ModelGenerations, ModelSwap};
+use super::shadow::ShadowStats;
+use super::thresholds::{SharedThresholds, Thresholds};
 
-pub const FEATURE_L1_COUNT: usize = 48;
-pub const FEATURE_L2_COUNT: usize = 52;
-pub const ATTACK_THRESHOLD: f32 = 0.85;
+const MAX_WORKERS: usize = 32;
+
+/// How the classifier groups flows into model runs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClassifierConfig {
+    pub batch_size: usize,      // Most flows scored by one model run
+    pub batch_wait_us: u64,     // Longest the first flow of a batch waits for more, 0 takes only what's queued
+    pub workers: usize,         // Classifier threads, each with its own model sessions
+    pub watch_models_secs: u64, // How often to check the model folder and reload it on changes, 0 to only reload on request
+    pub shadow_models: Option<String>,  // Challenger bundle scored next to the models in use, recorded but never alerted on
+}
+
+impl Default for ClassifierConfig {
+    fn default() -> Self {
+        Self { batch_size: 256, batch_wait_us: 2_000, workers: 1, watch_models_secs: 0, shadow_models: None }
+    }
+}
+
+impl ClassifierConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.batch_size == 0 {
+            return Err("batch_size must be greater than 0".into());
+        }
+        if self.batch_wait_us > 1_000_000 {
+            return Err("batch_wait_us can't be over 1 second".into());
+        }
+        if !(1..=MAX_WORKERS).contains(&self.workers) {
+            return Err(format!("workers must be between 1 and {MAX_WORKERS}"));
+        }
+        if self.shadow_models.as_deref().is_some_and(str::is_empty) {
+            return Err("shadow_models can't be empty, leave it out to run without shadow models".into());
+        }
+        Ok(())
+    }
+}
+
+/// Batch figures of the classifier thread, read by the stats publisher
+#[derive(Default)]
+pub struct InferenceStats {
+    batches: AtomicU64,
+    busy_us: AtomicU64,
+    last_batch_size: AtomicU64,
+    last_batch_us: AtomicU64,
+}
+
+impl InferenceStats {
+    fn record(&self, size: usize, micros: u128) {
+        let micros = micros as u64;
+        self.batches.fetch_add(1, Ordering::Relaxed);
+        self.busy_us.fetch_add(micros, Ordering::Relaxed);
+        self.last_batch_size.store(size as u64, Ordering::Relaxed);
+        self.last_batch_us.store(micros, Ordering::Relaxed);
+    }
+
+    pub fn batches(&self) -> u64 { self.batches.load(Ordering::Relaxed) }
+
+    pub fn last_batch_size(&self) -> u64 { self.last_batch_size.load(Ordering::Relaxed) }
+
+    // Time the models took on the last batch
+    pub fn last_batch_us(&self) -> u64 { self.last_batch_us.load(Ordering::Relaxed) }
+
+    pub fn avg_batch_us(&self) -> f64 {
+        let batches = self.batches();
+        if batches == 0 { 0.0 } else { self.busy_us.load(Ordering::Relaxed) as f64 / batches as f64 }
+    }
+}
 
 pub struct NidsModel {
     environment: Arc<Environment>,
-    binary: Arc<Mutex<Session>>,
-    multiclass: Arc<Mutex<Session>>,
+    // Owned by one worker thread, so runs never wait on each other
+    binary: Session,
+    multiclass: Session,
+    // Probabilities output named by the manifest, the first float output otherwise
+    binary_output: Option<usize>,
+    multiclass_output: Option<usize>,
+    // Input columns in the order the manifest lists them
+    binary_features: FeatureVector,
+    multiclass_features: FeatureVector,
+    // Set when a graph has a fixed batch dimension of 1, batches are then run one flow at a time
+    max_batch: Option<usize>,
+    // From the bundle, so flows keep the class map and threshold of the model that scored them
+    labels: Vec<String>,
+    threshold: f32,
 }
 
 #[derive(Debug, Clone)]
 pub struct Inference {
-    pub pred_label: u8,
+    pub pred_label: Option<u8>,     // None when no class cleared its minimum (multiclass only)
     pub probs: Vec<f32>,
-    pub micros: u128,
+    pub micros: u128,               // Model run time of the whole batch the flow was scored in
+    pub threshold: Option<f32>,     // Minimum the verdict was checked against
+    pub label: Option<String>,      // Class map name of pred_label (multiclass only)
 }
 
 #[derive(Debug, Clone)]
 pub struct MultiResult {
-    pub bin: Inference,
+    pub bin: Option<Inference>,     // None when the models don't score the flow (ICMP)
     pub multi: Option<Inference>,
+    pub shadow: Option<Box<MultiResult>>,   // Verdict of the challenger models, if they run
+}
+
+impl MultiResult {
+    pub fn unscored() -> Self {
+        Self { bin: None, multi: None, shadow: None }
+    }
+
+    /// BENIGN, the attack class (or "Attack" without one), None when not scored
+    pub fn label(&self) -> Option<&str> {
+        match &self.bin {
+            None => None,
+            Some(_) if !self.is_attack() => Some("BENIGN"),
+            Some(_) => Some(self.multi.as_ref().and_then(|m| m.label.as_deref()).unwrap_or("Attack")),
+        }
+    }
+
+    #[inline]
+    pub fn is_attack(&self) -> bool {
+        self.bin.as_ref().is_some_and(|b| b.pred_label == Some(1))
+    }
 }
 
 pub struct ClassifierHandles {
-    pub tx: Sender<FlowRecord>,
+    pub tx: FlowTx,
     pub rx: Receiver<(FlowRecord, MultiResult)>,
+    pub results: Arc<QueueMonitor>,
+    pub stats: Arc<InferenceStats>,
+    pub thresholds: SharedThresholds,
+    pub models: Arc<ModelSwap>,
+    pub shadow: Option<Arc<ShadowStats>>,
+    // Workers exit once every `tx` clone is dropped and the queue is empty
+    pub threads: Vec<JoinHandle<()>>,
 }
 
 impl NidsModel {
-    fn load(binary_path: &str, multiclass_path: &str) -> Result<Self> {
+    /// Loads both models and refuses them if their graphs don't match the manifest
+    pub(super) fn load(bundle: &ModelBundle) -> Result<Self> {
+        let (binary_path, multiclass_path) = (bundle.binary_path(), bundle.multiclass_path());
         let environment = Arc::new(
             Environment::builder()
                 .with_name("nids-model")
@@ -52,257 +170,385 @@ impl NidsModel {
         let binary = SessionBuilder::new(&environment)?
             .with_optimization_level(GraphOptimizationLevel::Level3)?
             .with_intra_threads(1)?
-            .with_model_from_file(binary_path)
-            .with_context(|| format!("Failed to load binary model from {}", binary_path))?;
+            .with_model_from_file(&binary_path)
+            .with_context(|| format!("Failed to load binary model from {}", binary_path.display()))?;
 
         let multiclass = SessionBuilder::new(&environment)?
             .with_optimization_level(GraphOptimizationLevel::Level3)?
             .with_intra_threads(1)?
-            .with_model_from_file(multiclass_path)
-            .with_context(|| format!("Failed to load multiclass model from {}", multiclass_path))?;
+            .with_model_from_file(&multiclass_path)
+            .with_context(|| format!("Failed to load multiclass model from {}", multiclass_path.display()))?;
+
+        let (binary_output, binary_batch) = check_graph(&binary, &bundle.binary, "binary", 2)?;
+        let (multiclass_output, multiclass_batch) = check_graph(&multiclass, &bundle.multiclass, "multiclass", bundle.labels.len())?;
+        let binary_features = FeatureVector::from_names(&bundle.binary.features).map_err(|e| anyhow!("binary model: {e}"))?;
+        let multiclass_features = FeatureVector::from_names(&bundle.multiclass.features).map_err(|e| anyhow!("multiclass model: {e}"))?;
 
         Ok(Self {
             environment,
-            binary: Arc::new(Mutex::new(binary)),
-            multiclass: Arc::new(Mutex::new(multiclass)),
+            binary,
+            multiclass,
+            binary_output,
+            multiclass_output,
+            binary_features,
+            multiclass_features,
+            max_batch: binary_batch.or(multiclass_batch),
+            labels: bundle.labels.clone(),
+            threshold: bundle.threshold,
         })
     }
 
-    fn run_binary(&self, flow: &FlowRecord) -> Result<Inference> {
-        let mut feats = [0f32; FEATURE_L1_COUNT];
-        extract_l1_features(flow, &mut feats);
-
-        let input = Array2::from_shape_vec((1, FEATURE_L1_COUNT), feats.to_vec())
+    /// Scores the flows as one batch, a row of binary features each
+    fn run_binary(&self, flows: &[&FlowRecord], thresholds: &Thresholds) -> Result<Vec<Inference>> {
+        let width = self.binary_features.len();
+        let mut input = Vec::with_capacity(flows.len() * width);
+        for flow in flows {
+            self.binary_features.extend(flow, &mut input);
+        }
+        let input = Array2::from_shape_vec((flows.len(), width), input)
             .context("Failed to create binary input array")?;
-        let cow = CowArray::from(input.into_dyn());
 
-        let t0 = Instant::now();
-        
-        let session = self.binary.lock()
-            .map_err(|e| anyhow!("Failed to lock binary session: {}", e))?;
-        
-        let tensor = Value::from_array(session.allocator(), &cow)
-            .context("Failed to create input tensor")?;
-        
-        let outputs = session.run(vec![tensor])
-            .context("Failed to run binary model")?;
-        
-        let dt = t0.elapsed().as_micros();
-
-        let probs = outputs.iter()
-            .find_map(|o| o.try_extract::<f32>().ok())
-            .and_then(|t| Some(t.view().iter().copied().collect::<Vec<f32>>()))
-            .ok_or_else(|| anyhow!("No probability output from binary model"))?;
-
-        if probs.len() < 2 {
-            return Err(anyhow!("Expected 2 probabilities, got {}", probs.len()));
-        }
+        let (rows, dt) = run_batch(&self.binary, self.binary_output, input, "binary")?;
+        let attack = thresholds.attack_or(self.threshold);
+        rows.into_iter()
+            .map(|probs| {
+                if probs.len() < 2 {
+                    return Err(anyhow!("Expected 2 probabilities, got {}", probs.len()));
+                }
+                let p_attack = probs[1];
+                let pred_label = if p_attack >= attack { 1 } else { 0 };
+                Ok(Inference { pred_label: Some(pred_label), probs, micros: dt, threshold: Some(attack), label: None })
+            })
+            .collect()
+    }
 
-        let p_attack = probs[1];
-        let pred_label = if p_attack >= ATTACK_THRESHOLD { 1 } else { 0 };
+    /// Scores the flows as one batch, a row of multiclass features each
+    fn run_multiclass(&self, flows: &[&FlowRecord], thresholds: &Thresholds) -> Result<Vec<Inference>> {
+        let width = self.multiclass_features.len();
+        let mut input = Vec::with_capacity(flows.len() * width);
+        for flow in flows {
+            self.multiclass_features.extend(flow, &mut input);
+        }
+        let input = Array2::from_shape_vec((flows.len(), width), input)
+            .context("Failed to create multiclass input array")?;
 
-        Ok(Inference { pred_label, probs, micros: dt })
+        let (rows, dt) = run_batch(&self.multiclass, self.multiclass_output, input, "multiclass")?;
+        rows.into_iter()
+            .map(|probs| {
+                if probs.is_empty() {
+                    return Err(anyhow!("Empty probability vector"));
+                }
+                let (pred_label, threshold) = thresholds.pick_class(&self.labels, &probs);
+                let label = pred_label.map(|idx| self.labels.get(idx as usize).cloned().unwrap_or_else(|| "Unknown".into()));
+                Ok(Inference { pred_label, probs, micros: dt, threshold, label })
+            })
+            .collect()
     }
 
-    fn run_multiclass(&self, flow: &FlowRecord) -> Result<Inference> {
-        let mut feats = [0f32; FEATURE_L2_COUNT];
-        extract_l2_features(flow, &mut feats);
+    /// One result per flow, in order. The binary model scores every TCP/UDP flow in a
+    /// single run, the multiclass model the ones it flags as attacks in a second one
+    fn classify_batch(&self, flows: &[FlowRecord], thresholds: &Thresholds) -> Result<Vec<MultiResult>> {
+        if let Some(max) = self.max_batch.filter(|max| flows.len() > *max) {
+            let mut results = Vec::with_capacity(flows.len());
+            for chunk in flows.chunks(max) {
+                results.extend(self.classify_batch(chunk, thresholds)?);
+            }
+            return Ok(results);
+        }
+        let mut results = vec![MultiResult::unscored(); flows.len()];
 
-        let input = Array2::from_shape_vec((1, FEATURE_L2_COUNT), feats.to_vec())
-            .context("Failed to create multiclass input array")?;
-        let cow = CowArray::from(input.into_dyn());
+        // The models were trained on TCP/UDP flows, ICMP pseudo-flows are passed through
+        let scored: Vec<usize> = (0..flows.len()).filter(|&i| !flows[i].key.is_icmp()).collect();
+        if scored.is_empty() {
+            return Ok(results);
+        }
 
-        let t0 = Instant::now();
-        
-        let session = self.multiclass.lock()
-            .map_err(|e| anyhow!("Failed to lock multiclass session: {}", e))?;
-        
-        let tensor = Value::from_array(session.allocator(), &cow)
-            .context("Failed to create input tensor")?;
-        
-        let outputs = session.run(vec![tensor])
-            .context("Failed to run multiclass model")?;
-        
-        let dt = t0.elapsed().as_micros();
-
-        let probs = outputs.iter()
-            .find_map(|o| o.try_extract::<f32>().ok())
-            .and_then(|t| Some(t.view().iter().copied().collect::<Vec<f32>>()))
-            .ok_or_else(|| anyhow!("No probability output from multiclass model"))?;
-
-        let pred_label = probs.iter()
-            .enumerate()
-            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-            .map(|(idx, _)| idx as u8)
-            .ok_or_else(|| anyhow!("Empty probability vector"))?;
-
-        Ok(Inference { pred_label, probs, micros: dt })
+        let batch: Vec<&FlowRecord> = scored.iter().map(|&i| &flows[i]).collect();
+        for (&i, bin) in scored.iter().zip(self.run_binary(&batch, thresholds)?) {
+            results[i].bin = Some(bin);
+        }
+
+        let attacks: Vec<usize> = scored.into_iter().filter(|&i| results[i].is_attack()).collect();
+        if !attacks.is_empty() {
+            let batch: Vec<&FlowRecord> = attacks.iter().map(|&i| &flows[i]).collect();
+            for (&i, multi) in attacks.iter().zip(self.run_multiclass(&batch, thresholds)?) {
+                results[i].multi = Some(multi);
+            }
+        }
+        Ok(results)
     }
+}
 
-    fn classify_flow(&self, flow: &FlowRecord) -> Result<MultiResult> {
-        let bin = self.run_binary(flow)?;
-        println!("Flow predicted {} time consumed: {} µs", bin.pred_label, bin.micros);
-
-        let multi = if bin.pred_label == 1 {
-            let multi_result = self.run_multiclass(flow)?;
-            println!("Malicious flow predicted class {} time consumed: {} µs", 
-                     multi_result.pred_label, multi_result.micros);
-            Some(multi_result)
-        } else {
-            None
-        };
+// Compares the graph's feature tensor and probabilities output with the manifest, returns the output's
+// position and the batch dimension when the graph fixes it
+fn check_graph(session: &Session, spec: &ModelSpec, model: &str, classes: usize) -> Result<(Option<usize>, Option<usize>)> {
+    let names = |names: Vec<&str>| names.join(", ");
+    if session.inputs.len() != 1 {
+        return Err(anyhow!("{model} model takes {} inputs ({}), Layton feeds a single feature tensor",
+            session.inputs.len(), names(session.inputs.iter().map(|i| i.name.as_str()).collect())));
+    }
+    let input = &session.inputs[0];
+    if let Some(name) = spec.input.as_ref().filter(|name| **name != input.name) {
+        return Err(anyhow!("{model} model input is {}, the manifest expects {name}", input.name));
+    }
+    if input.input_type != TensorElementDataType::Float32 {
+        return Err(anyhow!("{model} model input {} is {:?}, expected float32", input.name, input.input_type));
+    }
+    let batch = match input.dimensions().collect::<Vec<_>>().as_slice() {
+        [_, Some(n)] if *n != spec.features.len() => {
+            return Err(anyhow!("{model} model input {} takes {n} features, the manifest lists {}", input.name, spec.features.len()));
+        }
+        // Batches hold however many flows arrived, a fixed size other than one can't take them
+        [Some(n), _] if *n != 1 => {
+            return Err(anyhow!("{model} model input {} has a fixed batch size of {n}, expected a dynamic one or 1", input.name));
+        }
+        [batch, _] => *batch,
+        dims => return Err(anyhow!("{model} model input {} has shape {dims:?}, expected [batch, features]", input.name)),
+    };
 
-        Ok(MultiResult { bin, multi })
+    let Some(name) = &spec.output else { return Ok((None, batch)) };
+    let idx = session.outputs.iter().position(|o| o.name == *name).ok_or_else(|| {
+        anyhow!("{model} model has no output {name}, it has {}", names(session.outputs.iter().map(|o| o.name.as_str()).collect()))
+    })?;
+    let output = &session.outputs[idx];
+    if output.output_type != TensorElementDataType::Float32 {
+        return Err(anyhow!("{model} model output {name} is {:?}, expected float32 probabilities", output.output_type));
     }
+    if let Some(Some(n)) = output.dimensions().last() {
+        if n != classes {
+            return Err(anyhow!("{model} model output {name} has {n} classes, expected {classes}"));
+        }
+    }
+    Ok((Some(idx), batch))
+}
+
+// Runs the session on an N×F input and splits its probability output into one row per flow
+fn run_batch(session: &Session, output: Option<usize>, input: Array2<f32>, model: &str) -> Result<(Vec<Vec<f32>>, u128)> {
+    let n = input.nrows();
+    let cow = CowArray::from(input.into_dyn());
+
+    let t0 = Instant::now();
+
+    let tensor = Value::from_array(session.allocator(), &cow)
+        .context("Failed to create input tensor")?;
+
+    let outputs = session.run(vec![tensor])
+        .with_context(|| format!("Failed to run {model} model"))?;
+
+    let dt = t0.elapsed().as_micros();
+
+    let probs = outputs.iter()
+        .enumerate()
+        .filter(|(i, _)| output.is_none_or(|o| o == *i))
+        .find_map(|(_, o)| o.try_extract::<f32>().ok())
+        .map(|t| t.view().iter().copied().collect::<Vec<f32>>())
+        .ok_or_else(|| anyhow!("No probability output from {model} model"))?;
+
+    if probs.is_empty() || !probs.len().is_multiple_of(n) {
+        return Err(anyhow!("{model} model returned {} probabilities for {n} flows", probs.len()));
+    }
+    let per_flow = probs.len() / n;
+    Ok((probs.chunks(per_flow).map(<[f32]>::to_vec).collect(), dt))
 }
 
-pub fn spawn_classifier(binary_path: String, multiclass_path: String) -> Result<ClassifierHandles> {
-    let (tx_in, rx_in) = unbounded::<FlowRecord>();
-    let (tx_out, rx_out) = unbounded::<(FlowRecord, MultiResult)>();
+/// Starts the classifier workers and waits for all of them to load the models, a load failure
+/// is returned here. Workers take batches from the same queue, results keep their flow attached.
+/// `shadow` is a challenger bundle that scores every flow too, without deciding anything
+pub fn spawn_classifier(bundle: &ModelBundle, shadow: Option<&ModelBundle>, config: &ClassifierConfig, thresholds: SharedThresholds, queues: &QueuesConfig, events: Arc<dyn EventSink>) -> Result<ClassifierHandles> {
+    let (tx_in, rx_in) = queue::queue::<(FlowRecord, u64)>("flows", &queues.flows);
+    let (tx_out, rx_out) = queue::queue::<(FlowRecord, MultiResult)>("results", &queues.results);
+    let results = tx_out.monitor();
+    let stats = Arc::new(InferenceStats::default());
+    let errors = Arc::new(Mutex::new(ErrorThrottle::new(Duration::from_secs(5))));
+    let workers = config.workers.max(1);
+    let models = Arc::new(ModelSwap::new(bundle.dir.clone(), workers));
+    let shadow_stats = shadow.map(|b| Arc::new(ShadowStats::new(b.dir.display().to_string())));
+    let (ready_tx, ready_rx) = bounded::<Result<(), String>>(workers);
     
-    println!("Loading models from:\n  Binary: {}\n  Multiclass: {}", binary_path, multiclass_path);
+    println!("Loading models from:\n  Binary: {}\n  Multiclass: {}", bundle.binary_path().display(), bundle.multiclass_path().display());
+    if let Some(shadow) = shadow {
+        println!("Shadow models from:\n  Binary: {}\n  Multiclass: {}", shadow.binary_path().display(), shadow.multiclass_path().display());
+    }
     
-    thread::spawn(move || {
-        let model = match NidsModel::load(&binary_path, &multiclass_path) {
-            Ok(m) => {
-                println!("Models loaded successfully");
-                m
+    let threads = (0..workers)
+        .map(|id| {
+            let worker = Worker {
+                id,
+                rx_in: rx_in.clone(),
+                tx_out: tx_out.clone(),
+                batch_size: config.batch_size.max(1),
+                batch_wait: Duration::from_micros(config.batch_wait_us),
+                stats: stats.clone(),
+                thresholds: thresholds.clone(),
+                models: models.clone(),
+                shadow: shadow_stats.clone(),
+                errors: errors.clone(),
+                events: events.clone(),
+            };
+            let (bundle, shadow, ready_tx) = (bundle.clone(), shadow.cloned(), ready_tx.clone());
+            thread::Builder::new()
+                .name(format!("classifier-{id}"))
+                .spawn(move || {
+                    // The sessions stay on this thread, only the outcome of the load goes back
+                    let loaded = NidsModel::load(&bundle).and_then(|model| {
+                        let shadow = shadow.as_ref()
+                            .map(|b| NidsModel::load(b).context("Failed to load shadow models"))
+                            .transpose()?;
+                        Ok((model, shadow))
+                    });
+                    match loaded {
+                        Ok((model, shadow)) => {
+                            let _ = ready_tx.send(Ok(()));
+                            drop(ready_tx);
+                            worker.run(model, shadow);
+                        },
+                        Err(e) => {
+                            eprintln!("Failed to load models: {:?}", e);
+                            let _ = ready_tx.send(Err(format!("{e:#}")));
+                        }
+                    }
+                })
+                .context("Failed to spawn classifier thread")
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // Only the workers hold the queue ends now, the output closes when the last one exits
+    drop((rx_in, tx_out, ready_tx));
+
+    let mut failure = None;
+    for _ in 0..workers {
+        match ready_rx.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => { failure.get_or_insert(e); }
+            Err(_) => { failure.get_or_insert_with(|| "Classifier thread exited while loading the models".into()); }
+        }
+    }
+
+    match failure {
+        None => {
+            println!("Models loaded successfully by {workers} classifier worker(s)");
+            Ok(ClassifierHandles { tx: FlowTx::new(tx_in, models.clone()), rx: rx_out, results, stats, thresholds, models, shadow: shadow_stats, threads })
+        }
+        Some(e) => {
+            // Dropping the input stops the workers that did load
+            drop(tx_in);
+            for t in threads { let _ = t.join(); }
+            Err(anyhow!(e))
+        }
+    }
+}
+
+// What each classifier thread needs besides its own model
+struct Worker {
+    id: usize,
+    rx_in: Receiver<(FlowRecord, u64)>,
+    tx_out: QueueTx<(FlowRecord, MultiResult)>,
+    batch_size: usize,
+    batch_wait: Duration,
+    stats: Arc<InferenceStats>,
+    thresholds: SharedThresholds,
+    models: Arc<ModelSwap>,
+    shadow: Option<Arc<ShadowStats>>,
+    errors: Arc<Mutex<ErrorThrottle>>,
+    events: Arc<dyn EventSink>,
+}
+
+impl Worker {
+    // Processes batches until the input channel is closed and empty
+    fn run(self, model: NidsModel, shadow: Option<NidsModel>) {
+        println!("Classifier worker {} ready, waiting for flows...", self.id);
+
+        let mut models = ModelGenerations::new(model);
+        let mut batch: Vec<(FlowRecord, u64)> = Vec::with_capacity(self.batch_size);
+        'batches: while let Ok(first) = self.rx_in.recv() {
+            // Whatever arrives before the deadline rides along, a closed channel ends the batch early
+            batch.push(first);
+            let deadline = Instant::now() + self.batch_wait;
+            while batch.len() < self.batch_size {
+                match self.rx_in.recv_deadline(deadline) {
+                    Ok(flow) => batch.push(flow),
+                    Err(_) => break,
+                }
+            }
+
+            // Swapped between batches, a flow queued after a reload never meets an older model
+            for (generation, model) in self.models.take(self.id, models.newest()) {
+                println!("Classifier worker {} switched to model generation {generation}", self.id);
+                models.insert(generation, model);
+            }
+
+            // Each flow goes to the generation it was queued under
+            let groups = models.route(batch.drain(..));
+            let oldest = groups.keys().next().copied().unwrap_or(0);
+
+            // Picked up per batch, so a change applies while capture runs
+            let thresholds = self.thresholds.current();
+            for (generation, flows) in groups {
+                let Some(model) = models.get(generation) else { continue };
+                if !self.score(model, shadow.as_ref(), flows, &thresholds) { break 'batches; }
+            }
+
+            models.retire_before(oldest);
+        }
+
+        println!("Classifier worker {} exiting (channel closed)", self.id);
+    }
+
+    // Classifies the flows with one model and passes them on, false once the output is closed
+    fn score(&self, model: &NidsModel, shadow: Option<&NidsModel>, flows: Vec<FlowRecord>, thresholds: &Thresholds) -> bool {
+        if flows.is_empty() {
+            return true;
+        }
+        let t0 = Instant::now();
+        let results = match model.classify_batch(&flows, thresholds) {
+            Ok(mut results) => {
+                let dt = t0.elapsed().as_micros();
+                // Batch size and latency reach the stats event, nothing is logged per batch
+                self.stats.record(flows.len(), dt);
+                if let (Some(shadow), Some(stats)) = (shadow, &self.shadow) {
+                    self.shadow_score(shadow, stats, &flows, &mut results, thresholds);
+                }
+                results
             },
             Err(e) => {
-                eprintln!("Failed to load models: {:?}", e);
-                return;
-            }
+                // The flows still reach the store and the UI, just without a verdict
+                self.report_error(format!("Classification of {} flows failed, passed on unscored: {e:#}", flows.len()));
+                vec![MultiResult::unscored(); flows.len()]
+            },
         };
-        
-        println!("Classifier thread ready, waiting for flows...");
-        
-        // Simply process flows until the channel is closed
-        while let Ok(flow) = rx_in.recv() {
-            match model.classify_flow(&flow) {
-                Ok(result) => {
-                    if tx_out.send((flow, result)).is_err() {
-                        // Output channel closed, exit gracefully
-                        break;
-                    }
-                },
-                Err(e) => eprintln!("Classification error: {:?}", e),
+
+        for (flow, result) in flows.into_iter().zip(results) {
+            if self.tx_out.push((flow, result)).is_err() {
+                // Output channel closed, exit gracefully
+                return false;
             }
         }
-        
-        println!("Classifier thread exiting (channel closed)");
-    });
-    
-    Ok(ClassifierHandles { tx: tx_in, rx: rx_out })
-}
+        true
+    }
 
-#[inline]
-fn as_f32(v: f64) -> f32 {
-    let f = v as f32;
-    if f.is_finite() { f } else { 0.0 }
-}
+    // Same flows through the challenger, its verdicts ride along with the champion's. It gets the
+    // same live thresholds, so the comparison shows what switching bundles would change
+    fn shadow_score(&self, shadow: &NidsModel, stats: &ShadowStats, flows: &[FlowRecord], results: &mut [MultiResult], thresholds: &Thresholds) {
+        match shadow.classify_batch(flows, thresholds) {
+            Ok(shadow_results) => {
+                stats.record(flows, results, &shadow_results);
+                for (result, shadow_result) in results.iter_mut().zip(shadow_results) {
+                    result.shadow = Some(Box::new(shadow_result));
+                }
+            },
+            Err(e) => {
+                stats.failed();
+                self.report_error(format!("Shadow classification of {} flows failed: {e:#}", flows.len()));
+            },
+        }
+    }
 
-fn extract_l1_features(flow: &FlowRecord, out: &mut [f32; FEATURE_L1_COUNT]) {
-    out[0] = flow.flow_duration as f32;
-    out[1] = flow.total_fwd_bytes as f32;
-    out[2] = flow.total_bwd_bytes as f32;
-    out[3] = flow.fwd_packet_len_min as f32;
-    out[4] = as_f32(flow.fwd_packet_len_std);
-    out[5] = flow.bwd_packet_len_max as f32;
-    out[6] = flow.bwd_packet_len_min as f32;
-    out[7] = as_f32(flow.flow_bytes_per_sec);
-    out[8] = as_f32(flow.flow_packets_per_sec);
-    out[9] = as_f32(flow.flow_iat_mean);
-    out[10] = as_f32(flow.flow_iat_std);
-    out[11] = flow.fwd_iat_total as f32;
-    out[12] = as_f32(flow.fwd_iat_mean);
-    out[13] = as_f32(flow.fwd_iat_std);
-    out[14] = flow.fwd_iat_max as f32;
-    out[15] = flow.fwd_iat_min as f32;
-    out[16] = flow.bwd_iat_total as f32;
-    out[17] = as_f32(flow.bwd_iat_mean);
-    out[18] = as_f32(flow.bwd_iat_std);
-    out[19] = flow.fwd_psh_flags as f32;
-    out[20] = flow.fwd_urg_flags as f32;
-    out[21] = flow.bwd_header_len as f32;
-    out[22] = as_f32(flow.bwd_packets_per_sec);
-    out[23] = flow.packet_len_min as f32;
-    out[24] = flow.packet_len_max as f32;
-    out[25] = as_f32(flow.packet_len_mean);
-    out[26] = flow.fin_flag_count as f32;
-    out[27] = flow.syn_flag_count as f32;
-    out[28] = flow.rst_flag_count as f32;
-    out[29] = flow.psh_flag_count as f32;
-    out[30] = flow.urg_flag_count as f32;
-    out[31] = flow.cwr_flag_count as f32;
-    out[32] = flow.ece_flag_count as f32;
-    out[33] = as_f32(flow.down_up_ratio);
-    out[34] = as_f32(flow.bwd_bytes_bulk_avg);
-    out[35] = as_f32(flow.bwd_packet_bulk_avg);
-    out[36] = as_f32(flow.bwd_bulk_rate_avg);
-    out[37] = flow.subflow_fwd_packets as f32;
-    out[38] = flow.subflow_fwd_bytes as f32;
-    out[39] = flow.subflow_bwd_packets as f32;
-    out[40] = flow.fwd_init_win_bytes as f32;
-    out[41] = flow.bwd_init_win_bytes as f32;
-    out[42] = flow.fwd_act_data_packets as f32;
-    out[43] = as_f32(flow.fwd_seg_size_min);
-    out[44] = as_f32(flow.active_mean);
-    out[45] = as_f32(flow.active_std);
-    out[46] = as_f32(flow.idle_std);
-    out[47] = flow.idle_min as f32;
+    // Logged and reported at most once per throttle interval, a model failing on every batch
+    // doesn't flood either
+    fn report_error(&self, message: String) {
+        let msg = self.errors.lock().ok().and_then(|mut errors| errors.check(message));
+        if let Some(msg) = msg {
+            eprintln!("{msg}");
+            self.events.health(&HealthEvent::new(Component::Classifier, Severity::Error, msg));
+        }
+    }
 }
-
-fn extract_l2_features(flow: &FlowRecord, out: &mut [f32; FEATURE_L2_COUNT]) {
-    out[0] = flow.flow_duration as f32;
-    out[1] = flow.total_fwd_packets as f32;
-    out[2] = flow.fwd_packet_len_max as f32;
-    out[3] = flow.fwd_packet_len_min as f32;
-    out[4] = flow.bwd_packet_len_min as f32;
-    out[5] = as_f32(flow.bwd_packet_len_mean);
-    out[6] = as_f32(flow.bwd_packet_len_std);
-    out[7] = as_f32(flow.flow_bytes_per_sec);
-    out[8] = as_f32(flow.flow_packets_per_sec);
-    out[9] = as_f32(flow.flow_iat_mean);
-    out[10] = as_f32(flow.flow_iat_std);
-    out[11] = flow.flow_iat_max as f32;
-    out[12] = flow.flow_iat_min as f32;
-    out[13] = as_f32(flow.fwd_iat_mean);
-    out[14] = as_f32(flow.fwd_iat_std);
-    out[15] = flow.fwd_iat_min as f32;
-    out[16] = flow.bwd_iat_total as f32;
-    out[17] = as_f32(flow.bwd_iat_mean);
-    out[18] = as_f32(flow.bwd_iat_std);
-    out[19] = flow.bwd_iat_max as f32;
-    out[20] = flow.bwd_iat_min as f32;
-    out[21] = flow.fwd_psh_flags as f32;
-    out[22] = flow.fwd_urg_flags as f32;
-    out[23] = as_f32(flow.bwd_packets_per_sec);
-    out[24] = flow.packet_len_min as f32;
-    out[25] = flow.packet_len_max as f32;
-    out[26] = flow.packet_len_variance as f32;
-    out[27] = flow.fin_flag_count as f32;
-    out[28] = flow.syn_flag_count as f32;
-    out[29] = flow.rst_flag_count as f32;
-    out[30] = flow.psh_flag_count as f32;
-    out[31] = flow.ack_flag_count as f32;
-    out[32] = flow.urg_flag_count as f32;
-    out[33] = flow.cwr_flag_count as f32;
-    out[34] = flow.ece_flag_count as f32;
-    out[35] = as_f32(flow.down_up_ratio);
-    out[36] = flow.avg_packet_size as f32;
-    out[37] = flow.fwd_segment_size_avg as f32;
-    out[38] = as_f32(flow.bwd_bytes_bulk_avg);
-    out[39] = as_f32(flow.bwd_packet_bulk_avg);
-    out[40] = as_f32(flow.bwd_bulk_rate_avg);
-    out[41] = flow.subflow_fwd_packets as f32;
-    out[42] = flow.subflow_fwd_bytes as f32;
-    out[43] = flow.subflow_bwd_packets as f32;
-    out[44] = flow.subflow_bwd_bytes as f32;
-    out[45] = flow.fwd_init_win_bytes as f32;
-    out[46] = flow.bwd_init_win_bytes as f32;
-    out[47] = flow.fwd_act_data_packets as f32;
-    out[48] = as_f32(flow.fwd_seg_size_min);
-    out[49] = as_f32(flow.active_std);
-    out[50] = flow.active_max as f32;
-    out[51] = as_f32(flow.idle_std);
-}
\ No newline at end of file
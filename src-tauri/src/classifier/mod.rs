@@ -1,10 +1,19 @@
+pub mod bundle;
 pub mod classifier;
+pub mod reload;
+pub mod shadow;
+pub mod thresholds;
 
 pub use classifier::{
-    FEATURE_L1_COUNT,
-    ATTACK_THRESHOLD,
+    ClassifierConfig,
+    InferenceStats,
     Inference,
+    MultiResult,
     NidsModel,
     ClassifierHandles,
     spawn_classifier,
-};
\ No newline at end of file
+};
+pub use thresholds::{ATTACK_THRESHOLD, SharedThresholds, Thresholds};
+pub use bundle::{ModelBundle, ModelSpec, L1_FEATURES, L2_FEATURES, MANIFEST_FILE_NAME};
+pub use reload::{FlowTx, ModelGenerations, ModelReloader, ModelSwap, watch_models};
+pub use shadow::{Disagreement, ShadowReport, ShadowStats, MAX_RECENT_DISAGREEMENTS};
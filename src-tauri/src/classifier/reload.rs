@@ -0,0 +1,216 @@
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::pipeline::EventSink;
+use crate::processor::FlowRecord;
+use crate::queue::{QueueMonitor, QueueTx};
+use crate::types::{Component, HealthEvent, Severity};
+use super::bundle::ModelBundle;
+use super::classifier::NidsModel;
+use super::thresholds::SharedThresholds;
+
+/// Models published to the classifier workers. A reload loads and checks one set of sessions
+/// per worker first, then each worker swaps its own in between two batches
+pub struct ModelSwap {
+    dir: PathBuf,
+    workers: usize,
+    generation: AtomicU64,
+    // Sessions not yet taken, by worker id then generation
+    pending: Mutex<Vec<BTreeMap<u64, NidsModel>>>,
+    // One reload at a time, whether from the command or the watcher
+    reloading: Mutex<()>,
+}
+
+impl ModelSwap {
+    pub(super) fn new(dir: PathBuf, workers: usize) -> Self {
+        Self {
+            dir,
+            workers,
+            generation: AtomicU64::new(0),
+            pending: Mutex::new((0..workers).map(|_| BTreeMap::new()).collect()),
+            reloading: Mutex::new(()),
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Loads the bundle again from its folder. Nothing changes unless every worker's sessions
+    /// load and match the manifest, and the thresholds fit the new class map
+    pub fn reload(&self, thresholds: &SharedThresholds) -> Result<u64, String> {
+        let _reloading = self.reloading.lock().map_err(|_| "Failed to lock model reload")?;
+        let bundle = ModelBundle::load(&self.dir).map_err(|e| format!("Invalid model bundle: {e}"))?;
+        let models = (0..self.workers)
+            .map(|_| NidsModel::load(&bundle))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map_err(|e| format!("{e:#}"))?;
+
+        thresholds.rebind(bundle.labels.clone(), || {
+            let mut pending = self.pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let generation = self.generation() + 1;
+            for (worker, model) in pending.iter_mut().zip(models) {
+                worker.insert(generation, model);
+            }
+            // Bumped with the sessions in place, a worker that sees it finds its own
+            self.generation.store(generation, Ordering::Release);
+            generation
+        })
+    }
+
+    /// This worker's sessions of every generation newer than `have`, taken once. Two reloads
+    /// before the worker's next batch hand out both, flows may be queued under either
+    pub(super) fn take(&self, worker: usize, have: u64) -> BTreeMap<u64, NidsModel> {
+        if self.generation() <= have {
+            return BTreeMap::new();
+        }
+        let Ok(mut pending) = self.pending.lock() else { return BTreeMap::new() };
+        pending.get_mut(worker).map(std::mem::take).unwrap_or_default()
+    }
+}
+
+/// Reloads the models of a running pipeline without borrowing it, so the bundle can load
+/// while the pipeline stays free for other callers
+#[derive(Clone)]
+pub struct ModelReloader {
+    models: Arc<ModelSwap>,
+    thresholds: SharedThresholds,
+}
+
+impl ModelReloader {
+    pub(crate) fn new(models: Arc<ModelSwap>, thresholds: SharedThresholds) -> Self {
+        Self { models, thresholds }
+    }
+
+    /// Same as `ModelSwap::reload`, blocks while the sessions load
+    pub fn reload(&self) -> Result<u64, String> {
+        self.models.reload(&self.thresholds)
+    }
+}
+
+/// The models one classifier worker holds, by generation. The newest scores flows queued
+/// after the last reload, older ones finish the flows queued before it
+pub struct ModelGenerations<M> {
+    models: BTreeMap<u64, M>,
+}
+
+impl<M> ModelGenerations<M> {
+    /// Starts at generation 0 with the models loaded at startup
+    pub fn new(model: M) -> Self {
+        Self { models: BTreeMap::from([(0, model)]) }
+    }
+
+    pub fn newest(&self) -> u64 {
+        self.models.last_key_value().map_or(0, |(generation, _)| *generation)
+    }
+
+    pub fn insert(&mut self, generation: u64, model: M) {
+        self.models.insert(generation, model);
+    }
+
+    pub fn get(&self, generation: u64) -> Option<&M> {
+        self.models.get(&generation)
+    }
+
+    /// Groups the batch by the generation that scores each flow: the one it was queued under,
+    /// or the newest one before it this worker has
+    pub fn route<T>(&self, batch: impl IntoIterator<Item = (T, u64)>) -> BTreeMap<u64, Vec<T>> {
+        let mut groups: BTreeMap<u64, Vec<T>> = BTreeMap::new();
+        for (item, generation) in batch {
+            let generation = self.models.range(..=generation).next_back()
+                .or(self.models.first_key_value())
+                .map_or(generation, |(generation, _)| *generation);
+            groups.entry(generation).or_default().push(item);
+        }
+        groups
+    }
+
+    /// Drops the models older than `oldest`. Flows are queued in order, so once a batch has
+    /// none of a generation no more will come
+    pub fn retire_before(&mut self, oldest: u64) {
+        self.models.retain(|generation, _| *generation >= oldest);
+    }
+
+    pub fn generations(&self) -> Vec<u64> {
+        self.models.keys().copied().collect()
+    }
+}
+
+/// Classifier input. Every flow carries the model generation it was queued under,
+/// so a reload doesn't change the verdict of flows already waiting
+#[derive(Clone)]
+pub struct FlowTx {
+    tx: QueueTx<(FlowRecord, u64)>,
+    models: Arc<ModelSwap>,
+}
+
+impl FlowTx {
+    pub(super) fn new(tx: QueueTx<(FlowRecord, u64)>, models: Arc<ModelSwap>) -> Self {
+        Self { tx, models }
+    }
+
+    /// Queues the flow or drops it as the queue's policy says, false once the classifier is gone
+    pub fn push(&self, flow: FlowRecord) -> bool {
+        self.tx.push((flow, self.models.generation())).is_ok()
+    }
+
+    /// Waits for room whatever the policy, false once the classifier is gone
+    pub fn push_blocking(&self, flow: FlowRecord) -> bool {
+        self.tx.push_blocking((flow, self.models.generation())).is_ok()
+    }
+
+    pub fn monitor(&self) -> Arc<QueueMonitor> {
+        self.tx.monitor()
+    }
+}
+
+/// Reloads the models once the files in their folder changed and then stayed the same for a
+/// whole `interval`, so a bundle still being copied isn't picked up half written. Runs until
+/// `stop` is dropped
+pub fn watch_models(
+    models: Arc<ModelSwap>,
+    thresholds: SharedThresholds,
+    interval: Duration,
+    stop: Receiver<()>,
+    events: Arc<dyn EventSink>,
+) {
+    let mut loaded = fingerprint(&models.dir);
+    let mut last = loaded.clone();
+
+    while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(interval) {
+        let current = fingerprint(&models.dir);
+        if current != loaded && current == last {
+            // A failed bundle isn't retried until its files change again
+            loaded = current.clone();
+            let event = match models.reload(&thresholds) {
+                Ok(_) => HealthEvent::new(Component::Classifier, Severity::Info,
+                    format!("Models reloaded from {}", models.dir.display())),
+                Err(e) => HealthEvent::new(Component::Classifier, Severity::Error,
+                    format!("Model reload failed, keeping the current models: {e}")),
+            };
+            println!("{}", event.message);
+            events.health(&event);
+        }
+        last = current;
+    }
+}
+
+// Name, size and modification time of every file in the bundle folder
+fn fingerprint(dir: &Path) -> Vec<(PathBuf, u64, Option<SystemTime>)> {
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some((entry.path(), meta.len(), meta.modified().ok()))
+        })
+        .collect();
+    files.sort();
+    files
+}
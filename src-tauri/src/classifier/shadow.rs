@@ -0,0 +1,89 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+use crate::processor::FlowRecord;
+use crate::types::FlowKeyDTO;
+use super::classifier::MultiResult;
+
+/// Disagreeing flows a report lists, older ones only count in `disagreements`
+pub const MAX_RECENT_DISAGREEMENTS: usize = 50;
+
+/// A flow the two bundles labelled differently
+#[derive(Debug, Clone, Serialize)]
+pub struct Disagreement {
+    pub key: FlowKeyDTO,
+    pub end_us: u64,
+    pub champion: String,
+    pub challenger: String,
+}
+
+/// How the challenger (shadow) models compare with the running ones on the same flows
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ShadowReport {
+    pub models: String,                 // Challenger bundle folder
+    pub flows: u64,                     // Scored by both
+    pub agreed: u64,                    // Same label from both
+    pub agreement: f64,                 // agreed / flows
+    pub attack_agreed: u64,             // Same attack or benign call, whatever the class
+    pub failed_batches: u64,            // Challenger runs that failed, their flows aren't compared
+    // Champion label -> challenger label -> flows
+    pub confusion: BTreeMap<String, BTreeMap<String, u64>>,
+    // Per champion label, flows the challenger labelled otherwise
+    pub disagreements: BTreeMap<String, u64>,
+    // The latest MAX_RECENT_DISAGREEMENTS of them, oldest first
+    pub recent_disagreements: VecDeque<Disagreement>,
+}
+
+/// Running totals since the session started, updated by every classifier worker
+pub struct ShadowStats {
+    report: Mutex<ShadowReport>,
+}
+
+impl ShadowStats {
+    pub fn new(models: String) -> Self {
+        Self { report: Mutex::new(ShadowReport { models, ..ShadowReport::default() }) }
+    }
+
+    /// Compares the verdicts of one batch, flows either model left unscored are left out
+    pub fn record(&self, flows: &[FlowRecord], champion: &[MultiResult], challenger: &[MultiResult]) {
+        let Ok(mut report) = self.report.lock() else { return };
+        for ((flow, a), b) in flows.iter().zip(champion).zip(challenger) {
+            let (Some(label_a), Some(label_b)) = (a.label(), b.label()) else { continue };
+            report.flows += 1;
+            if label_a == label_b {
+                report.agreed += 1;
+            } else {
+                *report.disagreements.entry(label_a.to_string()).or_default() += 1;
+                if report.recent_disagreements.len() == MAX_RECENT_DISAGREEMENTS {
+                    report.recent_disagreements.pop_front();
+                }
+                report.recent_disagreements.push_back(Disagreement {
+                    key: flow.key.into(),
+                    end_us: flow.flow_last_time,
+                    champion: label_a.to_string(),
+                    challenger: label_b.to_string(),
+                });
+            }
+            if a.is_attack() == b.is_attack() {
+                report.attack_agreed += 1;
+            }
+            *report.confusion.entry(label_a.to_string()).or_default().entry(label_b.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn failed(&self) {
+        if let Ok(mut report) = self.report.lock() {
+            report.failed_batches += 1;
+        }
+    }
+
+    pub fn report(&self) -> ShadowReport {
+        let mut report = match self.report.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        report.agreement = if report.flows == 0 { 0.0 } else { report.agreed as f64 / report.flows as f64 };
+        report
+    }
+}
@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+pub const ATTACK_THRESHOLD: f32 = 0.85;
+
+/// Probabilities a verdict needs, from layton.json or set while a session runs
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Thresholds {
+    pub attack: Option<f32>,            // Minimum P(attack) of the binary model, the bundle's own when missing
+    pub classes: BTreeMap<String, f32>, // Minimum probability of the predicted multiclass label, unlisted labels have none
+}
+
+impl Thresholds {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.attack.is_some_and(|t| !(0.0..=1.0).contains(&t)) {
+            return Err("attack threshold must be between 0 and 1".into());
+        }
+        if let Some((class, _)) = self.classes.iter().find(|(_, p)| !(0.0..=1.0).contains(*p)) {
+            return Err(format!("threshold of class {class} must be between 0 and 1"));
+        }
+        Ok(())
+    }
+
+    /// Minimum P(attack) for a bundle whose own threshold is `bundle`, the override wins when set
+    pub fn attack_or(&self, bundle: f32) -> f32 {
+        self.attack.unwrap_or(bundle)
+    }
+
+    /// Most likely class of a model with these `labels`, None when it falls short of its minimum, and that minimum
+    pub fn pick_class(&self, labels: &[String], probs: &[f32]) -> (Option<u8>, Option<f32>) {
+        let Some((idx, p)) = probs.iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        else {
+            return (None, None);
+        };
+        let min = labels.get(idx).and_then(|label| self.classes.get(label)).copied();
+        let class = min.is_none_or(|min| *p >= min).then_some(idx as u8);
+        (class, min)
+    }
+}
+
+/// Thresholds every classifier worker reads, checked against the class map of the loaded
+/// models. A change swaps the whole set, so one batch is never judged by a mix of old and new values
+#[derive(Clone)]
+pub struct SharedThresholds {
+    current: Arc<Mutex<Active>>,
+}
+
+struct Active {
+    thresholds: Arc<Thresholds>,
+    labels: Vec<String>,
+}
+
+impl SharedThresholds {
+    pub fn new(labels: Vec<String>, thresholds: &Thresholds) -> Result<Self, String> {
+        thresholds.validate()?;
+        check_classes(&labels, thresholds)?;
+        let active = Active { thresholds: Arc::new(thresholds.clone()), labels };
+        Ok(Self { current: Arc::new(Mutex::new(active)) })
+    }
+
+    /// Applies from the next batch on, a class missing from the class map is an error
+    pub fn set(&self, thresholds: &Thresholds) -> Result<(), String> {
+        thresholds.validate()?;
+        let mut active = self.current.lock().map_err(|_| "Failed to lock thresholds")?;
+        check_classes(&active.labels, thresholds)?;
+        active.thresholds = Arc::new(thresholds.clone());
+        Ok(())
+    }
+
+    /// Moves the thresholds to the class map of new models, refused if a class with a minimum
+    /// is gone. `swap` runs under the same lock, so no change slips in between
+    pub fn rebind<T>(&self, labels: Vec<String>, swap: impl FnOnce() -> T) -> Result<T, String> {
+        let mut active = self.current.lock().map_err(|_| "Failed to lock thresholds")?;
+        check_classes(&labels, &active.thresholds)?;
+        active.labels = labels;
+        Ok(swap())
+    }
+
+    pub fn current(&self) -> Arc<Thresholds> {
+        match self.current.lock() {
+            Ok(guard) => guard.thresholds.clone(),
+            Err(poisoned) => poisoned.into_inner().thresholds.clone(),
+        }
+    }
+}
+
+fn check_classes(labels: &[String], thresholds: &Thresholds) -> Result<(), String> {
+    match thresholds.classes.keys().find(|class| !labels.contains(class)) {
+        Some(class) => Err(format!("Unknown class {class}, the model knows {}", labels.join(", "))),
+        None => Ok(()),
+    }
+}
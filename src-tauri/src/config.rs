@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use crate::capture::ReplayMode;
+use crate::classifier::{ClassifierConfig, Thresholds};
+use crate::export::ExportLabel;
+use crate::processor::{EngineConfig, FlowConfig};
+use crate::queue::QueuesConfig;
+use crate::store::StoreConfig;
+
+pub const CONFIG_FILE_NAME: &str = "layton.json";
+
+/// Settings read from `layton.json`. Every section is optional and falls back to its defaults
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LaytonConfig {
+    pub flow: FlowConfig,
+    pub engine: EngineConfig,
+    pub classifier: ClassifierConfig,
+    pub thresholds: Thresholds,
+    pub queues: QueuesConfig,
+    pub store: StoreConfig,
+    pub daemon: DaemonConfig,
+}
+
+/// Only read by the headless `layton daemon`, the desktop app picks these in the UI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    pub interface: Option<String>,      // Live capture, or
+    pub replay: Option<String>,         // a pcap/pcapng file
+    pub replay_mode: ReplayMode,
+    pub models_dir: String,             // Model bundle: manifest.json, the ONNX models and their class map
+    pub export: Option<ExportConfig>,
+    pub stats_interval_secs: u64,       // How often the traffic summary is logged, 0 disables it
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            interface: None,
+            replay: None,
+            replay_mode: ReplayMode::AsFastAsPossible,
+            models_dir: "classifier-models".into(),
+            export: None,
+            stats_interval_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportConfig {
+    pub path: String,
+    #[serde(default = "default_export_label")]
+    pub label: ExportLabel,
+}
+
+fn default_export_label() -> ExportLabel { ExportLabel::Classifier }
+
+impl LaytonConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let s = fs::read_to_string(path).map_err(|e| format!("read {}: {e}", path.display()))?;
+        let config: Self = serde_json::from_str(&s).map_err(|e| format!("parse {}: {e}", path.display()))?;
+        config.flow.validate().map_err(|e| format!("{}: {e}", path.display()))?;
+        config.engine.validate().map_err(|e| format!("{}: {e}", path.display()))?;
+        config.classifier.validate().map_err(|e| format!("{}: {e}", path.display()))?;
+        config.thresholds.validate().map_err(|e| format!("{}: {e}", path.display()))?;
+        config.queues.validate().map_err(|e| format!("{}: {e}", path.display()))?;
+        config.store.validate().map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Same as `load` but a missing file just means defaults
+    pub fn load_or_default<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        if path.as_ref().exists() { Self::load(path) } else { Ok(Self::default()) }
+    }
+}
@@ -0,0 +1,212 @@
+use crossbeam_channel::{bounded, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::capture::ReplayMode;
+use crate::classifier::ShadowReport;
+use crate::config::{LaytonConfig, CONFIG_FILE_NAME};
+use crate::export::CsvExporter;
+use crate::store::{FlowStore, STORE_FILE_NAME};
+use crate::pipeline::{EventSink, PacketSource, Pipeline, PipelineOptions};
+use crate::types::{ClassifiedFlowEvent, HealthEvent, NetworkStats, Severity};
+
+const USAGE: &str = "\
+Usage: layton daemon [--config <file>] [--interface <name> | --replay <file>]
+
+Runs the capture and classification pipeline without the UI until SIGINT/SIGTERM
+(or the end of the file when replaying). Alerts are logged to stdout.
+
+  --config <file>      Settings file, defaults to ./layton.json when present
+  --interface <name>   Capture live from this interface (overrides the config)
+  --replay <file>      Replay a pcap/pcapng file instead (overrides the config)";
+
+// Logs alerts and a periodic traffic summary
+struct LogSink {
+    stats_interval: Option<Duration>,
+    last_stats: Mutex<Instant>,
+    last_shadow: Mutex<Instant>,
+}
+
+impl EventSink for LogSink {
+    fn network_stats(&self, stats: &NetworkStats) {
+        let Some(interval) = self.stats_interval else { return };
+        let Ok(mut last) = self.last_stats.lock() else { return };
+        if last.elapsed() < interval {
+            return;
+        }
+        *last = Instant::now();
+        println!(
+            "[stats] {} active flows ({} tcp, {} udp, {} icmp), {:.0} pkt/s, {:.0} B/s, {} packets total, {} flows evicted",
+            stats.flow_count, stats.tcp_flows, stats.udp_flows, stats.icmp_flows,
+            stats.packets_per_second, stats.bytes_per_second, stats.total_packets, stats.evicted_flows,
+        );
+        println!(
+            "[stats] drops: {} kernel, {} interface, {} queue full; parse errors: {} not ip, {} other transport, {} malformed; {} flows queued for inference",
+            stats.kernel_drops, stats.interface_drops, stats.channel_full_drops,
+            stats.parse_errors_not_ip, stats.parse_errors_unsupported_transport, stats.parse_errors_malformed,
+            stats.classifier_queue_depth,
+        );
+        println!(
+            "[stats] inference: {} batches, last {} flows in {} µs, {:.0} µs per batch on average",
+            stats.inference_batches, stats.inference_last_batch_size, stats.inference_last_batch_us, stats.inference_avg_batch_us,
+        );
+    }
+
+    fn flow_classified(&self, event: &ClassifiedFlowEvent) {
+        if !event.is_attack {
+            return;
+        }
+        let k = &event.key;
+        println!(
+            "[ALERT] {} {}:{} <-> {}:{} proto {} p_attack={:.3} packets={} bytes={} duration={}us",
+            event.multi_label.as_deref().unwrap_or("Attack"),
+            k.ip_a, k.port_a, k.ip_b, k.port_b, k.protocol,
+            event.p_attack, event.total_packets, event.total_bytes, event.duration_us,
+        );
+    }
+
+    fn health(&self, event: &HealthEvent) {
+        let tag = format!("[{:?}] {:?}", event.severity, event.component).to_lowercase();
+        let line = format!("{tag}: {}", event.message);
+        match event.severity {
+            Severity::Info => println!("{line}"),
+            Severity::Warning | Severity::Error => eprintln!("{line}"),
+        }
+    }
+
+    // Logged with the stats, the full confusion is in the flow store
+    fn shadow_stats(&self, report: &ShadowReport) {
+        let Some(interval) = self.stats_interval else { return };
+        let Ok(mut last) = self.last_shadow.lock() else { return };
+        if last.elapsed() < interval {
+            return;
+        }
+        *last = Instant::now();
+        let disagreements: Vec<String> = report.disagreements.iter().map(|(label, n)| format!("{label} {n}")).collect();
+        println!(
+            "[shadow] {} flows compared, {:.1}% same label, {} same attack/benign call, {} failed batches; disagreements by class: {}",
+            report.flows, report.agreement * 100.0, report.attack_agreed, report.failed_batches,
+            if disagreements.is_empty() { "none".to_string() } else { disagreements.join(", ") },
+        );
+    }
+}
+
+struct Args {
+    config: Option<String>,
+    interface: Option<String>,
+    replay: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args { config: None, interface: None, replay: None };
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().cloned().ok_or_else(|| format!("{arg} needs a value\n\n{USAGE}"));
+        match arg.as_str() {
+            "--config" => parsed.config = Some(value()?),
+            "--interface" => parsed.interface = Some(value()?),
+            "--replay" => parsed.replay = Some(value()?),
+            "-h" | "--help" => return Err(USAGE.into()),
+            other => return Err(format!("Unknown argument {other}\n\n{USAGE}")),
+        }
+    }
+    Ok(parsed)
+}
+
+fn source_from(interface: Option<String>, replay: Option<String>, mode: ReplayMode) -> Result<Option<PacketSource>, String> {
+    match (interface, replay) {
+        (Some(_), Some(_)) => Err("Set either an interface or a replay file, not both".into()),
+        (Some(name), None) => Ok(Some(PacketSource::Interface(name))),
+        (None, Some(path)) => Ok(Some(PacketSource::Replay { path, mode })),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Entry point of `layton daemon`. Returns once the pipeline was stopped
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = parse_args(args)?;
+
+    let config = match &args.config {
+        Some(path) => LaytonConfig::load(path)?,
+        None => LaytonConfig::load_or_default(CONFIG_FILE_NAME)?,
+    };
+    let daemon = config.daemon;
+
+    // Command line wins over the config file
+    let source = match source_from(args.interface, args.replay, daemon.replay_mode)? {
+        Some(source) => source,
+        None => source_from(daemon.interface.clone(), daemon.replay.clone(), daemon.replay_mode)?
+            .ok_or_else(|| format!("No interface or replay file configured\n\n{USAGE}"))?,
+    };
+
+    let exporter = match &daemon.export {
+        Some(export) => {
+            let exporter = CsvExporter::create(&export.path, export.label.clone())
+                .map_err(|e| format!("Could not create {}: {e}", export.path))?;
+            println!("Exporting flows to {}", export.path);
+            Some(exporter)
+        }
+        None => None,
+    };
+    let exporter = Arc::new(Mutex::new(exporter));
+
+    let store = if config.store.enabled {
+        let path = config.store.path.clone().unwrap_or_else(|| STORE_FILE_NAME.into());
+        let store = FlowStore::open(&path, config.store.clone()).map_err(|e| format!("Could not open flow store {path}: {e}"))?;
+        println!("Storing flows in {path}");
+        Some(store)
+    } else {
+        None
+    };
+
+    // SIGINT, SIGTERM and SIGHUP all end the session cleanly
+    let (stop_tx, stop_rx) = bounded::<()>(1);
+    ctrlc::set_handler(move || { let _ = stop_tx.try_send(()); })
+        .map_err(|e| format!("Could not install signal handler: {e}"))?;
+
+    let sink = LogSink {
+        stats_interval: (daemon.stats_interval_secs > 0).then(|| Duration::from_secs(daemon.stats_interval_secs)),
+        last_stats: Mutex::new(Instant::now()),
+        last_shadow: Mutex::new(Instant::now()),
+    };
+    let options = PipelineOptions {
+        flow: config.flow,
+        engine: config.engine,
+        classifier: config.classifier,
+        thresholds: config.thresholds,
+        queues: config.queues,
+        models: daemon.models_dir.clone().into(),
+        exporter: exporter.clone(),
+        store: Arc::new(Mutex::new(store)),
+    };
+
+    let pipeline = Pipeline::start(&source, options, Arc::new(sink))?;
+    match &source {
+        PacketSource::Interface(name) => println!("Capturing on {name}"),
+        PacketSource::Replay { path, .. } => println!("Replaying {path}"),
+    }
+
+    loop {
+        match stop_rx.recv_timeout(Duration::from_secs(1)) {
+            Ok(()) | Err(RecvTimeoutError::Disconnected) => {
+                println!("Signal received, shutting down");
+                break;
+            }
+            Err(RecvTimeoutError::Timeout) if pipeline.source_finished() => {
+                println!("Capture ended, shutting down");
+                break;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+    }
+
+    let flushed = pipeline.stop()?;
+    println!("{flushed} active flows flushed");
+
+    if let Some(mut exporter) = exporter.lock().map_err(|_| "Failed to lock exporter")?.take() {
+        exporter.flush().map_err(|e| format!("Error flushing export: {e}"))?;
+        println!("{} flows exported, {} ICMP flows left out", exporter.rows(), exporter.skipped());
+    }
+    println!("Layton stopped");
+    Ok(())
+}
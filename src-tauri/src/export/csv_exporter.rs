@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+use crate::processor::{FlowRecord, CIC_FEATURES};
+
+/// Identifies the flow, written before the features
+pub const CIC_ID_COLUMNS: [&str; 7] = ["Flow ID", "Src IP", "Src Port", "Dst IP", "Dst Port", "Protocol", "Timestamp"];
+
+/// What goes in the Label column
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "mode", content = "value", rename_all = "snake_case")]
+pub enum ExportLabel {
+    None,               // No Label column at all
+    Fixed(String),      // Same label for every row, e.g. a capture of known traffic
+    Classifier,         // BENIGN or the multiclass label from the live classifier
+}
+
+impl ExportLabel {
+    /// CICFlowMeter never quotes its fields, so a fixed label can't hold a separator or a line break
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ExportLabel::Fixed(value) if value.is_empty() => Err("The export label is empty".into()),
+            ExportLabel::Fixed(value) if value.contains([',', '"', '\r', '\n']) => {
+                Err(format!("The export label {value:?} can't contain commas, quotes or line breaks"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Writes finalized flows as CICFlowMeter CSV rows, so captures can be turned into training data.
+/// ICMP pseudo-flows are left out, CICFlowMeter only produces TCP and UDP flows
+pub struct CsvExporter {
+    writer: BufWriter<File>,
+    label: ExportLabel,
+    rows: u64,
+    skipped: u64,
+}
+
+impl CsvExporter {
+    /// Creates (or truncates) the file and writes the header
+    pub fn create<P: AsRef<Path>>(path: P, label: ExportLabel) -> std::io::Result<Self> {
+        label.validate().map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        let mut header: Vec<&str> = CIC_ID_COLUMNS.to_vec();
+        header.extend(CIC_FEATURES.iter().map(|(name, _)| *name));
+        if !matches!(label, ExportLabel::None) {
+            header.push("Label");
+        }
+        writeln!(writer, "{}", header.join(","))?;
+
+        Ok(Self { writer, label, rows: 0, skipped: 0 })
+    }
+
+    /// `predicted` is the classifier's label for the flow, only used in Classifier mode
+    pub fn write_flow(&mut self, flow: &mut FlowRecord, predicted: Option<&str>) -> std::io::Result<()> {
+        // Their "ports" are the ICMP type/code and identifier, nothing CICFlowMeter would write
+        if flow.key.is_icmp() {
+            self.skipped += 1;
+            return Ok(());
+        }
+        // No-op for flows the engine already finalized
+        flow.finalize();
+
+        // CICFlowMeter's source is the first packet's sender
+        let ((src_ip, src_port), (dst_ip, dst_port)) = (flow.initiator, flow.responder());
+        let protocol = flow.key.protocol;
+        let mut row: Vec<String> = vec![
+            format!("{src_ip}-{dst_ip}-{src_port}-{dst_port}-{protocol}"),
+            src_ip.to_string(),
+            src_port.to_string(),
+            dst_ip.to_string(),
+            dst_port.to_string(),
+            protocol.to_string(),
+            format_timestamp(flow.flow_start_time),
+        ];
+        row.extend(CIC_FEATURES.iter().map(|(_, get)| get(flow).to_string()));
+
+        match &self.label {
+            ExportLabel::None => {}
+            ExportLabel::Fixed(value) => row.push(value.clone()),
+            ExportLabel::Classifier => row.push(predicted.unwrap_or("Unknown").to_string()),
+        }
+
+        writeln!(self.writer, "{}", row.join(","))?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    pub fn rows(&self) -> u64 { self.rows }
+
+    /// ICMP flows left out
+    pub fn skipped(&self) -> u64 { self.skipped }
+
+    pub fn flush(&mut self) -> std::io::Result<()> { self.writer.flush() }
+}
+
+// CICFlowMeter's "dd/MM/yyyy hh:mm:ss a", in UTC
+fn format_timestamp(micros: u64) -> String {
+    let secs = micros / 1_000_000;
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    let (hour, minute, second) = (rem / 3600, (rem % 3600) / 60, rem % 60);
+
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let (hour12, am_pm) = match hour {
+        0 => (12, "AM"),
+        1..=11 => (hour, "AM"),
+        12 => (12, "PM"),
+        _ => (hour - 12, "PM"),
+    };
+    format!("{day:02}/{month:02}/{year} {hour12:02}:{minute:02}:{second:02} {am_pm}")
+}
@@ -0,0 +1,7 @@
+pub mod csv_exporter;
+
+pub use crate::processor::CIC_FEATURES;
+pub use csv_exporter::{
+    CsvExporter,
+    ExportLabel,
+};
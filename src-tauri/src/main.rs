@@ -1,6 +1,26 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
-#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+#![cfg_attr(all(not(debug_assertions), feature = "gui"), windows_subsystem = "windows")]
 
 fn main() {
-    layton_lib::run()
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // `layton daemon ...` runs headless, builds without the gui feature are always headless
+    let daemon_args = match args.split_first() {
+        Some((cmd, rest)) if cmd == "daemon" => Some(rest),
+        _ if !cfg!(feature = "gui") => Some(&args[..]),
+        _ => None,
+    };
+
+    match daemon_args {
+        Some(daemon_args) => {
+            if let Err(e) = layton_lib::daemon::run(daemon_args) {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+        #[cfg(feature = "gui")]
+        None => layton_lib::run(),
+        #[cfg(not(feature = "gui"))]
+        None => unreachable!(),
+    }
 }
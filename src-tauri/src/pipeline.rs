@@ -0,0 +1,261 @@
+use crossbeam_channel::{bounded, Sender};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::capture::{PacketSniffer, ReplayMode};
+use crate::classifier::{self, ClassifierConfig, ClassifierHandles, ModelBundle, ModelReloader, ShadowReport, SharedThresholds, Thresholds};
+use crate::export::CsvExporter;
+use crate::processor::{EngineConfig, FeatureProcessor, FlowConfig};
+use crate::queue::QueuesConfig;
+use crate::store::FlowStore;
+use crate::types::{ClassifiedFlowEvent, Component, HealthEvent, NetworkStats, Severity};
+
+// Most classified flows the forwarder stores in one transaction
+const STORE_BATCH: usize = 256;
+
+// `protochain` walks IPv6 extension headers, plain `tcp`/`udp`/`icmp6` only check the fixed header
+pub const CAPTURE_FILTER: &str = "tcp or udp or icmp or ip6 protochain 6 or ip6 protochain 17 or ip6 protochain 58";
+
+/// Where the pipeline reports to. The desktop app forwards everything to the
+/// frontend, the daemon logs it.
+pub trait EventSink: Send + Sync {
+    fn network_stats(&self, stats: &NetworkStats);
+    fn flow_classified(&self, event: &ClassifiedFlowEvent);
+    fn health(&self, event: &HealthEvent);
+    fn shadow_stats(&self, report: &ShadowReport);
+}
+
+/// Lets a component that fails on every flow report once per interval instead of flooding the sink
+pub(crate) struct ErrorThrottle {
+    interval: Duration,
+    last: Option<Instant>,
+    suppressed: u64,
+}
+
+impl ErrorThrottle {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self { interval, last: None, suppressed: 0 }
+    }
+
+    /// The message to report now, None while still inside the interval
+    pub(crate) fn check(&mut self, message: String) -> Option<String> {
+        if self.last.is_some_and(|t| t.elapsed() < self.interval) {
+            self.suppressed += 1;
+            return None;
+        }
+        self.last = Some(Instant::now());
+        match std::mem::take(&mut self.suppressed) {
+            0 => Some(message),
+            n => Some(format!("{message} ({n} more since the last report)")),
+        }
+    }
+}
+
+pub enum PacketSource {
+    Interface(String),
+    Replay { path: String, mode: ReplayMode },
+}
+
+pub struct PipelineOptions {
+    pub flow: FlowConfig,
+    pub engine: EngineConfig,
+    pub classifier: ClassifierConfig,
+    pub thresholds: Thresholds,
+    pub queues: QueuesConfig,
+    pub models: PathBuf,        // Model bundle folder, see ModelBundle
+    pub exporter: Arc<Mutex<Option<CsvExporter>>>,
+    pub store: Arc<Mutex<Option<FlowStore>>>,
+}
+
+/// sniffer -> processor -> classifier, independent of any UI
+pub struct Pipeline {
+    sniffer: PacketSniffer,
+    processor: FeatureProcessor,
+    classifier: ClassifierHandles,
+    // Exports and reports the classified flows, ends when the classifier does
+    forwarder: JoinHandle<()>,
+    // Reloads the models when their files change, stops once its sender is dropped
+    watcher: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl Pipeline {
+    pub fn start(source: &PacketSource, options: PipelineOptions, sink: Arc<dyn EventSink>) -> Result<Self, String> {
+        let mut processor = FeatureProcessor::new(options.flow, &options.engine, &options.queues.packets);
+
+        let bundle = ModelBundle::load(&options.models)
+            .map_err(|e| format!("Invalid model bundle: {e}"))?;
+        let shadow = options.classifier.shadow_models.as_ref()
+            .map(ModelBundle::load)
+            .transpose()
+            .map_err(|e| format!("Invalid shadow model bundle: {e}"))?;
+        let thresholds = SharedThresholds::new(bundle.labels.clone(), &options.thresholds)
+            .map_err(|e| format!("Invalid thresholds: {e}"))?;
+
+        // Returns only once the models are loaded, so a broken bundle fails the start
+        let classifier = classifier::spawn_classifier(&bundle, shadow.as_ref(), &options.classifier, thresholds, &options.queues, sink.clone())
+            .map_err(|e| format!("Failed to start classifier: {e:#}"))?;
+
+        // Thread to receive the classified flows
+        let forwarder = {
+            let rx = classifier.rx.clone();
+            let sink = sink.clone();
+            let exporter = options.exporter.clone();
+            let store = options.store.clone();
+            let mut export_errors = ErrorThrottle::new(Duration::from_secs(10));
+            let mut store_errors = ErrorThrottle::new(Duration::from_secs(10));
+
+            std::thread::spawn(move || {
+                let mut events = Vec::with_capacity(STORE_BATCH);
+                while let Ok(first) = rx.recv() {
+                    // Whatever is already queued rides along, the store gets it in one transaction
+                    for (mut flow, res) in std::iter::once(first).chain(rx.try_iter().take(STORE_BATCH - 1)) {
+                        let scored = res.bin.is_some();
+                        let is_attack = res.is_attack();
+                        let p_attack = res.bin.as_ref().and_then(|b| b.probs.get(1).copied()).unwrap_or(0.0);
+                        let attack_threshold = res.bin.as_ref().and_then(|b| b.threshold);
+                        let shadow_label = res.shadow.as_ref().and_then(|s| s.label()).map(str::to_string);
+                        let shadow_p_attack = res.shadow.as_ref().and_then(|s| s.bin.as_ref()).and_then(|b| b.probs.get(1).copied());
+
+                        // No class when none cleared its minimum, the flow is still an attack
+                        let (multi_class, multi_label, multi_probs, class_threshold) = if let Some(m) = res.multi {
+                            (m.pred_label, m.label, Some(m.probs), m.threshold)
+                        } else {
+                            (None, None, None, None)
+                        };
+
+                        let event = ClassifiedFlowEvent {
+                            key: flow.key.into(),
+                            start_us: flow.flow_start_time,
+                            end_us: flow.flow_last_time,
+                            duration_us: flow.flow_duration,
+                            total_packets: flow.total_packets,
+                            total_bytes: flow.total_bytes,
+                            scored,
+                            is_attack,
+                            p_attack,
+                            multi_class,
+                            multi_label,
+                            multi_probs,
+                            attack_threshold,
+                            class_threshold,
+                            shadow_label,
+                            shadow_p_attack,
+                            tcp_state: flow.tcp_termination(),
+                            icmp: flow.icmp.clone(),
+                        };
+
+                        if let Ok(mut guard) = exporter.lock() {
+                            if let Some(exporter) = guard.as_mut() {
+                                if let Err(e) = exporter.write_flow(&mut flow, event.label()) {
+                                    eprintln!("CSV export failed: {e}");
+                                    if let Some(msg) = export_errors.check(format!("CSV export failed: {e}")) {
+                                        sink.health(&HealthEvent::new(Component::Export, Severity::Error, msg));
+                                    }
+                                }
+                            }
+                        }
+                        events.push(event);
+                    }
+
+                    if let Ok(mut guard) = store.lock() {
+                        if let Some(store) = guard.as_mut() {
+                            if let Err(e) = store.insert_batch(&events) {
+                                eprintln!("Flow store insert of {} flows failed: {e}", events.len());
+                                if let Some(msg) = store_errors.check(format!("Flow store insert failed: {e}")) {
+                                    sink.health(&HealthEvent::new(Component::Store, Severity::Error, msg));
+                                }
+                            }
+                        }
+                    }
+
+                    for event in events.drain(..) {
+                        sink.flow_classified(&event);
+                    }
+                }
+            })
+        };
+
+        // From here on the classifier and forwarder threads run, a failed step has to stop them
+        let mut sniffer = PacketSniffer::new_with_sender(processor.get_sender(), processor.get_counters(), sink.clone());
+        let opened = match source {
+            PacketSource::Interface(name) => sniffer.init_sniffer(name, CAPTURE_FILTER),
+            PacketSource::Replay { path, mode } => sniffer.init_replay(path, CAPTURE_FILTER, *mode),
+        };
+        if let Err(e) = opened {
+            let _ = shut_down_classifier(classifier, forwarder);
+            return Err(e.to_string());
+        }
+
+        // The engine runs before the sniffer does, a replay blocks on full queues nobody would drain
+        let started = processor.start_processor(sink.clone(), &classifier)
+            .and_then(|()| sniffer.start_sniffer());
+        if let Err(e) = started {
+            // Engine workers that did start hold classifier senders, stopping them drops those
+            let _ = processor.stop_processor();
+            let _ = shut_down_classifier(classifier, forwarder);
+            return Err(e.to_string());
+        }
+
+        let watcher = (options.classifier.watch_models_secs > 0).then(|| {
+            let (stop_tx, stop_rx) = bounded::<()>(0);
+            let (models, thresholds, sink) = (classifier.models.clone(), classifier.thresholds.clone(), sink.clone());
+            let interval = Duration::from_secs(options.classifier.watch_models_secs);
+            let handle = std::thread::spawn(move || classifier::watch_models(models, thresholds, interval, stop_rx, sink));
+            (stop_tx, handle)
+        });
+
+        Ok(Self { sniffer, processor, classifier, forwarder, watcher })
+    }
+
+    /// Changes the decision thresholds of the running classifier, from its next batch on
+    pub fn set_thresholds(&self, thresholds: &Thresholds) -> Result<(), String> {
+        self.classifier.thresholds.set(thresholds)
+    }
+
+    /// Loads the model bundle again and swaps it in without stopping the capture, through a
+    /// handle that doesn't borrow the pipeline. Flows already queued are still scored by the
+    /// models they were queued under
+    pub fn model_reloader(&self) -> ModelReloader {
+        ModelReloader::new(self.classifier.models.clone(), self.classifier.thresholds.clone())
+    }
+
+    /// Agreement of the shadow models with the running ones so far, None without shadow models
+    pub fn shadow_report(&self) -> Option<ShadowReport> {
+        self.classifier.shadow.as_ref().map(|s| s.report())
+    }
+
+    /// True once a replay reached the end of its file or the capture failed
+    pub fn source_finished(&self) -> bool {
+        !self.sniffer.is_running()
+    }
+
+    /// Stops capturing and waits until every flow still in the table went through the
+    /// classifier and out to the sink. Returns how many in-flight flows were flushed
+    pub fn stop(mut self) -> Result<usize, String> {
+        if let Some((stop_tx, handle)) = self.watcher.take() {
+            drop(stop_tx);
+            let _ = handle.join();
+        }
+        self.sniffer.stop_sniffer().map_err(|e| format!("Error stopping sniffer: {}", e))?;
+        let flushed = self.processor.stop_processor().map_err(|e| format!("Error stopping processor: {}", e))?;
+
+        // The engine dropped its senders
+        shut_down_classifier(self.classifier, self.forwarder)?;
+        Ok(flushed)
+    }
+}
+
+/// Drops the last classifier sender so the workers finish the queue and exit, which closes
+/// the forwarder's channel in turn, then waits for all of them
+fn shut_down_classifier(classifier: ClassifierHandles, forwarder: JoinHandle<()>) -> Result<(), String> {
+    let ClassifierHandles { tx, rx, threads, .. } = classifier;
+    drop(tx);
+    drop(rx);
+    for t in threads {
+        t.join().map_err(|_| "Classifier thread panicked")?;
+    }
+    forwarder.join().map_err(|_| "Forwarding thread panicked")?;
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Wall-clock time without packets after which the clock starts moving on its own
+const IDLE_FALLBACK: Duration = Duration::from_secs(1);
+
+#[inline]
+pub fn wall_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+/// Time source for flow expiry, driven by packet timestamps.
+///
+/// While packets keep arriving "now" is the newest packet timestamp, so timeouts only
+/// depend on the capture itself (live, buffered or replayed). Once the input has been
+/// quiet for a while the wall-clock time elapsed since the last packet is added on top,
+/// so idle flows still expire.
+#[derive(Debug)]
+pub struct FlowClock {
+    last_packet_us: Option<u64>,
+    last_packet_at: Instant,
+}
+
+impl FlowClock {
+    pub fn new() -> Self {
+        Self { last_packet_us: None, last_packet_at: Instant::now() }
+    }
+
+    /// Advances the clock with a packet timestamp. Out of order packets never move it back
+    pub fn observe(&mut self, timestamp: u64) {
+        self.last_packet_us = Some(self.last_packet_us.map_or(timestamp, |t| t.max(timestamp)));
+        self.last_packet_at = Instant::now();
+    }
+
+    pub fn now(&self) -> u64 {
+        match self.last_packet_us {
+            // Nothing seen yet, wall-clock is all we have
+            None => wall_micros(),
+            Some(ts) => {
+                let idle = self.last_packet_at.elapsed();
+                if idle < IDLE_FALLBACK { ts } else { ts + idle.as_micros() as u64 }
+            }
+        }
+    }
+}
+
+impl Default for FlowClock {
+    fn default() -> Self { Self::new() }
+}
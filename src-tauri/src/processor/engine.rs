@@ -1,39 +1,43 @@
 use crossbeam_channel::{select, tick, Receiver, Sender};
-use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use std::collections::hash_map::Entry;
+use std::time::Duration;
 
 use crate::capture::ParsedPacket;
+use crate::classifier::FlowTx;
 use crate::types::NetworkStats;
-use super::flow::{FlowKey, FlowRecord , FlowDirection, FLOW_TIMEOUT_US};
+use super::clock::{wall_micros, FlowClock};
+use super::flow::FlowRecord;
+use super::table::FlowTable;
 
+// Flow time between two expiry sweeps
+const EXPIRE_INTERVAL_US: u64 = 1_000_000;
 
-#[inline]
-fn now_micros() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_micros() as u64)
-        .unwrap_or(0)
-}
-
-pub fn processing_loop(running: Arc<AtomicBool>, packet_rx: Receiver<ParsedPacket>, stats_tx: Sender<NetworkStats>, classifier_tx: Sender<FlowRecord>) {
-    let start_time = now_micros();
+/// One worker of the engine, owning every flow its `shard` is routed into `table`. Runs
+/// until `running` is cleared, then hands every flow still in the table to the classifier.
+/// Returns how many flows that final flush sent. Stats only cover this shard, the
+/// publisher merges them
+pub fn processing_loop(shard: usize, running: Arc<AtomicBool>, packet_rx: Receiver<ParsedPacket>, stats_tx: Sender<(usize, NetworkStats)>, classifier_tx: FlowTx, mut table: FlowTable) -> usize {
+    let start_time = wall_micros();
 
     // Timers to send expired flows to the classifier and stats to the frontend
     let expire_tick = tick(Duration::from_secs(1));
     let stats_tick  = tick(Duration::from_secs(1));
 
-    // We create the HashMap for the FlowRecords
-    let mut flows: HashMap<FlowKey, FlowRecord> = HashMap::new();
+    // Flow expiry follows packet time, stats rates follow wall-clock time
+    let mut clock = FlowClock::new();
+    let mut next_expire_us: Option<u64> = None;
 
-    let mut last_rate_us = now_micros();
+    // Reusable buffer for the flows each step ends
+    let mut ended: Vec<FlowRecord> = Vec::new();
+
+    let mut last_rate_us = wall_micros();
     let mut pkts_acc: u64 = 0;
     let mut bytes_acc: u64 = 0;
 
     let mut total_pkts: i64 = 0;
     let mut total_bytes: i64 = 0;
+    let mut icmp_pkts: i64 = 0;
 
     let mut suspicious_flows: i64 = 0;
 
@@ -45,99 +49,96 @@ pub fn processing_loop(running: Arc<AtomicBool>, packet_rx: Receiver<ParsedPacke
             // We get the packets from the sniffer
             recv(packet_rx) -> msg => {
                 if let Ok(pkt) = msg {
-                    // Create normalized key for HashMap lookup
-                    let normalized_key = FlowKey::new(pkt.flow_key.ip_a, pkt.flow_key.ip_b, pkt.flow_key.port_a, pkt.flow_key.port_b, pkt.flow_key.protocol);
-
-                    let flow = match flows.entry(normalized_key.clone()) {
-                        // If Key exist we get value and make it mutable
-                        Entry::Occupied(e) => e.into_mut(),
-                        // If it doesn't we compute direction
-                        Entry::Vacant(e) => {
-                            // For new flows, determine the direction of the FIRST packet
-                            let first_direction = if (pkt.flow_key.ip_a, pkt.flow_key.port_a) <= (pkt.flow_key.ip_b, pkt.flow_key.port_b) {
-                                FlowDirection::Forward
-                            } else {
-                                FlowDirection::Backward
-                                
-                            };    
-                           // And insert the flow
-                           let mut flow = FlowRecord::new(normalized_key, pkt.timestamp, first_direction);
-                           e.insert(flow)
-                        },
-                    };
-
-                    // Check for flow termination (FIN flag)
-                    let has_fin = pkt.tcp_flags & 0x01 != 0;
-                    
-                    // Update flow features
-                    flow.update_tcp_flow(
-                        pkt.timestamp,
-                        pkt.flow_key.ip_a, pkt.flow_key.ip_b, 
-                        pkt.flow_key.port_a, pkt.flow_key.port_b,
-                        pkt.flow_key.protocol,
-                        pkt.packet_len,
-                        Some(pkt.payload_len),
-                        pkt.tcp_flags,
-                        pkt.window_size,
-                        pkt.header_len,
-                    );
-
-                    // And send it to the classifier and remove it from the HashMap if should be removed
-                    if flow.should_terminate(pkt.timestamp, has_fin) {
-                        // TODO SEND TO CLASSIFIER
-                        // flow.finalize();
-                        let flow_copy = flow.clone();
-                        flows.remove(&normalized_key);
-                        let _ = classifier_tx.send(flow_copy);
+                    clock.observe(pkt.timestamp);
+
+                    table.process(&pkt, &mut ended);
+                    for flow in ended.drain(..) {
+                        let _ = classifier_tx.push(flow);
                     }
 
                     pkts_acc += 1;
                     total_pkts += 1;
                     bytes_acc += pkt.payload_len as u64;
                     total_bytes += pkt.payload_len as i64;
+                    if pkt.flow_key.is_icmp() { icmp_pkts += 1; }
+
+                    // Sweep as soon as packet time crosses the next boundary, so replays
+                    // expire flows at the same points no matter how fast they are read
+                    let now = clock.now();
+                    let due = *next_expire_us.get_or_insert(now + EXPIRE_INTERVAL_US);
+                    if now >= due {
+                        expire_flows(&mut table, now, &mut ended, &classifier_tx);
+                        next_expire_us = Some(now + EXPIRE_INTERVAL_US);
+                    }
                 }
             },
 
             recv(expire_tick) -> _ => {
-                let now = now_micros();
-                // Create a vector with the flows ready to be sent to the classifier
-                let mut flows_to_classify: Vec<FlowRecord> = Vec::new();
-                // Populate the vector
-                flows.retain(|key, flow| {
-                    let should_keep = (now - flow.last_seen_micros()) < FLOW_TIMEOUT_US;
-                    if !should_keep{
-                        // flow.finalize(); Compute final features
-                        flows_to_classify.push(flow.clone());
-                    }
-                    should_keep
-                });
-
-                // And send them to the classifier
-                for flow in flows_to_classify{
-                    let _ = classifier_tx.send(flow);
+                // Only does work once the input went idle and the clock fell back to wall time
+                let now = clock.now();
+                if next_expire_us.is_some_and(|due| now >= due) {
+                    expire_flows(&mut table, now, &mut ended, &classifier_tx);
+                    next_expire_us = Some(now + EXPIRE_INTERVAL_US);
                 }
             },
 
             recv(stats_tick) -> _ => {
-                let now = now_micros();
-                let dt = ((now - last_rate_us) as f64 / 1_000_000.0).max(1e-6);
+                let now = wall_micros();
+                // Wall time can step back (NTP), a negative interval would wrap
+                let dt = (now.saturating_sub(last_rate_us) as f64 / 1_000_000.0).max(1e-6);
+
+                // Active flows per transport protocol
+                let (mut tcp_flows, mut udp_flows, mut icmp_flows) = (0i64, 0i64, 0i64);
+                for key in table.keys() {
+                    match key.protocol {
+                        6 => tcp_flows += 1,
+                        17 => udp_flows += 1,
+                        1 | 58 => icmp_flows += 1,
+                        _ => {}
+                    }
+                }
 
                 let stats = NetworkStats {
-                    flow_count: flows.len() as i64,
+                    flow_count: table.len() as i64,
                     packets_per_second: (pkts_acc as f64) / dt,
                     bytes_per_second: (bytes_acc as f64) / dt,
                     total_packets: total_pkts,
                     total_bytes: total_bytes,
-                    uptime_seconds: ((now - start_time) / 1_000_000) as i64,
+                    uptime_seconds: (now.saturating_sub(start_time) / 1_000_000) as i64,
+                    tcp_flows,
+                    udp_flows,
+                    icmp_flows,
+                    icmp_packets: icmp_pkts,
+                    evicted_flows: table.evicted() as i64,
+                    ..NetworkStats::default()
                 };
 
-                let _ = stats_tx.send(stats);
+                let _ = stats_tx.try_send((shard, stats));
                 pkts_acc = 0;
                 bytes_acc = 0;
                 last_rate_us = now;
             },
         }
     }
-}
 
+    // The sniffer is already stopped, whatever it queued still belongs to the session
+    for pkt in packet_rx.try_iter() {
+        table.process(&pkt, &mut ended);
+    }
+    ended.extend(table.drain());
+
+    // Nothing may be dropped here, whatever the queue's policy
+    let flushed = ended.len();
+    for flow in ended.drain(..) {
+        let _ = classifier_tx.push_blocking(flow);
+    }
+    flushed
+}
 
+// Sends every flow idle for longer than its protocol's timeout, or past TIME_WAIT, to the classifier
+fn expire_flows(table: &mut FlowTable, now: u64, ended: &mut Vec<FlowRecord>, classifier_tx: &FlowTx) {
+    table.expire(now, ended);
+    for flow in ended.drain(..) {
+        let _ = classifier_tx.push(flow);
+    }
+}
@@ -1,77 +1,159 @@
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
 };
 use std::thread::{self, JoinHandle};
-use tauri::AppHandle;
 
-use crate::processor::FlowRecord;
-use crate::capture::ParsedPacket;
+use crate::classifier::ClassifierHandles;
+use crate::pipeline::EventSink;
+use crate::processor::{FlowConfig, FlowTable};
+use crate::capture::{CaptureCounters, ParsedPacket};
+use crate::queue::{self, QueueConfig};
 use crate::types::NetworkStats;
-use super::{engine, publisher};
+use super::publisher::{self, Gauges};
+use super::shard::PacketRouter;
+use super::table::{EvictionPolicy, FLOW_ENTRY_BYTES};
+use super::engine;
+
+const MAX_WORKERS: usize = 64;
+
+/// Threading and memory limits of the flow engine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineConfig {
+    pub workers: usize,         // Flow tables, each on its own thread. Flows are split between them by key
+    pub max_flows: usize,       // Active flows across all workers, 0 for no limit
+    pub max_memory_mb: usize,   // Same limit as an estimate of the tables' memory, 0 for no limit
+    pub eviction: EvictionPolicy,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            workers: 1,
+            max_flows: 500_000,     // About 600 MB of flow state
+            max_memory_mb: 0,
+            eviction: EvictionPolicy::Lru,
+        }
+    }
+}
+
+impl EngineConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_WORKERS).contains(&self.workers) {
+            return Err(format!("workers must be between 1 and {MAX_WORKERS}"));
+        }
+        Ok(())
+    }
+
+    /// Flows each worker may hold, the tighter of both limits split evenly
+    pub fn flows_per_worker(&self) -> Option<usize> {
+        let by_memory = (self.max_memory_mb > 0).then_some((self.max_memory_mb << 20) / FLOW_ENTRY_BYTES);
+        let by_count = (self.max_flows > 0).then_some(self.max_flows);
+        let total = match (by_count, by_memory) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => a.or(b)?,
+        };
+        Some(total.div_ceil(self.workers.max(1)))
+    }
+}
 
 pub struct FeatureProcessor {
     running: Arc<AtomicBool>,
-    processing_thread: Option<JoinHandle<()>>,
+    processing_threads: Vec<JoinHandle<usize>>,
     publisher_thread: Option<JoinHandle<()>>,
-    packet_tx: Sender<ParsedPacket>,
-    packet_rx: Receiver<ParsedPacket>,
-    stats_tx: Sender<NetworkStats>,
-    stats_rx: Receiver<NetworkStats>,
+    router: PacketRouter,
+    packet_rxs: Vec<Receiver<ParsedPacket>>,
+    stats_tx: Sender<(usize, NetworkStats)>,
+    stats_rx: Receiver<(usize, NetworkStats)>,
+    flow_config: Arc<FlowConfig>,
+    engine: EngineConfig,
+    counters: Arc<CaptureCounters>,
 }
 
 impl FeatureProcessor {
-    pub fn new() -> Self {
-        let (packet_tx, packet_rx) = unbounded();
-        let (stats_tx, stats_rx) = unbounded();
+    pub fn new(flow_config: FlowConfig, engine: &EngineConfig, packet_queue: &QueueConfig) -> Self {
+        let workers = engine.workers.max(1);
+        let (txs, packet_rxs): (Vec<_>, Vec<_>) = (0..workers)
+            .map(|i| {
+                let stage = if workers == 1 { "packets".to_string() } else { format!("packets.{i}") };
+                queue::queue(stage, packet_queue)
+            })
+            .unzip();
+        // One snapshot per worker and second, a stalled publisher just misses some
+        let (stats_tx, stats_rx) = bounded(8 * workers);
         Self {
             running: Arc::new(AtomicBool::new(false)),
-            processing_thread: None,
+            processing_threads: Vec::new(),
             publisher_thread: None,
-            packet_tx,
-            packet_rx,
+            router: PacketRouter::new(txs),
+            packet_rxs,
             stats_tx,
             stats_rx,
+            flow_config: Arc::new(flow_config),
+            engine: engine.clone(),
+            counters: Arc::new(CaptureCounters::default()),
         }
     }
 
-    pub fn get_sender(&self) -> Sender<ParsedPacket> { self.packet_tx.clone() }
+    pub fn get_sender(&self) -> PacketRouter { self.router.clone() }
 
-    pub fn start_processor(&mut self, app: AppHandle, classifier_tx: Sender<FlowRecord>) -> Result<(), Box<dyn Error>> {
+    // The sniffer counts what it drops here, the publisher reports it with the stats
+    pub fn get_counters(&self) -> Arc<CaptureCounters> { self.counters.clone() }
+
+    /// Finished flows go to `classifier`, whose queues and batches are also reported with the stats
+    pub fn start_processor(&mut self, sink: Arc<dyn EventSink>, classifier: &ClassifierHandles) -> Result<(), Box<dyn Error>> {
         if self.running.load(Ordering::Relaxed) {
             return Err("Processor is already running".into());
         }
         self.running.store(true, Ordering::Relaxed);
 
-        let processing = {
+        for (shard, rx) in self.packet_rxs.iter().enumerate() {
             let running = self.running.clone();
-            let rx = self.packet_rx.clone();
+            let rx = rx.clone();
             let stats_tx = self.stats_tx.clone();
-            thread::spawn(move || engine::processing_loop(running, rx, stats_tx, classifier_tx))
-        };
+            let classifier_tx = classifier.tx.clone();
+            let table = FlowTable::with_limit(self.flow_config.clone(), self.engine.flows_per_worker(), self.engine.eviction);
+            let handle = thread::Builder::new()
+                .name(format!("flow-engine-{shard}"))
+                .spawn(move || engine::processing_loop(shard, running, rx, stats_tx, classifier_tx, table))?;
+            self.processing_threads.push(handle);
+        }
 
         let publisher = {
             let running = self.running.clone();
             let stats_rx = self.stats_rx.clone();
-            let app = app.clone();
-            thread::spawn(move || publisher::publisher_loop(running, stats_rx, app))
+            let gauges = Gauges {
+                counters: self.counters.clone(),
+                packets: self.router.monitors(),
+                flows: classifier.tx.monitor(),
+                results: classifier.results.clone(),
+                inference: classifier.stats.clone(),
+                shadow: classifier.shadow.clone(),
+            };
+            let workers = self.packet_rxs.len();
+            thread::spawn(move || publisher::publisher_loop(running, stats_rx, sink, workers, gauges))
         };
 
-        self.processing_thread = Some(processing);
         self.publisher_thread = Some(publisher);
         Ok(())
     }
 
-    pub fn stop_processor(&mut self) -> Result<(), Box<dyn Error>> {
+    /// Stops the workers once they flushed their active flows to the classifier, returns how many they flushed
+    pub fn stop_processor(&mut self) -> Result<usize, Box<dyn Error>> {
         if !self.running.load(Ordering::Relaxed) {
             return Err("Processor isn't running".into());
         }
         self.running.store(false, Ordering::Relaxed);
 
-        if let Some(h) = self.processing_thread.take() { let _ = h.join(); }
+        let mut flushed = 0;
+        for h in self.processing_threads.drain(..) {
+            flushed += h.join().map_err(|_| "Processing thread panicked")?;
+        }
         if let Some(h) = self.publisher_thread.take() { let _ = h.join(); }
-        Ok(())
+        Ok(flushed)
     }
 }
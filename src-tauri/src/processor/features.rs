@@ -0,0 +1,124 @@
+use super::FlowRecord;
+
+pub type Feature = fn(&FlowRecord) -> f64;
+
+/// CICFlowMeter-4.0 feature columns in output order, each with its FlowRecord value.
+/// Models and the CSV export pick their columns from here by name
+pub const CIC_FEATURES: &[(&str, Feature)] = &[
+    ("Flow Duration", |f| f.flow_duration as f64),
+    ("Total Fwd Packet", |f| f.total_fwd_packets as f64),
+    ("Total Bwd packets", |f| f.total_bwd_packets as f64),
+    ("Total Length of Fwd Packet", |f| f.total_fwd_bytes as f64),
+    ("Total Length of Bwd Packet", |f| f.total_bwd_bytes as f64),
+    ("Fwd Packet Length Max", |f| f.fwd_packet_len_max as f64),
+    ("Fwd Packet Length Min", |f| f.fwd_packet_len_min as f64),
+    ("Fwd Packet Length Mean", |f| f.fwd_packet_len_mean),
+    ("Fwd Packet Length Std", |f| f.fwd_packet_len_std),
+    ("Bwd Packet Length Max", |f| f.bwd_packet_len_max as f64),
+    ("Bwd Packet Length Min", |f| f.bwd_packet_len_min as f64),
+    ("Bwd Packet Length Mean", |f| f.bwd_packet_len_mean),
+    ("Bwd Packet Length Std", |f| f.bwd_packet_len_std),
+    ("Flow Bytes/s", |f| f.flow_bytes_per_sec),
+    ("Flow Packets/s", |f| f.flow_packets_per_sec),
+    ("Flow IAT Mean", |f| f.flow_iat_mean),
+    ("Flow IAT Std", |f| f.flow_iat_std),
+    ("Flow IAT Max", |f| f.flow_iat_max as f64),
+    ("Flow IAT Min", |f| f.flow_iat_min as f64),
+    ("Fwd IAT Total", |f| f.fwd_iat_total as f64),
+    ("Fwd IAT Mean", |f| f.fwd_iat_mean),
+    ("Fwd IAT Std", |f| f.fwd_iat_std),
+    ("Fwd IAT Max", |f| f.fwd_iat_max as f64),
+    ("Fwd IAT Min", |f| f.fwd_iat_min as f64),
+    ("Bwd IAT Total", |f| f.bwd_iat_total as f64),
+    ("Bwd IAT Mean", |f| f.bwd_iat_mean),
+    ("Bwd IAT Std", |f| f.bwd_iat_std),
+    ("Bwd IAT Max", |f| f.bwd_iat_max as f64),
+    ("Bwd IAT Min", |f| f.bwd_iat_min as f64),
+    ("Fwd PSH Flags", |f| f.fwd_psh_flags as f64),
+    ("Bwd PSH Flags", |f| f.bwd_psh_flags as f64),
+    ("Fwd URG Flags", |f| f.fwd_urg_flags as f64),
+    ("Bwd URG Flags", |f| f.bwd_urg_flags as f64),
+    ("Fwd Header Length", |f| f.fwd_header_len as f64),
+    ("Bwd Header Length", |f| f.bwd_header_len as f64),
+    ("Fwd Packets/s", |f| f.fwd_packets_per_sec),
+    ("Bwd Packets/s", |f| f.bwd_packets_per_sec),
+    ("Packet Length Min", |f| f.packet_len_min as f64),
+    ("Packet Length Max", |f| f.packet_len_max as f64),
+    ("Packet Length Mean", |f| f.packet_len_mean),
+    ("Packet Length Std", |f| f.packet_len_std),
+    ("Packet Length Variance", |f| f.packet_len_variance),
+    ("FIN Flag Count", |f| f.fin_flag_count as f64),
+    ("SYN Flag Count", |f| f.syn_flag_count as f64),
+    ("RST Flag Count", |f| f.rst_flag_count as f64),
+    ("PSH Flag Count", |f| f.psh_flag_count as f64),
+    ("ACK Flag Count", |f| f.ack_flag_count as f64),
+    ("URG Flag Count", |f| f.urg_flag_count as f64),
+    ("CWR Flag Count", |f| f.cwr_flag_count as f64),
+    ("ECE Flag Count", |f| f.ece_flag_count as f64),
+    ("Down/Up Ratio", |f| f.down_up_ratio),
+    ("Average Packet Size", |f| f.avg_packet_size),
+    ("Fwd Segment Size Avg", |f| f.fwd_segment_size_avg),
+    ("Bwd Segment Size Avg", |f| f.bwd_segment_size_avg),
+    ("Fwd Bytes/Bulk Avg", |f| f.fwd_bytes_bulk_avg),
+    ("Fwd Packet/Bulk Avg", |f| f.fwd_packet_bulk_avg),
+    ("Fwd Bulk Rate Avg", |f| f.fwd_bulk_rate_avg),
+    ("Bwd Bytes/Bulk Avg", |f| f.bwd_bytes_bulk_avg),
+    ("Bwd Packet/Bulk Avg", |f| f.bwd_packet_bulk_avg),
+    ("Bwd Bulk Rate Avg", |f| f.bwd_bulk_rate_avg),
+    ("Subflow Fwd Packets", |f| f.subflow_fwd_packets() as f64),
+    ("Subflow Fwd Bytes", |f| f.subflow_fwd_bytes() as f64),
+    ("Subflow Bwd Packets", |f| f.subflow_bwd_packets() as f64),
+    ("Subflow Bwd Bytes", |f| f.subflow_bwd_bytes() as f64),
+    ("FWD Init Win Bytes", |f| f.fwd_init_win_bytes as f64),
+    ("Bwd Init Win Bytes", |f| f.bwd_init_win_bytes as f64),
+    ("Fwd Act Data Pkts", |f| f.fwd_act_data_packets as f64),
+    ("Fwd Seg Size Min", |f| f.fwd_seg_size_min),
+    ("Active Mean", |f| f.active_mean),
+    ("Active Std", |f| f.active_std),
+    ("Active Max", |f| f.active_max as f64),
+    ("Active Min", |f| f.active_min as f64),
+    ("Idle Mean", |f| f.idle_mean),
+    ("Idle Std", |f| f.idle_std),
+    ("Idle Max", |f| f.idle_max as f64),
+    ("Idle Min", |f| f.idle_min as f64),
+];
+
+pub fn feature(name: &str) -> Option<Feature> {
+    CIC_FEATURES.iter().find(|(n, _)| *n == name).map(|(_, get)| *get)
+}
+
+/// Ordered feature columns of a model, resolved once so extraction is a plain loop
+#[derive(Clone)]
+pub struct FeatureVector {
+    getters: Vec<Feature>,
+}
+
+impl FeatureVector {
+    /// Fails listing every name that isn't a CICFlowMeter feature
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self, String> {
+        let mut getters = Vec::with_capacity(names.len());
+        let mut unknown = Vec::new();
+        for name in names {
+            match feature(name.as_ref()) {
+                Some(get) => getters.push(get),
+                None => unknown.push(format!("\"{}\"", name.as_ref())),
+            }
+        }
+        if !unknown.is_empty() {
+            return Err(format!("Unknown features {}", unknown.join(", ")));
+        }
+        Ok(Self { getters })
+    }
+
+    pub fn len(&self) -> usize { self.getters.len() }
+
+    pub fn is_empty(&self) -> bool { self.getters.is_empty() }
+
+    /// Appends the flow's row, NaN and infinite rates become 0 as the models were trained
+    pub fn extend(&self, flow: &FlowRecord, out: &mut Vec<f32>) {
+        out.extend(self.getters.iter().map(|get| {
+            let v = get(flow) as f32;
+            if v.is_finite() { v } else { 0.0 }
+        }));
+    }
+}
@@ -1,14 +1,67 @@
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Arc;
 use std::time::SystemTime;
 
-pub const FLOW_TIMEOUT_US: u64 = 120_000_000; // 120 seconds
-const SUBFLOW_TIMEOUT_US: u64 = 1_000_000; // 1 second
-const ACTIVITY_TIMEOUT_US: u64 = 5_000_000; // 5 seconds
-const BULK_THRESHOLD: u32 = 4; // Minimum packets for bulk transfer
+use super::tcp_state::{FlowCloseState, TcpConnection, TcpTermination};
+
+/// Gap that ends a bulk run, fixed at 1 s in CICFlowMeter
+const BULK_IDLE_US: u64 = 1_000_000;
+
+/// Timeouts and thresholds of the flow meter. Defaults match CICFlowMeter, other
+/// training datasets may need other values. Every missing field takes its default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlowConfig {
+    pub flow_timeout_us: u64,           // Active timeout, max lifetime of a flow
+    pub tcp_idle_timeout_us: u64,       // Idle timeouts per protocol
+    pub udp_idle_timeout_us: u64,
+    pub icmp_idle_timeout_us: u64,
+    pub time_wait_us: u64,              // Grace period after a full TCP teardown
+    pub subflow_timeout_us: u64,
+    pub activity_timeout_us: u64,       // Gap that splits active and idle periods
+    pub bulk_threshold: u32,            // Minimum packets for bulk transfer
+}
+
+impl Default for FlowConfig {
+    fn default() -> Self {
+        Self {
+            flow_timeout_us: 120_000_000,       // 120 seconds
+            tcp_idle_timeout_us: 120_000_000,   // 120 seconds
+            udp_idle_timeout_us: 30_000_000,    // 30 seconds, UDP has no FIN to end a flow
+            icmp_idle_timeout_us: 30_000_000,   // 30 seconds, same as UDP
+            time_wait_us: 2_000_000,            // 2 seconds to absorb retransmitted FIN/ACKs
+            subflow_timeout_us: 1_000_000,      // 1 second
+            activity_timeout_us: 5_000_000,     // 5 seconds
+            bulk_threshold: 4,
+        }
+    }
+}
+
+impl FlowConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let timeouts = [
+            ("flow_timeout_us", self.flow_timeout_us),
+            ("tcp_idle_timeout_us", self.tcp_idle_timeout_us),
+            ("udp_idle_timeout_us", self.udp_idle_timeout_us),
+            ("icmp_idle_timeout_us", self.icmp_idle_timeout_us),
+            ("subflow_timeout_us", self.subflow_timeout_us),
+            ("activity_timeout_us", self.activity_timeout_us),
+        ];
+        if let Some((name, _)) = timeouts.iter().find(|(_, v)| *v == 0) {
+            return Err(format!("{name} must be greater than 0"));
+        }
+        if self.bulk_threshold < 2 {
+            return Err("bulk_threshold must be at least 2 packets".into());
+        }
+        Ok(())
+    }
+}
 
 #[derive(Debug,Clone,Hash,PartialEq,Eq,Copy)]
 pub struct FlowKey{
-    pub ip_a: u32,
-    pub ip_b: u32,
+    pub ip_a: IpAddr,
+    pub ip_b: IpAddr,
     pub port_a: u16,
     pub port_b: u16,
     pub protocol: u8,
@@ -16,8 +69,9 @@ pub struct FlowKey{
 
 
 impl FlowKey {
-    pub fn new(src_ip: u32, dst_ip: u32, src_port: u16, dst_port: u16, protocol: u8) -> Self {
+    pub fn new(src_ip: IpAddr, dst_ip: IpAddr, src_port: u16, dst_port: u16, protocol: u8) -> Self {
         // Normalize flow key so both directions map to the same entry
+        // (IpAddr orders v4 and v6 addresses numerically within each family)
         if (src_ip, src_port) <= (dst_ip, dst_port) {
             Self { 
                 ip_a: src_ip, 
@@ -36,27 +90,117 @@ impl FlowKey {
             }
         }
     }
+
+    /// ICMP pseudo-flow key. `port_a` holds `type << 8 | code` and `port_b` the identifier,
+    /// they are not swapped like ports because they describe the exchange, not an endpoint.
+    /// Replies are folded into their request type so both directions share one entry.
+    pub fn icmp(src_ip: IpAddr, dst_ip: IpAddr, icmp_type: u8, code: u8, identifier: u16, protocol: u8) -> Self {
+        let (ip_a, ip_b) = if src_ip <= dst_ip { (src_ip, dst_ip) } else { (dst_ip, src_ip) };
+        let query_type = icmp_request_type(protocol, icmp_type);
+        Self {
+            ip_a,
+            ip_b,
+            port_a: (query_type as u16) << 8 | code as u16,
+            port_b: identifier,
+            protocol,
+        }
+    }
+
+    #[inline]
+    pub fn is_icmp(&self) -> bool {
+        matches!(self.protocol, 1 | 58)
+    }
+}
+
+/// Maps ICMP/ICMPv6 replies to the request type they answer, other types are returned as is
+fn icmp_request_type(protocol: u8, icmp_type: u8) -> u8 {
+    match (protocol, icmp_type) {
+        (1, 0) => 8,        // Echo reply -> echo request
+        (1, 14) => 13,      // Timestamp reply -> timestamp
+        (1, 16) => 15,      // Information reply -> information request
+        (1, 18) => 17,      // Address mask reply -> address mask request
+        (58, 129) => 128,   // ICMPv6 echo reply -> echo request
+        _ => icmp_type,
+    }
+}
+
+/// True for ICMP query messages, the only ones carrying an identifier
+pub fn is_icmp_query(protocol: u8, icmp_type: u8) -> bool {
+    match protocol {
+        1 => matches!(icmp_type, 0 | 8 | 13 | 14 | 15 | 16 | 17 | 18),
+        58 => matches!(icmp_type, 128 | 129),
+        _ => false,
+    }
 }
 
-#[derive(Debug,Clone,Copy)]
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
 pub enum FlowDirection{ Forward, Backward }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FlowStatus { Active, Idle, Closed, Expired }
 
-#[derive(Debug, Clone)]
-pub enum FlowCloseState { NonClosing, FinCli, AckFinSv, AckCli }
+/// Per message type counters of an ICMP/ICMPv6 pseudo-flow
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IcmpCounters {
+    pub icmp_type: u8,                          // Request type the pseudo-flow is keyed by
+    pub icmp_code: u8,
+    pub identifier: u16,
+
+    pub echo_requests: u64,
+    pub echo_replies: u64,
+    pub dest_unreachable: u64,
+    pub time_exceeded: u64,
+    pub other: u64,
+
+    // Per second over the flow duration
+    pub echo_request_rate: f64,
+    pub echo_reply_rate: f64,
+    pub dest_unreachable_rate: f64,
+    pub time_exceeded_rate: f64,
+    pub other_rate: f64,
+}
+
+impl IcmpCounters {
+    fn from_key(key: &FlowKey) -> Self {
+        Self {
+            icmp_type: (key.port_a >> 8) as u8,
+            icmp_code: (key.port_a & 0xff) as u8,
+            identifier: key.port_b,
+            ..Default::default()
+        }
+    }
+
+    fn count(&mut self, protocol: u8, icmp_type: u8) {
+        match (protocol, icmp_type) {
+            (1, 8) | (58, 128) => self.echo_requests += 1,
+            (1, 0) | (58, 129) => self.echo_replies += 1,
+            (1, 3) | (58, 1) => self.dest_unreachable += 1,
+            (1, 11) | (58, 3) => self.time_exceeded += 1,
+            _ => self.other += 1,
+        }
+    }
+
+    fn update_rates(&mut self, duration_seconds: f64) {
+        self.echo_request_rate = self.echo_requests as f64 / duration_seconds;
+        self.echo_reply_rate = self.echo_replies as f64 / duration_seconds;
+        self.dest_unreachable_rate = self.dest_unreachable as f64 / duration_seconds;
+        self.time_exceeded_rate = self.time_exceeded as f64 / duration_seconds;
+        self.other_rate = self.other as f64 / duration_seconds;
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct FlowRecord {
     pub key: FlowKey,
+    pub config: Arc<FlowConfig>,
 
     // Flow state
     pub status: FlowStatus,                     // Done
-    pub close_state: FlowCloseState,
+    pub tcp: Option<TcpConnection>,             // TCP flows only
 
     // Helper attributes
-    pub first_packet_forward: bool,             // Done
+    pub initiator: (IpAddr, u16),               // Sender of the first packet, CICFlowMeter's forward side
     pub last_packet_timestamp: u64,             // Done
     pub last_fwd_packet_timestamp: u64,         // Done
     pub last_bwd_packet_timestamp: u64,         // Done
@@ -160,42 +304,37 @@ pub struct FlowRecord {
     pub bwd_segment_size_tot: f64,              // Done
     pub fwd_seg_size_min: f64,                  // Done
 
-    // FWD Bulk Features
-    pub num_fwd_bulk_transmissions: i32,        // Done
-    pub fwd_bulk_start: u64,                    // Done
-    pub fwd_bulk_end: u64,                      // Done
+    // FWD Bulk Features, totals over every bulk so far
+    pub num_fwd_bulk_transmissions: u64,        // Done
     pub fwd_bulk_duration: u64,                 // Done
-    pub fwd_bytes_curr_bulk: f64,               // Done
-    pub fwd_bytes_bulk_tot: f64,                // Done
-    pub fwd_packet_bulk_tot: f64,               // Done
+    pub fwd_bytes_bulk_tot: u64,                // Done
+    pub fwd_packet_bulk_tot: u64,               // Done
     pub fwd_bytes_bulk_avg: f64,                // Done
     pub fwd_packet_bulk_avg: f64,               // Done
     pub fwd_bulk_rate_avg: f64,                 // Done
 
-    // BWD Bulk Features
-    pub num_bwd_bulk_transmissions: i32,        // Done
-    pub bwd_bulk_start: u64,                    // Done
-    pub bwd_bulk_end: u64,                      // Done
+    // BWD Bulk Features, totals over every bulk so far
+    pub num_bwd_bulk_transmissions: u64,        // Done
     pub bwd_bulk_duration: u64,                 // Done
-    pub bwd_bytes_curr_bulk: f64,               // Done
-    pub bwd_bytes_bulk_tot: f64,                // Done
-    pub bwd_packet_bulk_tot: f64,               // Done
+    pub bwd_bytes_bulk_tot: u64,                // Done
+    pub bwd_packet_bulk_tot: u64,               // Done
     pub bwd_bytes_bulk_avg: f64,                // Done
     pub bwd_packet_bulk_avg: f64,               // Done
     pub bwd_bulk_rate_avg: f64,                 // Done
 
-    // Helpers to compute FWD and BWD bulks
-    pub fwd_consecutive_packets: u32,           // Done
-    pub bwd_consecutive_packets: u32,           // Done
-    pub last_bulk_direction: Option<FlowDirection>,
+    // Helpers to compute FWD and BWD bulks: the current run of data packets, a start of 0 means none
+    pub fwd_bulk_start: u64,                    // Done
+    pub fwd_bulk_last: u64,                     // Done
+    pub fwd_bulk_packets: u32,                  // Done
+    pub fwd_bulk_bytes: u64,                    // Done
+    pub bwd_bulk_start: u64,                    // Done
+    pub bwd_bulk_last: u64,                     // Done
+    pub bwd_bulk_packets: u32,                  // Done
+    pub bwd_bulk_bytes: u64,                    // Done
 
-    // Subflow features
-    pub total_fwd_subflows: i32,                // Done
-    pub subflow_fwd_packets: u32,               // Done
-    pub subflow_fwd_bytes: u32,                 // Done
-    pub total_bwd_subflows: i32,                // Done
-    pub subflow_bwd_packets: u32,               // Done
-    pub subflow_bwd_bytes: u32,                 // Done
+    // Subflow features, the Subflow columns are the flow totals divided by sf_count
+    pub sf_count: u64,                          // Done
+    pub sf_last_packet_timestamp: u64,          // Done
 
     // Window features
     pub fwd_init_win_bytes: u32,                // Done
@@ -210,10 +349,8 @@ pub struct FlowRecord {
     pub active_mean: f64,                       // Done
     pub active_std: f64,                        // Done
     pub active_time_m2: f64,                    // Done
-    pub last_activity_time: u64,                // Done
-    pub current_active_start: u64,              // Done              
-    pub current_idle_start: u64,                // Done
-    pub is_in_active_period: bool,              // Done
+    pub start_active_time: u64,                 // Done, first packet of the current active period
+    pub end_active_time: u64,                   // Done, last packet of the current active period
 
     pub idle_counts: u64,                       // Done
     pub idle_time_tot: u64,                     // Done
@@ -223,6 +360,9 @@ pub struct FlowRecord {
     pub idle_std: f64,                          // Done
     pub idle_time_m2: f64,                      // Done
 
+    // ICMP pseudo-flows only
+    pub icmp: Option<IcmpCounters>,
+
     // Classification results
     pub classification_time: SystemTime,
     pub classified: bool,
@@ -231,13 +371,14 @@ pub struct FlowRecord {
 }
 
 impl FlowRecord {
-    pub fn new(key: FlowKey, start_time: u64, first_packet_direction: FlowDirection) -> Self {
+    pub fn new(key: FlowKey, start_time: u64, initiator: (IpAddr, u16), config: Arc<FlowConfig>) -> Self {
         #![allow(unused_mut, unused_assignments)]
         let mut s = Self {
             key,
+            config,
             status: FlowStatus::Active,
-            first_packet_forward: matches!(first_packet_direction, FlowDirection::Forward),
-            close_state: FlowCloseState::NonClosing,
+            initiator,
+            tcp: (key.protocol == 6).then(|| TcpConnection::new(FlowDirection::Forward)),
             last_packet_timestamp: start_time,
             last_fwd_packet_timestamp: start_time,
             last_bwd_packet_timestamp: 0,
@@ -313,34 +454,29 @@ impl FlowRecord {
             bwd_segment_size_tot: 0.0,
             fwd_seg_size_min: f64::INFINITY,
             num_fwd_bulk_transmissions: 0,
-            fwd_bulk_start: 0,
-            fwd_bulk_end: 0,
             fwd_bulk_duration: 0,
-            fwd_bytes_curr_bulk: 0.0,
-            fwd_bytes_bulk_tot: 0.0,
-            fwd_packet_bulk_tot: 0.0,
+            fwd_bytes_bulk_tot: 0,
+            fwd_packet_bulk_tot: 0,
             fwd_bytes_bulk_avg: 0.0,
             fwd_packet_bulk_avg: 0.0,
             fwd_bulk_rate_avg: 0.0,
             num_bwd_bulk_transmissions: 0,
-            bwd_bulk_start: 0,
-            bwd_bulk_end: 0,
             bwd_bulk_duration: 0,
-            bwd_bytes_curr_bulk: 0.0,
-            bwd_bytes_bulk_tot: 0.0,
-            bwd_packet_bulk_tot: 0.0,
+            bwd_bytes_bulk_tot: 0,
+            bwd_packet_bulk_tot: 0,
             bwd_bytes_bulk_avg: 0.0,
             bwd_packet_bulk_avg: 0.0,
             bwd_bulk_rate_avg: 0.0,
-            fwd_consecutive_packets: 1,
-            bwd_consecutive_packets: 0,
-            last_bulk_direction: None,
-            total_fwd_subflows: 0,
-            subflow_fwd_packets: 0,
-            subflow_fwd_bytes: 0,
-            total_bwd_subflows: 0,
-            subflow_bwd_packets: 0,
-            subflow_bwd_bytes: 0,
+            fwd_bulk_start: 0,
+            fwd_bulk_last: 0,
+            fwd_bulk_packets: 0,
+            fwd_bulk_bytes: 0,
+            bwd_bulk_start: 0,
+            bwd_bulk_last: 0,
+            bwd_bulk_packets: 0,
+            bwd_bulk_bytes: 0,
+            sf_count: 0,
+            sf_last_packet_timestamp: start_time,
             fwd_init_win_bytes: 0,
             bwd_init_win_bytes: 0,
             fwd_act_data_packets: 0,
@@ -351,10 +487,8 @@ impl FlowRecord {
             active_max: 0,
             active_std: 0.0,
             active_time_m2: 0.0,
-            last_activity_time: start_time,
-            current_active_start: start_time,
-            current_idle_start: 0,
-            is_in_active_period: true,
+            start_active_time: start_time,
+            end_active_time: start_time,
             idle_counts: 0,
             idle_time_tot: 0,
             idle_min: u64::MAX,
@@ -362,6 +496,7 @@ impl FlowRecord {
             idle_max: 0,
             idle_std: 0.0,
             idle_time_m2: 0.0,
+            icmp: key.is_icmp().then(|| IcmpCounters::from_key(&key)),
             classification_time: SystemTime::now(),
             classified: false,
             benign: true,
@@ -378,61 +513,49 @@ impl FlowRecord {
     }
 
 
-    fn get_flow_direction(&self, src_ip: u32, dst_ip: u32, src_port: u16, dst_port: u16) -> FlowDirection {
-        // Compare packet's actual src/dst with normalized flow key
-        if self.key.ip_a == src_ip && self.key.ip_b == dst_ip && 
-           self.key.port_a == src_port && self.key.port_b == dst_port {
+    /// The other endpoint of the flow, CICFlowMeter's destination
+    pub fn responder(&self) -> (IpAddr, u16) {
+        if self.initiator == (self.key.ip_a, self.key.port_a) {
+            (self.key.ip_b, self.key.port_b)
+        } else {
+            (self.key.ip_a, self.key.port_a)
+        }
+    }
+
+    fn get_flow_direction(&self, src_ip: IpAddr, src_port: u16) -> FlowDirection {
+        // Packets from whoever sent the first one are forward, like CICFlowMeter
+        if (src_ip, src_port) == self.initiator {
             FlowDirection::Forward
         } else {
             FlowDirection::Backward
         }
     }
 
-    pub fn update_subflow_features(&mut self, timestamp: u64, direction: FlowDirection, payload_len: u32) {        
-        match direction {
-            FlowDirection::Forward => {
-                if self.last_fwd_packet_timestamp > 0 {
-                    let time_gap = timestamp.saturating_sub(self.last_fwd_packet_timestamp);
-                    if time_gap > SUBFLOW_TIMEOUT_US {
-                        // New subflow detected
-                        self.total_fwd_subflows += 1;
-                        // Reset subflow counters for new subflow
-                        self.subflow_fwd_packets = 1;
-                        self.subflow_fwd_bytes = payload_len;
-                    } else {
-                        // Continue current subflow
-                        self.subflow_fwd_packets += 1;
-                        self.subflow_fwd_bytes += payload_len;
-                    }
-                } else {
-                    // First forward packet
-                    self.total_fwd_subflows = 1;
-                    self.subflow_fwd_packets = 1;
-                    self.subflow_fwd_bytes = payload_len;
-                }
-            }
-            FlowDirection::Backward => {
-                if self.last_bwd_packet_timestamp > 0 {
-                    let time_gap = timestamp.saturating_sub(self.last_bwd_packet_timestamp);
-                    if time_gap > SUBFLOW_TIMEOUT_US {
-                        // New subflow detected
-                        self.total_bwd_subflows += 1;
-                        // Reset subflow counters for new subflow
-                        self.subflow_bwd_packets = 1;
-                        self.subflow_bwd_bytes = payload_len;
-                    } else {
-                        // Continue current subflow
-                        self.subflow_bwd_packets += 1;
-                        self.subflow_bwd_bytes += payload_len;
-                    }
-                } else {
-                    // First backward packet
-                    self.total_bwd_subflows = 1;
-                    self.subflow_bwd_packets = 1;
-                    self.subflow_bwd_bytes = payload_len;
-                }
-            }
+    /// CICFlowMeter's detectUpdateSubflows: every gap longer than the subflow timeout, in
+    /// either direction, opens one more subflow. The first packet doesn't count as one
+    fn update_subflows(&mut self, timestamp: u64) {
+        if timestamp.saturating_sub(self.sf_last_packet_timestamp) > self.config.subflow_timeout_us {
+            self.sf_count += 1;
         }
+        self.sf_last_packet_timestamp = timestamp;
+    }
+
+    // Subflow columns: flow totals over the subflow count with integer division, 0 while no
+    // gap opened a subflow, as CICFlowMeter's getSflow_* report them
+    pub fn subflow_fwd_packets(&self) -> u64 {
+        self.total_fwd_packets.checked_div(self.sf_count).unwrap_or(0)
+    }
+
+    pub fn subflow_fwd_bytes(&self) -> u64 {
+        self.total_fwd_bytes.checked_div(self.sf_count).unwrap_or(0)
+    }
+
+    pub fn subflow_bwd_packets(&self) -> u64 {
+        self.total_bwd_packets.checked_div(self.sf_count).unwrap_or(0)
+    }
+
+    pub fn subflow_bwd_bytes(&self) -> u64 {
+        self.total_bwd_bytes.checked_div(self.sf_count).unwrap_or(0)
     }
 
     fn update_packet_length_stats(&mut self, packet_len: u32, direction: FlowDirection) {
@@ -527,7 +650,7 @@ impl FlowRecord {
                         self.fwd_iat_total += fwd_iat;
                         
                         self.fwd_iat_min = self.fwd_iat_min.min(fwd_iat);
-                        self.fwd_iat_max = self.fwd_iat_max.min(fwd_iat);
+                        self.fwd_iat_max = self.fwd_iat_max.max(fwd_iat);
                         
                         let n_fwd = self.total_fwd_packets as f64;
                         if n_fwd > 1.0 {
@@ -548,8 +671,8 @@ impl FlowRecord {
                         let bwd_iat = timestamp.saturating_sub(self.last_bwd_packet_timestamp);
                         self.bwd_iat_total += bwd_iat;
                         
-                        self.bwd_iat_min = self.bwd_iat_min.min(flow_iat);
-                        self.bwd_iat_max = self.bwd_iat_max.min(flow_iat);
+                        self.bwd_iat_min = self.bwd_iat_min.min(bwd_iat);
+                        self.bwd_iat_max = self.bwd_iat_max.max(bwd_iat);
                         
                         let n_bwd = self.total_bwd_packets as f64;
                         if n_bwd > 1.0 {
@@ -577,35 +700,33 @@ impl FlowRecord {
         self.last_packet_timestamp = timestamp;
     }
 
-    /// Update Active/Idle time features according to CICFlowMeter rules
-    fn update_active_idle_stats(&mut self, timestamp: u64) {
-        if self.total_packets > 1 {
-            let time_since_last = timestamp.saturating_sub(self.last_activity_time);
-            
-            if time_since_last > ACTIVITY_TIMEOUT_US {
-                // Transition from active to idle
-                if self.is_in_active_period {
-                    let active_duration = self.last_activity_time.saturating_sub(self.current_active_start);
-                    self.update_active_time_stats(active_duration);
-                    self.current_idle_start = self.last_activity_time;
-                    self.is_in_active_period = false;
-                }
-                
-                // We're in idle period
-                let idle_duration = timestamp.saturating_sub(self.current_idle_start);
-                // Don't update idle stats yet - wait for next active period
-            } else {
-                // Still active or transition from idle to active
-                if !self.is_in_active_period {
-                    let idle_duration = timestamp - self.current_idle_start;
-                    self.update_idle_time_stats(idle_duration);
-                    self.current_active_start = timestamp;
-                    self.is_in_active_period = true;
-                }
+    /// CICFlowMeter's updateActiveIdleTime: a gap longer than the activity timeout ends the
+    /// active period, kept only if it lasted at all, and the gap itself is an idle period
+    fn update_active_idle_time(&mut self, current_time: u64) {
+        if current_time.saturating_sub(self.end_active_time) > self.config.activity_timeout_us {
+            let active = self.end_active_time.saturating_sub(self.start_active_time);
+            if active > 0 {
+                self.update_active_time_stats(active);
             }
+            self.update_idle_time_stats(current_time - self.end_active_time);
+            self.start_active_time = current_time;
+            self.end_active_time = current_time;
+        } else {
+            self.end_active_time = current_time;
+        }
+    }
+
+    /// CICFlowMeter's endActiveIdleTime: keeps the active period the flow ended in and,
+    /// unless FIN or RST ended it, counts what was left of the flow timeout as idle
+    fn end_active_idle_time(&mut self, is_flag_end: bool) {
+        let active = self.end_active_time.saturating_sub(self.start_active_time);
+        if active > 0 {
+            self.update_active_time_stats(active);
+        }
+        let lifetime = self.end_active_time.saturating_sub(self.flow_start_time);
+        if !is_flag_end && self.config.flow_timeout_us > lifetime {
+            self.update_idle_time_stats(self.config.flow_timeout_us - lifetime);
         }
-        
-        self.last_activity_time = timestamp;
     }
 
     fn update_active_time_stats(&mut self, duration: u64) {
@@ -644,93 +765,70 @@ impl FlowRecord {
         }
     }
 
-    /// Update bulk transfer features
-    fn update_bulk_features(&mut self, direction: FlowDirection, payload_len: u32) {
+    /// CICFlowMeter's updateForwardBulk/updateBackwardBulk. A bulk is a run of `bulk_threshold`
+    /// or more data packets in one direction with no gap over 1 s and no data packet from the
+    /// other side since it started. It is counted as soon as it reaches the threshold and every
+    /// later packet of the run is added to it.
+    fn update_bulk_features(&mut self, timestamp: u64, direction: FlowDirection, payload_len: u32) {
         match direction {
             FlowDirection::Forward => {
-                if matches!(self.last_bulk_direction, Some(FlowDirection::Forward)) {
-                    self.fwd_consecutive_packets += 1;
-                } else {
-                    // Direction changed or first packet
-                    if self.bwd_consecutive_packets >= BULK_THRESHOLD {
-                        self.finalize_bwd_bulk();
-                    }
-                    self.fwd_consecutive_packets = 1;
-                    self.fwd_bulk_start = self.last_packet_timestamp;
+                if self.bwd_bulk_last > self.fwd_bulk_start {
+                    self.fwd_bulk_start = 0;
                 }
-                
-                if payload_len > 0 {
-                    self.fwd_bytes_curr_bulk += payload_len as f64;
+                if payload_len == 0 {
+                    return;
                 }
-                
-                self.last_bulk_direction = Some(FlowDirection::Forward);
-            }
 
-            FlowDirection::Backward => {
-                if matches!(self.last_bulk_direction, Some(FlowDirection::Backward)) {
-                    self.bwd_consecutive_packets += 1;
+                if self.fwd_bulk_start == 0 || timestamp.saturating_sub(self.fwd_bulk_last) > BULK_IDLE_US {
+                    self.fwd_bulk_start = timestamp;
+                    self.fwd_bulk_packets = 1;
+                    self.fwd_bulk_bytes = payload_len as u64;
                 } else {
-                    // Direction changed or first packet
-                    if self.fwd_consecutive_packets >= BULK_THRESHOLD {
-                        self.finalize_fwd_bulk();
+                    self.fwd_bulk_packets += 1;
+                    self.fwd_bulk_bytes += payload_len as u64;
+                    if self.fwd_bulk_packets == self.config.bulk_threshold {
+                        self.num_fwd_bulk_transmissions += 1;
+                        self.fwd_packet_bulk_tot += self.fwd_bulk_packets as u64;
+                        self.fwd_bytes_bulk_tot += self.fwd_bulk_bytes;
+                        self.fwd_bulk_duration += timestamp - self.fwd_bulk_start;
+                    } else if self.fwd_bulk_packets > self.config.bulk_threshold {
+                        self.fwd_packet_bulk_tot += 1;
+                        self.fwd_bytes_bulk_tot += payload_len as u64;
+                        self.fwd_bulk_duration += timestamp - self.fwd_bulk_last;
                     }
-                    self.bwd_consecutive_packets = 1;
-                    self.bwd_bulk_start = self.last_packet_timestamp;
-                }
-                
-                if payload_len > 0 {
-                    self.bwd_bytes_curr_bulk += payload_len as f64;
                 }
-                
-                self.last_bulk_direction = Some(FlowDirection::Backward);
+                self.fwd_bulk_last = timestamp;
             }
-        }
-    }
 
-    fn finalize_fwd_bulk(&mut self) {
-        if self.fwd_consecutive_packets >= BULK_THRESHOLD {
-            self.num_fwd_bulk_transmissions += 1;
-            self.fwd_bytes_bulk_tot += self.fwd_bytes_curr_bulk;
-            self.fwd_packet_bulk_tot += self.fwd_consecutive_packets as f64;
-            
-            // Calculate averages
-            let n_bulk = self.num_fwd_bulk_transmissions as f64;
-            self.fwd_bytes_bulk_avg = self.fwd_bytes_bulk_tot / n_bulk;
-            self.fwd_packet_bulk_avg = self.fwd_packet_bulk_tot / n_bulk;
-            
-            // Calculate bulk rate if we have duration
-            self.fwd_bulk_end = self.last_packet_timestamp;
-            self.fwd_bulk_duration = self.fwd_bulk_end - self.fwd_bulk_start;
-            if self.fwd_bulk_duration > 0 {
-                self.fwd_bulk_rate_avg = self.fwd_bytes_curr_bulk / (self.fwd_bulk_duration as f64 / 1_000_000.0);
-            }
-        }
-        
-        self.fwd_bytes_curr_bulk = 0.0;
-        self.fwd_consecutive_packets = 0;
-    }
+            FlowDirection::Backward => {
+                if self.fwd_bulk_last > self.bwd_bulk_start {
+                    self.bwd_bulk_start = 0;
+                }
+                if payload_len == 0 {
+                    return;
+                }
 
-    fn finalize_bwd_bulk(&mut self) {
-        if self.bwd_consecutive_packets >= BULK_THRESHOLD {
-            self.num_bwd_bulk_transmissions += 1;
-            self.bwd_bytes_bulk_tot += self.bwd_bytes_curr_bulk;
-            self.bwd_packet_bulk_tot += self.bwd_consecutive_packets as f64;
-            
-            // Calculate averages
-            let n_bulk = self.num_bwd_bulk_transmissions as f64;
-            self.bwd_bytes_bulk_avg = self.bwd_bytes_bulk_tot / n_bulk;
-            self.bwd_packet_bulk_avg = self.bwd_packet_bulk_tot / n_bulk;
-            
-            // Calculate bulk rate if we have duration
-            self.bwd_bulk_end = self.last_packet_timestamp;
-            self.bwd_bulk_duration = self.bwd_bulk_end - self.bwd_bulk_start;
-            if self.bwd_bulk_duration > 0 {
-                self.bwd_bulk_rate_avg = self.bwd_bytes_curr_bulk / (self.bwd_bulk_duration as f64 / 1_000_000.0);
+                if self.bwd_bulk_start == 0 || timestamp.saturating_sub(self.bwd_bulk_last) > BULK_IDLE_US {
+                    self.bwd_bulk_start = timestamp;
+                    self.bwd_bulk_packets = 1;
+                    self.bwd_bulk_bytes = payload_len as u64;
+                } else {
+                    self.bwd_bulk_packets += 1;
+                    self.bwd_bulk_bytes += payload_len as u64;
+                    if self.bwd_bulk_packets == self.config.bulk_threshold {
+                        self.num_bwd_bulk_transmissions += 1;
+                        self.bwd_packet_bulk_tot += self.bwd_bulk_packets as u64;
+                        self.bwd_bytes_bulk_tot += self.bwd_bulk_bytes;
+                        self.bwd_bulk_duration += timestamp - self.bwd_bulk_start;
+                    } else if self.bwd_bulk_packets > self.config.bulk_threshold {
+                        self.bwd_packet_bulk_tot += 1;
+                        self.bwd_bytes_bulk_tot += payload_len as u64;
+                        self.bwd_bulk_duration += timestamp - self.bwd_bulk_last;
+                    }
+                }
+                self.bwd_bulk_last = timestamp;
             }
         }
-        
-        self.bwd_bytes_curr_bulk = 0.0;
-        self.bwd_consecutive_packets = 0;
     }
 
     /// Update TCP flags according to CICFlowMeter rules (count ALL packets, not just first)
@@ -758,30 +856,86 @@ impl FlowRecord {
         if tcp_flags & 0x80 != 0 { self.cwr_flag_count += 1; }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn update_tcp_flow(
         &mut self,
         timestamp: u64,
-        src_ip: u32,
-        dst_ip: u32,
+        src_ip: IpAddr,
         src_port: u16,
-        dst_port: u16,
-        protocol: u8,
-        packet_len: u32,
         payload_len: Option<u32>,
         tcp_flags: u8,
         window_size: u16,
+        seq: u32,
+        ack: u32,
         header_len: u32,
     ) {
-        let direction = self.get_flow_direction(src_ip, dst_ip, src_port, dst_port);
-        let payload_size = payload_len.unwrap_or(0);
-        
+        let direction = self.get_flow_direction(src_ip, src_port);
+        self.update_common_features(timestamp, direction, payload_len.unwrap_or(0), header_len);
+
+        // Initialize window size on the first packet of each direction
+        match direction {
+            FlowDirection::Forward if self.total_fwd_packets == 1 => self.fwd_init_win_bytes = window_size as u32,
+            FlowDirection::Backward if self.total_bwd_packets == 1 => self.bwd_init_win_bytes = window_size as u32,
+            _ => {}
+        }
+
+        // Update TCP flags
+        self.update_tcp_flags(tcp_flags, direction);
 
-        // Update subflow features
+        // Update connection state
         let payload_size = payload_len.unwrap_or(0);
-        self.update_subflow_features(timestamp, direction, payload_size);
+        if let Some(tcp) = self.tcp.as_mut() {
+            tcp.update(direction, tcp_flags, seq, ack, payload_size, timestamp);
+        }
+
+        // Calculate derived features
+        self.calculate_derived_features();
+    }
+
+    /// UDP has no flags nor window, everything else is shared with TCP
+    pub fn update_udp_flow(
+        &mut self,
+        timestamp: u64,
+        src_ip: IpAddr,
+        src_port: u16,
+        payload_len: Option<u32>,
+        header_len: u32,
+    ) {
+        let direction = self.get_flow_direction(src_ip, src_port);
+        self.update_common_features(timestamp, direction, payload_len.unwrap_or(0), header_len);
+
+        // Calculate derived features
+        self.calculate_derived_features();
+    }
+
+    /// ICMP has no ports, direction only depends on the sender's address
+    pub fn update_icmp_flow(
+        &mut self,
+        timestamp: u64,
+        src_ip: IpAddr,
+        payload_len: Option<u32>,
+        header_len: u32,
+        icmp_type: u8,
+    ) {
+        let direction = if src_ip == self.initiator.0 { FlowDirection::Forward } else { FlowDirection::Backward };
+        self.update_common_features(timestamp, direction, payload_len.unwrap_or(0), header_len);
 
-        // Update packet length statistics
-        self.update_packet_length_stats(packet_len, direction);
+        let protocol = self.key.protocol;
+        if let Some(icmp) = self.icmp.as_mut() {
+            icmp.count(protocol, icmp_type);
+        }
+
+        // Calculate derived features
+        self.calculate_derived_features();
+    }
+
+    /// Features every transport protocol shares
+    fn update_common_features(&mut self, timestamp: u64, direction: FlowDirection, payload_size: u32, header_len: u32) {
+        // Update subflow count
+        self.update_subflows(timestamp);
+
+        // Update packet length statistics, CICFlowMeter measures packets by their payload
+        self.update_packet_length_stats(payload_size, direction);
         
         // Update byte counters
         self.total_bytes += payload_size as u64;
@@ -789,29 +943,15 @@ impl FlowRecord {
             FlowDirection::Forward => {
                 self.total_fwd_bytes += payload_size as u64;
                 self.fwd_header_len += header_len;
-                
-                // Track minimum segment size for forward direction
+                // CICFlowMeter's min_seg_size_forward is the smallest forward header, not payload
+                self.fwd_seg_size_min = self.fwd_seg_size_min.min(header_len as f64);
                 if payload_size > 0 {
                     self.fwd_act_data_packets += 1;
-                    let seg_size = payload_size as f64;
-                    if seg_size < self.fwd_seg_size_min {
-                        self.fwd_seg_size_min = seg_size;
-                    }
-                }
-                
-                // Initialize window size on first packet
-                if self.total_fwd_packets == 1 {
-                    self.fwd_init_win_bytes = window_size as u32;
                 }
             }
             FlowDirection::Backward => {
                 self.total_bwd_bytes += payload_size as u64;
                 self.bwd_header_len += header_len;
-                
-                // Initialize window size on first backward packet
-                if self.total_bwd_packets == 1 {
-                    self.bwd_init_win_bytes = window_size as u32;
-                }
             }
         }
         
@@ -819,22 +959,16 @@ impl FlowRecord {
         self.update_iat_stats(timestamp, direction);
         
         // Update Active/Idle statistics
-        self.update_active_idle_stats(timestamp);
+        self.update_active_idle_time(timestamp);
         
         // Update bulk transfer features
-        self.update_bulk_features(direction, payload_size);
-        
-        // Update TCP flags
-        self.update_tcp_flags(tcp_flags, direction);
+        self.update_bulk_features(timestamp, direction, payload_size);
         
         // Update flow metadata
         self.flow_last_time = timestamp;
         self.flow_duration = self.flow_last_time.saturating_sub(self.flow_start_time);
         self.last_checked_time = timestamp;
         self.status = FlowStatus::Active;
-        
-        // Calculate derived features
-        self.calculate_derived_features();
     }
 
     /// Calculate derived features like rates and ratios
@@ -846,6 +980,9 @@ impl FlowRecord {
             self.flow_bytes_per_sec = (self.total_bytes as f64) / duration_seconds;
             self.fwd_packets_per_sec = (self.total_fwd_packets as f64) / duration_seconds;
             self.bwd_packets_per_sec = (self.total_bwd_packets as f64) / duration_seconds;
+            if let Some(icmp) = self.icmp.as_mut() {
+                icmp.update_rates(duration_seconds);
+            }
         }
         
         if self.total_packets > 0 {
@@ -860,20 +997,90 @@ impl FlowRecord {
             self.bwd_segment_size_avg = (self.total_bwd_bytes as f64) / (self.total_bwd_packets as f64);
         }
         
-        // Down/Up ratio (backward/forward)
-        if self.total_fwd_bytes > 0 {
-            self.down_up_ratio = (self.total_bwd_bytes as f64) / (self.total_fwd_bytes as f64);
+        // Bulk averages and rates, whole numbers like CICFlowMeter's
+        if let Some(bytes) = self.fwd_bytes_bulk_tot.checked_div(self.num_fwd_bulk_transmissions) {
+            self.fwd_bytes_bulk_avg = bytes as f64;
+            self.fwd_packet_bulk_avg = (self.fwd_packet_bulk_tot / self.num_fwd_bulk_transmissions) as f64;
+        }
+        if self.fwd_bulk_duration > 0 {
+            self.fwd_bulk_rate_avg = (self.fwd_bytes_bulk_tot as f64 / (self.fwd_bulk_duration as f64 / 1_000_000.0)).trunc();
+        }
+        if let Some(bytes) = self.bwd_bytes_bulk_tot.checked_div(self.num_bwd_bulk_transmissions) {
+            self.bwd_bytes_bulk_avg = bytes as f64;
+            self.bwd_packet_bulk_avg = (self.bwd_packet_bulk_tot / self.num_bwd_bulk_transmissions) as f64;
+        }
+        if self.bwd_bulk_duration > 0 {
+            self.bwd_bulk_rate_avg = (self.bwd_bytes_bulk_tot as f64 / (self.bwd_bulk_duration as f64 / 1_000_000.0)).trunc();
+        }
+
+        // Down/Up ratio (backward/forward packets, integer division like CICFlowMeter)
+        if let Some(ratio) = self.total_bwd_packets.checked_div(self.total_fwd_packets) {
+            self.down_up_ratio = ratio as f64;
+        }
+    }
+
+    /// Closes every open accumulator (active period, trailing idle time) and
+    /// computes the final derived features. Must run before a flow is classified or exported.
+    /// Closed when the TCP connection ended with a teardown or reset, Expired on timeouts.
+    /// Calling it again on a finalized flow does nothing.
+    pub fn finalize(&mut self) {
+        if matches!(self.status, FlowStatus::Closed | FlowStatus::Expired) {
+            return;
+        }
+
+        let closed_by_peer = self.tcp.as_ref().is_some_and(|tcp| {
+            matches!(tcp.close_state, FlowCloseState::TimeWait | FlowCloseState::Reset(_))
+        });
+
+        // Active/Idle: the period the last packet belongs to never saw its end
+        self.end_active_idle_time(closed_by_peer);
+
+        self.calculate_derived_features();
+
+        // Minimums without a single sample still hold their sentinel, CICFlowMeter reports 0
+        for min in [&mut self.fwd_packet_len_min, &mut self.bwd_packet_len_min, &mut self.packet_len_min] {
+            if *min == u32::MAX { *min = 0; }
+        }
+        for min in [&mut self.flow_iat_min, &mut self.fwd_iat_min, &mut self.bwd_iat_min, &mut self.active_min, &mut self.idle_min] {
+            if *min == u64::MAX { *min = 0; }
+        }
+        if self.fwd_seg_size_min.is_infinite() { self.fwd_seg_size_min = 0.0; }
+
+        self.status = if closed_by_peer { FlowStatus::Closed } else { FlowStatus::Expired };
+    }
+
+    /// How long the flow may stay without packets before the engine expires it
+    pub fn idle_timeout_us(&self) -> u64 {
+        match self.key.protocol {
+            17 => self.config.udp_idle_timeout_us,
+            1 | 58 => self.config.icmp_idle_timeout_us,
+            _ => self.config.tcp_idle_timeout_us,
         }
     }
 
-    /// Check if flow should be terminated according to CICFlowMeter rules
-    pub fn should_terminate(&self, current_time: u64, has_fin_flag: bool) -> bool {
-        // TCP flows: terminate on FIN flag OR timeout
-        if has_fin_flag {
+    /// Check if flow should be terminated right after a packet
+    pub fn should_terminate(&self, current_time: u64) -> bool {
+        // TCP flows: a RST ends the connection at once, FIN teardowns wait for TIME_WAIT
+        if self.tcp.as_ref().is_some_and(|tcp| tcp.is_reset()) {
             return true;
         }
         // Timeout check
-        (current_time - self.flow_start_time) > FLOW_TIMEOUT_US
+        current_time.saturating_sub(self.flow_start_time) > self.config.flow_timeout_us
+    }
+
+    /// Check if the expiry sweep should end the flow: idle for too long or done with TIME_WAIT
+    pub fn is_finished(&self, now: u64) -> bool {
+        now.saturating_sub(self.last_seen_micros()) >= self.idle_timeout_us()
+            || self.tcp.as_ref().is_some_and(|tcp| tcp.time_wait_over(now, self.config.time_wait_us))
+    }
+
+    /// A SYN arriving after the connection closed starts a new flow on the same key
+    pub fn is_reused_by(&self, tcp_flags: u8) -> bool {
+        self.tcp.as_ref().is_some_and(|tcp| tcp.is_reused_by(tcp_flags))
+    }
+
+    pub fn tcp_termination(&self) -> Option<TcpTermination> {
+        self.tcp.as_ref().map(|tcp| tcp.termination())
     }
 }
 
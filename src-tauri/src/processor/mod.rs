@@ -1,9 +1,19 @@
 pub mod feature_processor;
+mod clock;
 mod engine;
+mod features;
 mod publisher;
+mod shard;
 mod flow;
+mod table;
+mod tcp_state;
 
-pub use feature_processor::FeatureProcessor;
+pub use clock::FlowClock;
+pub use features::{feature, Feature, FeatureVector, CIC_FEATURES};
+pub use feature_processor::{EngineConfig, FeatureProcessor};
 pub use flow::{
-    FlowKey, FlowDirection, FlowStatus, FlowCloseState, FlowRecord
+    FlowKey, FlowDirection, FlowStatus, FlowRecord, FlowConfig, IcmpCounters, is_icmp_query
 };
+pub use shard::PacketRouter;
+pub use table::{EvictionPolicy, FlowTable, FLOW_ENTRY_BYTES};
+pub use tcp_state::{FlowCloseState, TcpConnection, TcpHandshake, TcpTermination};
@@ -2,30 +2,88 @@ use crossbeam_channel::{select, tick, Receiver};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
-use tauri::{AppHandle, Emitter};
-
+use crate::capture::CaptureCounters;
+use crate::classifier::{InferenceStats, ShadowStats};
+use crate::pipeline::EventSink;
+use crate::queue::QueueMonitor;
 use crate::types::NetworkStats;
 
+/// Figures shared by every engine worker, read once per emitted snapshot
+pub struct Gauges {
+    pub counters: Arc<CaptureCounters>,
+    pub packets: Vec<Arc<QueueMonitor>>,    // One per worker
+    pub flows: Arc<QueueMonitor>,
+    pub results: Arc<QueueMonitor>,
+    pub inference: Arc<InferenceStats>,
+    pub shadow: Option<Arc<ShadowStats>>,   // Reported on its own, less often than the stats
+}
+
+impl Gauges {
+    fn fill(&self, stats: &mut NetworkStats) {
+        let c = &self.counters;
+        stats.kernel_drops = CaptureCounters::get(&c.kernel_drops);
+        stats.interface_drops = CaptureCounters::get(&c.interface_drops);
+        stats.parse_errors_not_ip = CaptureCounters::get(&c.not_ip);
+        stats.parse_errors_unsupported_transport = CaptureCounters::get(&c.unsupported_transport);
+        stats.parse_errors_malformed = CaptureCounters::get(&c.malformed);
+        stats.channel_full_drops = self.packets.iter().map(|q| q.dropped() as i64).sum();
+        stats.classifier_queue_depth = self.flows.len() as i64;
+        stats.inference_batches = self.inference.batches() as i64;
+        stats.inference_last_batch_size = self.inference.last_batch_size() as i64;
+        stats.inference_last_batch_us = self.inference.last_batch_us() as i64;
+        stats.inference_avg_batch_us = self.inference.avg_batch_us();
+        stats.queues = self.packets.iter()
+            .chain([&self.flows, &self.results])
+            .map(|q| q.occupancy())
+            .collect();
+    }
+}
+
 pub fn publisher_loop(
     running: Arc<AtomicBool>,
-    stats_rx: Receiver<NetworkStats>,
-    app: AppHandle,
+    stats_rx: Receiver<(usize, NetworkStats)>,
+    sink: Arc<dyn EventSink>,
+    workers: usize,
+    gauges: Gauges,
 ) {
-    let emit_tick = tick(Duration::from_millis(250));
-    let mut latest: Option<NetworkStats> = None;
+    let shadow_tick = tick(Duration::from_secs(5));
+    // Latest snapshot of every worker, merged once all of them sent one for the round
+    let mut latest: Vec<Option<NetworkStats>> = vec![None; workers];
+    let mut fresh = vec![false; workers];
 
     loop {
         if !running.load(Ordering::Relaxed) { break; }
 
         select! {
             recv(stats_rx) -> msg => {
-                if let Ok(s) = msg { latest = Some(s); }
+                let Ok((shard, s)) = msg else { continue };
+                if shard >= workers { continue; }
+                // A worker reporting twice means another one's snapshot got lost, don't wait for it
+                if fresh[shard] {
+                    emit(&latest, &gauges, sink.as_ref());
+                    fresh.fill(false);
+                }
+                latest[shard] = Some(s);
+                fresh[shard] = true;
+                if fresh.iter().all(|f| *f) {
+                    emit(&latest, &gauges, sink.as_ref());
+                    fresh.fill(false);
+                }
             }
-            recv(emit_tick) -> _ => {
-                if let Some(ref s) = latest {
-                    let _ = app.emit("network-stats", s);
+            recv(shadow_tick) -> _ => {
+                if let Some(shadow) = &gauges.shadow {
+                    sink.shadow_stats(&shadow.report());
                 }
             }
         }
     }
 }
+
+fn emit(latest: &[Option<NetworkStats>], gauges: &Gauges, sink: &dyn EventSink) {
+    let mut merged = NetworkStats::default();
+    for s in latest.iter().flatten() {
+        merged.add_shard(s);
+    }
+    gauges.fill(&mut merged);
+    sink.network_stats(&merged);
+}
@@ -0,0 +1,44 @@
+use crossbeam_channel::SendError;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use crate::capture::ParsedPacket;
+use crate::queue::{QueueMonitor, QueueTx};
+use super::flow::FlowKey;
+
+/// Sends every packet to the engine worker that owns its flow. The key is normalized, so
+/// both directions land on the same worker and a flow's packets keep their order
+#[derive(Clone)]
+pub struct PacketRouter {
+    shards: Vec<QueueTx<ParsedPacket>>,
+}
+
+impl PacketRouter {
+    pub fn new(shards: Vec<QueueTx<ParsedPacket>>) -> Self {
+        assert!(!shards.is_empty(), "PacketRouter needs at least one shard");
+        Self { shards }
+    }
+
+    #[inline]
+    pub fn shard_of(&self, key: &FlowKey) -> usize {
+        if self.shards.len() == 1 {
+            return 0;
+        }
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    pub fn push(&self, pkt: ParsedPacket) -> Result<(), SendError<ParsedPacket>> {
+        self.shards[self.shard_of(&pkt.flow_key)].push(pkt)
+    }
+
+    pub fn push_blocking(&self, pkt: ParsedPacket) -> Result<(), SendError<ParsedPacket>> {
+        self.shards[self.shard_of(&pkt.flow_key)].push_blocking(pkt)
+    }
+
+    pub fn monitors(&self) -> Vec<Arc<QueueMonitor>> {
+        self.shards.iter().map(|s| s.monitor()).collect()
+    }
+}
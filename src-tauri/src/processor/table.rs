@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::Arc;
+
+use crate::capture::{ParsedPacket, TransportInfo};
+use super::flow::{FlowConfig, FlowKey, FlowRecord};
+
+// Approximate memory of one table entry, the map keeps about 1/8 of its slots free
+pub const FLOW_ENTRY_BYTES: usize = size_of::<(FlowKey, FlowRecord)>() * 8 / 7;
+
+// Share of a full table evicted at once, so the scan for victims is paid rarely
+const EVICT_BATCH_DIVISOR: usize = 64;
+
+/// Which flows go first once the table is full
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionPolicy {
+    Lru,            // Least recently seen packet
+    OldestFirst,    // Earliest first packet
+}
+
+impl EvictionPolicy {
+    #[inline]
+    fn age_of(&self, flow: &FlowRecord) -> u64 {
+        match self {
+            EvictionPolicy::Lru => flow.last_packet_timestamp,
+            EvictionPolicy::OldestFirst => flow.flow_start_time,
+        }
+    }
+}
+
+/// Active flows keyed by their normalized key. The engine thread owns one, the
+/// conformance tests drive one directly from a pcap.
+///
+/// Every flow handed out by the table has already been finalized.
+pub struct FlowTable {
+    flows: HashMap<FlowKey, FlowRecord>,
+    config: Arc<FlowConfig>,
+    max_flows: Option<usize>,
+    eviction: EvictionPolicy,
+    evicted: u64,
+}
+
+impl FlowTable {
+    pub fn new(config: Arc<FlowConfig>) -> Self {
+        Self::with_limit(config, None, EvictionPolicy::Lru)
+    }
+
+    /// A table that never holds more than `max_flows`. Past it, new flows make room by
+    /// ending the flows `eviction` picks early, which are handed out like any ended flow
+    pub fn with_limit(config: Arc<FlowConfig>, max_flows: Option<usize>, eviction: EvictionPolicy) -> Self {
+        Self { flows: HashMap::new(), config, max_flows: max_flows.map(|m| m.max(1)), eviction, evicted: 0 }
+    }
+
+    /// Adds a packet to its flow. Flows ended by it (4-tuple reuse, RST, active timeout) are pushed to `ended`
+    pub fn process(&mut self, pkt: &ParsedPacket, ended: &mut Vec<FlowRecord>) {
+        let key = pkt.flow_key;
+
+        // A SYN on a connection that already closed reuses the 4-tuple, end the old flow first
+        if let TransportInfo::Tcp { flags, .. } = pkt.transport {
+            if self.flows.get(&key).is_some_and(|f| f.is_reused_by(flags)) {
+                if let Some(mut old) = self.flows.remove(&key) {
+                    old.finalize();
+                    ended.push(old);
+                }
+            }
+        }
+
+        if self.max_flows.is_some_and(|max| self.flows.len() >= max) && !self.flows.contains_key(&key) {
+            self.evict(ended);
+        }
+
+        let flow = match self.flows.entry(key) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
+                // The first packet's sender is the forward side for the rest of the flow
+                e.insert(FlowRecord::new(key, pkt.timestamp, (pkt.src_ip, pkt.src_port), self.config.clone()))
+            }
+        };
+
+        // Update flow features
+        match pkt.transport {
+            TransportInfo::Tcp { flags, window_size, seq, ack } => flow.update_tcp_flow(
+                pkt.timestamp,
+                pkt.src_ip, pkt.src_port,
+                Some(pkt.payload_len),
+                flags,
+                window_size,
+                seq,
+                ack,
+                pkt.header_len,
+            ),
+            TransportInfo::Udp => flow.update_udp_flow(
+                pkt.timestamp,
+                pkt.src_ip, pkt.src_port,
+                Some(pkt.payload_len),
+                pkt.header_len,
+            ),
+            TransportInfo::Icmp { icmp_type, .. } => flow.update_icmp_flow(
+                pkt.timestamp,
+                pkt.src_ip,
+                Some(pkt.payload_len),
+                pkt.header_len,
+                icmp_type,
+            ),
+        }
+
+        // RST or active timeout, FIN teardowns are ended by the sweep after TIME_WAIT
+        if flow.should_terminate(pkt.timestamp) {
+            if let Some(mut done) = self.flows.remove(&key) {
+                done.finalize();
+                ended.push(done);
+            }
+        }
+    }
+
+    /// Ends every flow idle for longer than its protocol's timeout, or past TIME_WAIT
+    pub fn expire(&mut self, now: u64, ended: &mut Vec<FlowRecord>) {
+        let finished: Vec<FlowKey> = self.flows.iter()
+            .filter(|(_, flow)| flow.is_finished(now))
+            .map(|(key, _)| *key)
+            .collect();
+
+        for key in finished {
+            if let Some(mut flow) = self.flows.remove(&key) {
+                flow.finalize();
+                ended.push(flow);
+            }
+        }
+    }
+
+    // Ends the batch of flows the policy ranks first to make room for new ones
+    fn evict(&mut self, ended: &mut Vec<FlowRecord>) {
+        let Some(max) = self.max_flows else { return };
+        let n = (max / EVICT_BATCH_DIVISOR).max(1);
+
+        let mut victims: Vec<(u64, FlowKey)> = self.flows.iter()
+            .map(|(key, flow)| (self.eviction.age_of(flow), *key))
+            .collect();
+        if n < victims.len() {
+            victims.select_nth_unstable_by_key(n - 1, |(age, _)| *age);
+            victims.truncate(n);
+        }
+
+        for (_, key) in victims {
+            if let Some(mut flow) = self.flows.remove(&key) {
+                flow.finalize();
+                ended.push(flow);
+                self.evicted += 1;
+            }
+        }
+    }
+
+    /// Ends every flow still in the table, used when the input is over
+    pub fn drain(&mut self) -> Vec<FlowRecord> {
+        self.flows
+            .drain()
+            .map(|(_, mut flow)| {
+                flow.finalize();
+                flow
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize { self.flows.len() }
+
+    pub fn is_empty(&self) -> bool { self.flows.is_empty() }
+
+    pub fn keys(&self) -> impl Iterator<Item = &FlowKey> { self.flows.keys() }
+
+    // Flows ended early because the table was full
+    pub fn evicted(&self) -> u64 { self.evicted }
+}
@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+
+use super::flow::FlowDirection;
+
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const ACK: u8 = 0x10;
+
+/// Progress of the three way handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpHandshake {
+    NotSeen,        // First packet wasn't a SYN, connection picked up mid-stream
+    SynSent,
+    SynReceived,    // SYN-ACK seen, waiting for the client ACK
+    Established,
+}
+
+/// Teardown progress. Client is the side that sent the SYN (or the first packet when no SYN was seen)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowCloseState {
+    NonClosing,             // No FIN or RST yet
+    FinCli,                 // Client half-closed, server may still send data
+    FinSv,                  // Server half-closed, client may still send data
+    FinBoth,                // Both FINs sent, waiting for the last ACK
+    TimeWait,               // Both FINs acknowledged, grace period for retransmissions
+    Reset(FlowDirection),   // RST sent by the given side
+}
+
+/// How the connection ended, reported with the classified flow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TcpTermination {
+    Open,                   // No teardown seen, the flow ended by timeout
+    HandshakeIncomplete,    // SYN sent but the connection never got established
+    HalfClosed,             // Only one side (or no ACK for the last FIN) finished
+    GracefulClose,
+    ResetByClient,
+    ResetByServer,
+}
+
+#[derive(Debug, Clone)]
+pub struct TcpConnection {
+    pub handshake: TcpHandshake,
+    pub close_state: FlowCloseState,
+    pub client: FlowDirection,
+    fin_next_seq: [Option<u32>; 2],     // Sequence number acknowledging each side's FIN
+    fin_acked: [bool; 2],
+    pub closed_at: Option<u64>,         // When TIME_WAIT started or the RST was seen
+}
+
+#[inline]
+fn side(direction: FlowDirection) -> usize {
+    match direction {
+        FlowDirection::Forward => 0,
+        FlowDirection::Backward => 1,
+    }
+}
+
+// Sequence numbers wrap, compare them in serial number arithmetic
+#[inline]
+fn seq_ge(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) >= 0
+}
+
+impl TcpConnection {
+    pub fn new(first_direction: FlowDirection) -> Self {
+        Self {
+            handshake: TcpHandshake::NotSeen,
+            close_state: FlowCloseState::NonClosing,
+            client: first_direction,
+            fin_next_seq: [None, None],
+            fin_acked: [false, false],
+            closed_at: None,
+        }
+    }
+
+    pub fn update(&mut self, direction: FlowDirection, flags: u8, seq: u32, ack: u32, payload_len: u32, timestamp: u64) {
+        // Nothing changes after a reset, trailing packets only feed the statistics
+        if matches!(self.close_state, FlowCloseState::Reset(_)) {
+            return;
+        }
+        if flags & RST != 0 {
+            self.close_state = FlowCloseState::Reset(direction);
+            self.closed_at = Some(timestamp);
+            return;
+        }
+
+        let from_client = side(direction) == side(self.client);
+
+        // Handshake
+        match self.handshake {
+            TcpHandshake::NotSeen if flags & SYN != 0 && flags & ACK == 0 => {
+                self.handshake = TcpHandshake::SynSent;
+                self.client = direction;
+            }
+            TcpHandshake::SynSent if flags & SYN != 0 && flags & ACK != 0 && !from_client => {
+                self.handshake = TcpHandshake::SynReceived;
+            }
+            TcpHandshake::SynReceived if flags & SYN == 0 && flags & ACK != 0 && from_client => {
+                self.handshake = TcpHandshake::Established;
+            }
+            _ => {}
+        }
+
+        // A FIN takes one sequence number after the segment's payload
+        let this = side(direction);
+        if flags & FIN != 0 && self.fin_next_seq[this].is_none() {
+            self.fin_next_seq[this] = Some(seq.wrapping_add(payload_len).wrapping_add(1));
+        }
+        if flags & ACK != 0 {
+            let other = 1 - this;
+            if let Some(fin_seq) = self.fin_next_seq[other] {
+                if seq_ge(ack, fin_seq) {
+                    self.fin_acked[other] = true;
+                }
+            }
+        }
+
+        let client = side(self.client);
+        self.close_state = match (self.fin_next_seq[client].is_some(), self.fin_next_seq[1 - client].is_some()) {
+            (false, false) => FlowCloseState::NonClosing,
+            (true, false) => FlowCloseState::FinCli,
+            (false, true) => FlowCloseState::FinSv,
+            (true, true) if self.fin_acked[0] && self.fin_acked[1] => FlowCloseState::TimeWait,
+            (true, true) => FlowCloseState::FinBoth,
+        };
+        if self.close_state == FlowCloseState::TimeWait && self.closed_at.is_none() {
+            self.closed_at = Some(timestamp);
+        }
+    }
+
+    #[inline]
+    pub fn is_reset(&self) -> bool {
+        matches!(self.close_state, FlowCloseState::Reset(_))
+    }
+
+    /// True once the connection is fully closed and the TIME_WAIT grace period is over
+    pub fn time_wait_over(&self, now: u64, time_wait_us: u64) -> bool {
+        self.close_state == FlowCloseState::TimeWait
+            && self.closed_at.is_some_and(|t| now.saturating_sub(t) >= time_wait_us)
+    }
+
+    /// A new SYN on a connection that already ended means the 4-tuple got reused
+    pub fn is_reused_by(&self, flags: u8) -> bool {
+        flags & SYN != 0 && flags & ACK == 0
+            && matches!(self.close_state, FlowCloseState::TimeWait | FlowCloseState::Reset(_))
+    }
+
+    pub fn termination(&self) -> TcpTermination {
+        match self.close_state {
+            FlowCloseState::Reset(direction) if side(direction) == side(self.client) => TcpTermination::ResetByClient,
+            FlowCloseState::Reset(_) => TcpTermination::ResetByServer,
+            FlowCloseState::TimeWait => TcpTermination::GracefulClose,
+            FlowCloseState::FinCli | FlowCloseState::FinSv | FlowCloseState::FinBoth => TcpTermination::HalfClosed,
+            FlowCloseState::NonClosing => match self.handshake {
+                TcpHandshake::SynSent | TcpHandshake::SynReceived => TcpTermination::HandshakeIncomplete,
+                _ => TcpTermination::Open,
+            },
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use crossbeam_channel::{bounded, Receiver, SendError, Sender, TrySendError};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// What a full queue does with the next item
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum OverloadPolicy {
+    DropNewest,
+    DropOldest,
+    // Past half capacity only one item in `keep_one_in` is queued, a full queue drops
+    Sample { keep_one_in: u32 },
+    // The producer waits, pushing the pressure to the stage before it
+    Block,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueConfig {
+    pub capacity: usize,
+    #[serde(flatten)]
+    pub overload: OverloadPolicy,
+}
+
+impl QueueConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.capacity == 0 {
+            return Err("capacity must be greater than 0".into());
+        }
+        if let OverloadPolicy::Sample { keep_one_in: 0 } = self.overload {
+            return Err("keep_one_in must be at least 1".into());
+        }
+        Ok(())
+    }
+}
+
+/// One queue per pipeline stage: sniffer -> engine -> classifier -> forwarder
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueuesConfig {
+    pub packets: QueueConfig,   // Parsed packets waiting for the flow engine, one queue per worker
+    pub flows: QueueConfig,     // Finished flows waiting for inference
+    pub results: QueueConfig,   // Classified flows waiting for export and the UI
+}
+
+impl Default for QueuesConfig {
+    fn default() -> Self {
+        Self {
+            packets: QueueConfig { capacity: 65_536, overload: OverloadPolicy::DropNewest },
+            flows: QueueConfig { capacity: 16_384, overload: OverloadPolicy::Block },
+            results: QueueConfig { capacity: 16_384, overload: OverloadPolicy::Block },
+        }
+    }
+}
+
+impl QueuesConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        self.packets.validate().map_err(|e| format!("packets queue: {e}"))?;
+        self.flows.validate().map_err(|e| format!("flows queue: {e}"))?;
+        self.results.validate().map_err(|e| format!("results queue: {e}"))
+    }
+}
+
+/// Snapshot of a queue for the stats
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueOccupancy {
+    pub stage: String,
+    pub len: u64,
+    pub capacity: u64,
+    pub high_watermark: u64,    // Longest the queue has been this session
+    pub dropped: u64,           // Items the overload policy discarded
+}
+
+/// Shared counters of one queue, readable from any thread
+pub struct QueueMonitor {
+    stage: String,
+    capacity: usize,
+    len: Box<dyn Fn() -> usize + Send + Sync>,
+    high_watermark: AtomicUsize,
+    dropped: AtomicU64,
+    offered: AtomicU64,         // Sample policy position
+}
+
+impl QueueMonitor {
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        (self.len)()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn occupancy(&self) -> QueueOccupancy {
+        QueueOccupancy {
+            stage: self.stage.clone(),
+            len: self.len() as u64,
+            capacity: self.capacity as u64,
+            high_watermark: self.high_watermark.load(Ordering::Relaxed) as u64,
+            dropped: self.dropped(),
+        }
+    }
+
+    #[inline]
+    fn record_len(&self, len: usize) {
+        self.high_watermark.fetch_max(len, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn drop_one(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Sending half of a bounded stage queue that applies its overload policy on push
+pub struct QueueTx<T> {
+    tx: Sender<T>,
+    // Only needed for DropOldest
+    oldest: Option<Receiver<T>>,
+    overload: OverloadPolicy,
+    monitor: Arc<QueueMonitor>,
+}
+
+impl<T> Clone for QueueTx<T> {
+    fn clone(&self) -> Self {
+        Self { tx: self.tx.clone(), oldest: self.oldest.clone(), overload: self.overload, monitor: self.monitor.clone() }
+    }
+}
+
+pub fn queue<T: Send + 'static>(stage: impl Into<String>, config: &QueueConfig) -> (QueueTx<T>, Receiver<T>) {
+    let (tx, rx) = bounded::<T>(config.capacity);
+    let len_rx = rx.clone();
+    let monitor = Arc::new(QueueMonitor {
+        stage: stage.into(),
+        capacity: config.capacity,
+        len: Box::new(move || len_rx.len()),
+        high_watermark: AtomicUsize::new(0),
+        dropped: AtomicU64::new(0),
+        offered: AtomicU64::new(0),
+    });
+    let oldest = matches!(config.overload, OverloadPolicy::DropOldest).then(|| rx.clone());
+    (QueueTx { tx, oldest, overload: config.overload, monitor }, rx)
+}
+
+impl<T> QueueTx<T> {
+    /// Queues the item or discards it as the policy says
+    pub fn push(&self, item: T) -> Result<(), SendError<T>> {
+        let item = match self.overload {
+            OverloadPolicy::Block => return self.push_blocking(item),
+            OverloadPolicy::Sample { keep_one_in } if self.tx.len() * 2 >= self.monitor.capacity => {
+                let n = self.monitor.offered.fetch_add(1, Ordering::Relaxed);
+                if !n.is_multiple_of(keep_one_in.max(1) as u64) {
+                    self.monitor.drop_one();
+                    return Ok(());
+                }
+                item
+            }
+            _ => item,
+        };
+
+        match self.tx.try_send(item) {
+            Ok(()) => {}
+            Err(TrySendError::Disconnected(item)) => return Err(SendError(item)),
+            Err(TrySendError::Full(item)) => match &self.oldest {
+                // Make room by discarding the head, another producer may refill it first
+                Some(rx) => {
+                    let _ = rx.try_recv();
+                    self.monitor.drop_one();
+                    match self.tx.try_send(item) {
+                        Ok(()) => {}
+                        Err(TrySendError::Full(_)) => self.monitor.drop_one(),
+                        Err(TrySendError::Disconnected(item)) => return Err(SendError(item)),
+                    }
+                }
+                None => self.monitor.drop_one(),
+            },
+        }
+        self.monitor.record_len(self.tx.len());
+        Ok(())
+    }
+
+    /// Waits for room whatever the policy, for producers that must not lose items (replays, shutdown flush)
+    pub fn push_blocking(&self, item: T) -> Result<(), SendError<T>> {
+        self.tx.send(item)?;
+        self.monitor.record_len(self.tx.len());
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.tx.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tx.is_empty()
+    }
+
+    pub fn monitor(&self) -> Arc<QueueMonitor> {
+        self.monitor.clone()
+    }
+}
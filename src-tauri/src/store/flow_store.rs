@@ -0,0 +1,381 @@
+use rusqlite::{params, params_from_iter, types::Value, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::types::{ClassifiedFlowEvent, FlowKeyDTO};
+
+pub const STORE_FILE_NAME: &str = "flows.db";
+
+// Retention is enforced on open and then once PRUNE_EVERY rows were inserted since
+const PRUNE_EVERY: u32 = 1000;
+const DEFAULT_PAGE: u64 = 100;
+const MAX_PAGE: u64 = 1000;
+const MAX_AGE_DAYS: u64 = 36_500;
+const DAY_US: u64 = 86_400 * 1_000_000;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS flows (
+    id              INTEGER PRIMARY KEY AUTOINCREMENT,
+    stored_at_us    INTEGER NOT NULL,
+    start_us        INTEGER NOT NULL,
+    end_us          INTEGER NOT NULL,
+    duration_us     INTEGER NOT NULL,
+    ip_a            TEXT NOT NULL,
+    ip_b            TEXT NOT NULL,
+    port_a          INTEGER NOT NULL,
+    port_b          INTEGER NOT NULL,
+    protocol        INTEGER NOT NULL,
+    ip_version      INTEGER NOT NULL,
+    total_packets   INTEGER NOT NULL,
+    total_bytes     INTEGER NOT NULL,
+    scored          INTEGER NOT NULL,
+    is_attack       INTEGER NOT NULL,
+    p_attack        REAL NOT NULL,
+    label           TEXT,
+    multi_class     INTEGER,
+    multi_label     TEXT,
+    multi_probs     TEXT,
+    tcp_state       TEXT,
+    icmp            TEXT,
+    attack_threshold REAL,
+    class_threshold REAL,
+    shadow_label    TEXT,
+    shadow_p_attack REAL
+);
+CREATE INDEX IF NOT EXISTS flows_end_us ON flows(end_us);
+CREATE INDEX IF NOT EXISTS flows_stored_at ON flows(stored_at_us);
+CREATE INDEX IF NOT EXISTS flows_ip_a ON flows(ip_a);
+CREATE INDEX IF NOT EXISTS flows_ip_b ON flows(ip_b);
+CREATE INDEX IF NOT EXISTS flows_label ON flows(label);
+CREATE INDEX IF NOT EXISTS flows_p_attack ON flows(p_attack);
+";
+
+const COLUMNS: &str = "id, start_us, end_us, duration_us, ip_a, ip_b, port_a, port_b, protocol, ip_version, \
+    total_packets, total_bytes, scored, is_attack, p_attack, label, multi_class, multi_label, multi_probs, tcp_state, icmp, attack_threshold, class_threshold, \
+    shadow_label, shadow_p_attack";
+
+// Columns added after the first release, created on open in older databases
+const ADDED_COLUMNS: [(&str, &str); 4] = [
+    ("attack_threshold", "REAL"), ("class_threshold", "REAL"), ("shadow_label", "TEXT"), ("shadow_p_attack", "REAL"),
+];
+
+/// Where the classified flows are kept and for how long
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StoreConfig {
+    pub enabled: bool,
+    pub path: Option<String>,   // Defaults to flows.db in the app data dir (desktop) or the working dir (daemon)
+    pub max_age_days: u64,      // Age counts from when the flow was stored, 0 keeps everything
+    pub max_rows: u64,          // Oldest rows go first, 0 for no limit
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self { enabled: true, path: None, max_age_days: 7, max_rows: 1_000_000 }
+    }
+}
+
+impl StoreConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_age_days > MAX_AGE_DAYS {
+            return Err(format!("max_age_days must be at most {MAX_AGE_DAYS}, use 0 to keep everything"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowSort {
+    #[default]
+    EndTime,
+    StartTime,
+    Duration,
+    PAttack,
+    Packets,
+    Bytes,
+}
+
+impl FlowSort {
+    fn column(self) -> &'static str {
+        match self {
+            FlowSort::EndTime => "end_us",
+            FlowSort::StartTime => "start_us",
+            FlowSort::Duration => "duration_us",
+            FlowSort::PAttack => "p_attack",
+            FlowSort::Packets => "total_packets",
+            FlowSort::Bytes => "total_bytes",
+        }
+    }
+}
+
+/// Filters of `query_flows`, every field is optional
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FlowQuery {
+    pub from_us: Option<u64>,       // Flows still alive at or after this time
+    pub to_us: Option<u64>,         // Flows that started at or before this time
+    pub ip: Option<String>,         // Either endpoint
+    pub port: Option<u16>,          // Either endpoint
+    pub protocol: Option<u8>,
+    pub label: Option<String>,      // BENIGN, an attack class or Attack
+    pub attacks_only: bool,
+    pub min_p_attack: Option<f32>,
+    pub max_p_attack: Option<f32>,
+    pub sort: FlowSort,
+    pub descending: bool,
+    pub offset: u64,
+    pub limit: u64,                 // Capped at MAX_PAGE
+}
+
+impl Default for FlowQuery {
+    fn default() -> Self {
+        Self {
+            from_us: None,
+            to_us: None,
+            ip: None,
+            port: None,
+            protocol: None,
+            label: None,
+            attacks_only: false,
+            min_p_attack: None,
+            max_p_attack: None,
+            sort: FlowSort::EndTime,
+            descending: true,
+            offset: 0,
+            limit: DEFAULT_PAGE,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StoredFlow {
+    pub id: i64,
+    #[serde(flatten)]
+    pub flow: ClassifiedFlowEvent,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FlowPage {
+    pub total: u64,                 // Rows matching the filters, ignoring offset/limit
+    pub flows: Vec<StoredFlow>,
+}
+
+/// SQLite history of the classified flows
+pub struct FlowStore {
+    conn: Connection,
+    config: StoreConfig,
+    since_prune: u32,
+}
+
+fn now_us() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as i64).unwrap_or(0)
+}
+
+fn to_json<T: Serialize>(value: &Option<T>) -> Option<String> {
+    value.as_ref().and_then(|v| serde_json::to_string(v).ok())
+}
+
+fn from_json<T: for<'de> Deserialize<'de>>(text: Option<String>) -> Option<T> {
+    text.and_then(|t| serde_json::from_str(&t).ok())
+}
+
+fn read_flow(row: &Row) -> rusqlite::Result<StoredFlow> {
+    let flow = ClassifiedFlowEvent {
+        key: FlowKeyDTO {
+            ip_a: row.get("ip_a")?,
+            ip_b: row.get("ip_b")?,
+            port_a: row.get("port_a")?,
+            port_b: row.get("port_b")?,
+            protocol: row.get("protocol")?,
+            ip_version: row.get("ip_version")?,
+        },
+        start_us: row.get::<_, i64>("start_us")? as u64,
+        end_us: row.get::<_, i64>("end_us")? as u64,
+        duration_us: row.get::<_, i64>("duration_us")? as u64,
+        total_packets: row.get::<_, i64>("total_packets")? as u64,
+        total_bytes: row.get::<_, i64>("total_bytes")? as u64,
+        scored: row.get("scored")?,
+        is_attack: row.get("is_attack")?,
+        p_attack: row.get::<_, f64>("p_attack")? as f32,
+        multi_class: row.get("multi_class")?,
+        multi_label: row.get("multi_label")?,
+        multi_probs: from_json(row.get("multi_probs")?),
+        attack_threshold: row.get::<_, Option<f64>>("attack_threshold")?.map(|t| t as f32),
+        class_threshold: row.get::<_, Option<f64>>("class_threshold")?.map(|t| t as f32),
+        shadow_label: row.get("shadow_label")?,
+        shadow_p_attack: row.get::<_, Option<f64>>("shadow_p_attack")?.map(|p| p as f32),
+        tcp_state: from_json(row.get("tcp_state")?),
+        icmp: from_json(row.get("icmp")?),
+    };
+    Ok(StoredFlow { id: row.get("id")?, flow })
+}
+
+impl FlowStore {
+    pub fn open<P: AsRef<Path>>(path: P, config: StoreConfig) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        // WAL keeps inserts cheap. Queries share this connection, so they still wait for an insert in progress
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.execute_batch(SCHEMA)?;
+        for (column, kind) in ADDED_COLUMNS {
+            let exists: bool = conn.query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('flows') WHERE name = ?1", [column], |r| r.get(0),
+            )?;
+            if !exists {
+                conn.execute_batch(&format!("ALTER TABLE flows ADD COLUMN {column} {kind}"))?;
+            }
+        }
+
+        let mut store = Self { conn, config, since_prune: 0 };
+        store.prune()?;
+        Ok(store)
+    }
+
+    pub fn insert(&mut self, event: &ClassifiedFlowEvent) -> rusqlite::Result<()> {
+        self.insert_batch(std::slice::from_ref(event))
+    }
+
+    /// Stores the events in one transaction, all of them or none. One commit per batch
+    /// instead of per row is what keeps up with a busy capture
+    pub fn insert_batch(&mut self, events: &[ClassifiedFlowEvent]) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO flows (stored_at_us, start_us, end_us, duration_us, ip_a, ip_b, port_a, port_b, protocol, ip_version, \
+                 total_packets, total_bytes, scored, is_attack, p_attack, label, multi_class, multi_label, multi_probs, tcp_state, icmp, \
+                 attack_threshold, class_threshold, shadow_label, shadow_p_attack) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)",
+            )?;
+            let stored_at = now_us();
+            for event in events {
+                let k = &event.key;
+                stmt.execute(params![
+                    stored_at,
+                    event.start_us as i64,
+                    event.end_us as i64,
+                    event.duration_us as i64,
+                    k.ip_a,
+                    k.ip_b,
+                    k.port_a,
+                    k.port_b,
+                    k.protocol,
+                    k.ip_version,
+                    event.total_packets as i64,
+                    event.total_bytes as i64,
+                    event.scored,
+                    event.is_attack,
+                    event.p_attack as f64,
+                    event.label(),
+                    event.multi_class,
+                    event.multi_label,
+                    to_json(&event.multi_probs),
+                    to_json(&event.tcp_state),
+                    to_json(&event.icmp),
+                    event.attack_threshold.map(|t| t as f64),
+                    event.class_threshold.map(|t| t as f64),
+                    event.shadow_label,
+                    event.shadow_p_attack.map(|p| p as f64),
+                ])?;
+            }
+        }
+        tx.commit()?;
+
+        self.since_prune = self.since_prune.saturating_add(u32::try_from(events.len()).unwrap_or(u32::MAX));
+        if self.since_prune >= PRUNE_EVERY {
+            self.prune()?;
+        }
+        Ok(())
+    }
+
+    /// Applies the retention limits, returns how many rows were deleted
+    pub fn prune(&mut self) -> rusqlite::Result<usize> {
+        self.since_prune = 0;
+        let mut deleted = 0;
+
+        if self.config.max_age_days > 0 {
+            let max_age = self.config.max_age_days.saturating_mul(DAY_US).min(i64::MAX as u64) as i64;
+            let cutoff = now_us().saturating_sub(max_age);
+            deleted += self.conn.execute("DELETE FROM flows WHERE stored_at_us < ?1", [cutoff])?;
+        }
+        if self.config.max_rows > 0 {
+            let newest_dropped: Option<i64> = self.conn
+                .query_row("SELECT id FROM flows ORDER BY id DESC LIMIT 1 OFFSET ?1", [self.config.max_rows as i64], |r| r.get(0))
+                .optional()?;
+            if let Some(id) = newest_dropped {
+                deleted += self.conn.execute("DELETE FROM flows WHERE id <= ?1", [id])?;
+            }
+        }
+        Ok(deleted)
+    }
+
+    pub fn query(&self, q: &FlowQuery) -> rusqlite::Result<FlowPage> {
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some(from) = q.from_us {
+            conditions.push("end_us >= ?");
+            values.push(Value::Integer(from as i64));
+        }
+        if let Some(to) = q.to_us {
+            conditions.push("start_us <= ?");
+            values.push(Value::Integer(to as i64));
+        }
+        if let Some(ip) = &q.ip {
+            // Same text form as the stored key, so "::0001" finds "::1"
+            let ip = ip.trim().parse::<IpAddr>().map(|a| a.to_string()).unwrap_or_else(|_| ip.trim().to_string());
+            conditions.push("(ip_a = ? OR ip_b = ?)");
+            values.push(Value::Text(ip.clone()));
+            values.push(Value::Text(ip));
+        }
+        if let Some(port) = q.port {
+            conditions.push("(port_a = ? OR port_b = ?)");
+            values.push(Value::Integer(port as i64));
+            values.push(Value::Integer(port as i64));
+        }
+        if let Some(protocol) = q.protocol {
+            conditions.push("protocol = ?");
+            values.push(Value::Integer(protocol as i64));
+        }
+        if let Some(label) = &q.label {
+            conditions.push("label = ?");
+            values.push(Value::Text(label.clone()));
+        }
+        if q.attacks_only {
+            conditions.push("is_attack = 1");
+        }
+        if let Some(min) = q.min_p_attack {
+            conditions.push("p_attack >= ?");
+            values.push(Value::Real(min as f64));
+        }
+        if let Some(max) = q.max_p_attack {
+            conditions.push("p_attack <= ?");
+            values.push(Value::Real(max as f64));
+        }
+
+        let filter = if conditions.is_empty() { String::new() } else { format!(" WHERE {}", conditions.join(" AND ")) };
+
+        let total: i64 = self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM flows{filter}"),
+            params_from_iter(values.iter()),
+            |r| r.get(0),
+        )?;
+
+        let direction = if q.descending { "DESC" } else { "ASC" };
+        let sql = format!(
+            "SELECT {COLUMNS} FROM flows{filter} ORDER BY {col} {direction}, id {direction} LIMIT ? OFFSET ?",
+            col = q.sort.column(),
+        );
+        values.push(Value::Integer(q.limit.clamp(1, MAX_PAGE) as i64));
+        values.push(Value::Integer(q.offset.min(i64::MAX as u64) as i64));
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let flows = stmt
+            .query_map(params_from_iter(values.iter()), read_flow)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(FlowPage { total: total as u64, flows })
+    }
+}
@@ -0,0 +1,11 @@
+pub mod flow_store;
+
+pub use flow_store::{
+    FlowPage,
+    FlowQuery,
+    FlowSort,
+    FlowStore,
+    StoreConfig,
+    StoredFlow,
+    STORE_FILE_NAME,
+};
@@ -1,4 +1,8 @@
 use serde::{Serialize, Deserialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::processor::{FlowKey, IcmpCounters, TcpTermination};
+use crate::queue::QueueOccupancy;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct NetworkStats {
@@ -9,7 +13,47 @@ pub struct NetworkStats {
     pub total_packets: i64,
     pub total_bytes: i64,
     pub uptime_seconds: i64,
+    // Active flows per protocol, ICMP counts pseudo-flows
+    pub tcp_flows: i64,
+    pub udp_flows: i64,
+    pub icmp_flows: i64,
+    pub icmp_packets: i64,
+    // Flows ended early because the flow table was full, a sign of floods and scans
+    pub evicted_flows: i64,
+    // Packets lost before the engine, totals since the session started
+    pub kernel_drops: i64,
+    pub interface_drops: i64,
+    pub parse_errors_not_ip: i64,
+    pub parse_errors_unsupported_transport: i64,
+    pub parse_errors_malformed: i64,
+    pub channel_full_drops: i64,
+    // Finished flows waiting for inference
+    pub classifier_queue_depth: i64,
+    // Model runs, each scoring a batch of flows
+    pub inference_batches: i64,
+    pub inference_last_batch_size: i64,
+    pub inference_last_batch_us: i64,
+    pub inference_avg_batch_us: f64,
+    // Every stage queue: packets, flows, results
+    pub queues: Vec<QueueOccupancy>,
+}
 
+impl NetworkStats {
+    /// Adds one engine worker's flow and traffic figures. Drop counters and queues are
+    /// shared by all workers and filled in once
+    pub fn add_shard(&mut self, shard: &NetworkStats) {
+        self.flow_count += shard.flow_count;
+        self.packets_per_second += shard.packets_per_second;
+        self.bytes_per_second += shard.bytes_per_second;
+        self.total_packets += shard.total_packets;
+        self.total_bytes += shard.total_bytes;
+        self.uptime_seconds = self.uptime_seconds.max(shard.uptime_seconds);
+        self.tcp_flows += shard.tcp_flows;
+        self.udp_flows += shard.udp_flows;
+        self.icmp_flows += shard.icmp_flows;
+        self.icmp_packets += shard.icmp_packets;
+        self.evicted_flows += shard.evicted_flows;
+    }
 }
 
 impl Default for NetworkStats {
@@ -21,6 +65,100 @@ impl Default for NetworkStats {
             total_packets: 0,
             total_bytes: 0,
             uptime_seconds: 0,
+            tcp_flows: 0,
+            udp_flows: 0,
+            icmp_flows: 0,
+            icmp_packets: 0,
+            evicted_flows: 0,
+            kernel_drops: 0,
+            interface_drops: 0,
+            parse_errors_not_ip: 0,
+            parse_errors_unsupported_transport: 0,
+            parse_errors_malformed: 0,
+            channel_full_drops: 0,
+            classifier_queue_depth: 0,
+            inference_batches: 0,
+            inference_last_batch_size: 0,
+            inference_last_batch_us: 0,
+            inference_avg_batch_us: 0.0,
+            queues: Vec::new(),
         }
     }
-}
\ No newline at end of file
+}
+
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FlowKeyDTO {
+    pub ip_a: String, pub ip_b: String, pub port_a: u16, pub port_b: u16, pub protocol: u8, pub ip_version: u8,
+}
+
+impl From<FlowKey> for FlowKeyDTO {
+    fn from(k: FlowKey) -> Self {
+        let ip_version = if k.ip_a.is_ipv6() { 6 } else { 4 };
+        Self { ip_a: k.ip_a.to_string(), ip_b: k.ip_b.to_string(), port_a: k.port_a, port_b: k.port_b, protocol: k.protocol, ip_version }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ClassifiedFlowEvent {
+    pub key: FlowKeyDTO,
+    pub start_us: u64,
+    pub end_us: u64,
+    pub duration_us: u64,
+    pub total_packets: u64,
+    pub total_bytes: u64,
+    // Binario (scored es false para los pseudo-flujos ICMP)
+    pub scored: bool,
+    pub is_attack: bool,
+    pub p_attack: f32,
+    // Multiclase (solo si is_attack)
+    pub multi_class: Option<u8>,
+    pub multi_label: Option<String>,
+    pub multi_probs: Option<Vec<f32>>,
+    // Thresholds that produced the verdict: P(attack) minimum and the predicted class's own, if set
+    pub attack_threshold: Option<f32>,
+    pub class_threshold: Option<f32>,
+    // Challenger models in shadow mode, recorded only: alerts follow the fields above
+    pub shadow_label: Option<String>,
+    pub shadow_p_attack: Option<f32>,
+    // Solo TCP: como terminó la conexión
+    pub tcp_state: Option<TcpTermination>,
+    // Solo ICMP
+    pub icmp: Option<IcmpCounters>,
+}
+
+impl ClassifiedFlowEvent {
+    /// BENIGN, the attack class (or "Attack" without a multiclass result), None when not scored
+    pub fn label(&self) -> Option<&str> {
+        match (self.scored, self.is_attack) {
+            (false, _) => None,
+            (true, false) => Some("BENIGN"),
+            (true, true) => Some(self.multi_label.as_deref().unwrap_or("Attack")),
+        }
+    }
+}
+
+/// Part of the pipeline a health event comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Component { Sniffer, Processor, Classifier, Export, Store }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity { Info, Warning, Error }
+
+/// Failures and state changes the user should see instead of a silent log line
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthEvent {
+    pub component: Component,
+    pub severity: Severity,
+    pub message: String,
+    pub timestamp_us: u64,      // Wall clock
+}
+
+impl HealthEvent {
+    pub fn new(component: Component, severity: Severity, message: impl Into<String>) -> Self {
+        let timestamp_us = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as u64).unwrap_or(0);
+        Self { component, severity, message: message.into(), timestamp_us }
+    }
+}
@@ -0,0 +1,243 @@
+// Feeds the reference captures in tests/fixtures/cicflowmeter through the flow table and
+// compares every feature against the CICFlowMeter rows stored next to each capture.
+//
+//   cargo test --test cicflowmeter_conformance -- --nocapture
+//
+// prints the per-feature diff report even when everything matches.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use layton_lib::capture::PacketSniffer;
+use layton_lib::export::{CsvExporter, ExportLabel, CIC_FEATURES};
+use layton_lib::processor::{FlowConfig, FlowRecord, FlowTable};
+
+// CICFlowMeter writes doubles with full precision, the slack covers float summation order
+const ABS_TOLERANCE: f64 = 1e-3;
+const REL_TOLERANCE: f64 = 1e-6;
+
+// Identify the flow, never compared as features
+const ID_COLUMNS: &[&str] = &["Flow ID", "Src IP", "Src Port", "Dst IP", "Dst Port", "Protocol", "Timestamp", "Label"];
+
+struct Mismatch {
+    fixture: String,
+    flow: String,
+    feature: String,
+    expected: String,
+    actual: String,
+}
+
+#[derive(Default)]
+struct FeatureSummary {
+    checked: usize,
+    failed: usize,
+    max_abs_diff: f64,
+}
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cicflowmeter")
+}
+
+// Every flow of the capture, finalized, with the default CICFlowMeter timeouts
+fn run_capture(path: &Path) -> Vec<FlowRecord> {
+    let mut cap = pcap::Capture::from_file(path).unwrap_or_else(|e| panic!("open {}: {e}", path.display()));
+    let mut table = FlowTable::new(Arc::new(FlowConfig::default()));
+    let mut flows = Vec::new();
+
+    while let Ok(packet) = cap.next_packet() {
+        if let Ok(parsed) = PacketSniffer::parse_packet(packet.header, packet.data) {
+            table.process(&parsed, &mut flows);
+        }
+    }
+    flows.extend(table.drain());
+    flows
+}
+
+// CICFlowMeter never quotes fields, a plain split is enough
+fn read_csv(path: &Path) -> (Vec<String>, Vec<Vec<String>>) {
+    let text = fs::read_to_string(path).unwrap_or_else(|e| panic!("read {}: {e}", path.display()));
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let header = lines.next().expect("empty csv").split(',').map(|c| c.trim().to_string()).collect();
+    let rows = lines.map(|l| l.split(',').map(|c| c.trim().to_string()).collect()).collect();
+    (header, rows)
+}
+
+fn column<'a>(header: &[String], row: &'a [String], name: &str) -> &'a str {
+    let idx = header.iter().position(|c| c == name).unwrap_or_else(|| panic!("missing column {name}"));
+    &row[idx]
+}
+
+// CICFlowMeter's Src is the first packet's sender, so is our flow's initiator
+fn find_flow(flows: &[FlowRecord], src: (IpAddr, u16), dst: (IpAddr, u16), protocol: u8) -> Option<&FlowRecord> {
+    flows.iter().find(|f| f.key.protocol == protocol && f.initiator == src && f.responder() == dst)
+}
+
+fn within_tolerance(expected: f64, actual: f64) -> bool {
+    (expected - actual).abs() <= ABS_TOLERANCE + REL_TOLERANCE * expected.abs()
+}
+
+#[test]
+fn flow_features_match_cicflowmeter() {
+    let dir = fixtures_dir();
+    let mut captures: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("fixtures dir")
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| matches!(p.extension().and_then(|e| e.to_str()), Some("pcap" | "pcapng")))
+        .collect();
+    captures.sort();
+    assert!(!captures.is_empty(), "no reference captures in {}", dir.display());
+
+    let mut mismatches: Vec<Mismatch> = Vec::new();
+    let mut summary: BTreeMap<&str, FeatureSummary> = BTreeMap::new();
+    let mut not_compared: BTreeMap<String, usize> = BTreeMap::new();
+    let mut rows_checked = 0;
+
+    for capture in &captures {
+        // A capture nothing checks is a gap in the suite, not something to skip
+        let golden = capture.with_extension("csv");
+        assert!(golden.exists(), "{}: no golden csv, run gen_fixtures.py or regen_goldens.sh", capture.display());
+        let fixture = capture.file_stem().unwrap().to_string_lossy().into_owned();
+        let flows = run_capture(capture);
+        let (header, rows) = read_csv(&golden);
+
+        // Every feature has to be pinned, a golden without a column checks nothing for it
+        for (name, _) in CIC_FEATURES {
+            if !header.iter().any(|c| c == name) {
+                mismatches.push(Mismatch {
+                    fixture: fixture.clone(),
+                    flow: "<golden>".into(),
+                    feature: name.to_string(),
+                    expected: "column".into(),
+                    actual: "missing".into(),
+                });
+            }
+        }
+
+        for row in &rows {
+            let src_ip: IpAddr = column(&header, row, "Src IP").parse().expect("Src IP");
+            let dst_ip: IpAddr = column(&header, row, "Dst IP").parse().expect("Dst IP");
+            let src_port: u16 = column(&header, row, "Src Port").parse().expect("Src Port");
+            let dst_port: u16 = column(&header, row, "Dst Port").parse().expect("Dst Port");
+            let protocol: u8 = column(&header, row, "Protocol").parse().expect("Protocol");
+            let flow_id = format!("{src_ip}:{src_port} -> {dst_ip}:{dst_port} ({protocol})");
+            rows_checked += 1;
+
+            let Some(flow) = find_flow(&flows, (src_ip, src_port), (dst_ip, dst_port), protocol) else {
+                mismatches.push(Mismatch {
+                    fixture: fixture.clone(),
+                    flow: flow_id,
+                    feature: "<flow>".into(),
+                    expected: "present".into(),
+                    actual: "missing".into(),
+                });
+                continue;
+            };
+
+            for (name, cell) in header.iter().zip(row) {
+                if ID_COLUMNS.contains(&name.as_str()) || cell.is_empty() {
+                    continue;
+                }
+                let Some((feature, get)) = CIC_FEATURES.iter().find(|(n, _)| n == name) else {
+                    *not_compared.entry(name.clone()).or_default() += 1;
+                    continue;
+                };
+                let expected: f64 = cell.parse().unwrap_or_else(|_| panic!("{fixture}: bad value {cell:?} for {name}"));
+                let actual = get(flow);
+
+                let entry = summary.entry(feature).or_default();
+                entry.checked += 1;
+                let diff = (expected - actual).abs();
+                if diff > entry.max_abs_diff { entry.max_abs_diff = diff; }
+
+                if !within_tolerance(expected, actual) {
+                    entry.failed += 1;
+                    mismatches.push(Mismatch {
+                        fixture: fixture.clone(),
+                        flow: flow_id.clone(),
+                        feature: name.clone(),
+                        expected: cell.clone(),
+                        actual: actual.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    // Per-feature report
+    println!("\nCICFlowMeter conformance: {} captures, {} flows", captures.len(), rows_checked);
+    println!("{:<28} {:>8} {:>8} {:>14}", "feature", "checked", "failed", "max |diff|");
+    for (feature, s) in &summary {
+        let mark = if s.failed > 0 { "  <--" } else { "" };
+        println!("{:<28} {:>8} {:>8} {:>14.6}{}", feature, s.checked, s.failed, s.max_abs_diff, mark);
+    }
+    if !not_compared.is_empty() {
+        println!("\ncolumns without a FlowRecord counterpart:");
+        for (name, n) in &not_compared {
+            println!("  {name} ({n} rows)");
+        }
+    }
+    if !mismatches.is_empty() {
+        println!("\nmismatches:");
+        for m in &mismatches {
+            println!("  [{}] {} {}: expected {}, got {}", m.fixture, m.flow, m.feature, m.expected, m.actual);
+        }
+    }
+
+    assert!(mismatches.is_empty(), "{} feature values differ from CICFlowMeter, see the report above", mismatches.len());
+}
+
+// The exporter's own CSV has to read back as the golden file does, Src being the client
+// whichever side of the flow key it is on
+#[test]
+fn exported_csv_round_trips() {
+    // Columns whose definition differs from the obvious reading, they have to be in every golden
+    let checked = [
+        "Fwd Header Length", "Bwd Header Length", "Fwd Seg Size Min",
+        "Subflow Fwd Packets", "Subflow Fwd Bytes", "Subflow Bwd Packets", "Subflow Bwd Bytes",
+    ];
+    for name in ["tcp_request_response", "tcp_client_high_endpoint", "udp_subflows"] {
+        let capture = fixtures_dir().join(format!("{name}.pcap"));
+        let out = std::env::temp_dir().join(format!("layton_export_{name}_{}.csv", std::process::id()));
+
+        let mut flows = run_capture(&capture);
+        let mut exporter = CsvExporter::create(&out, ExportLabel::Fixed("BENIGN".into())).expect("create csv");
+        for flow in &mut flows {
+            exporter.write_flow(flow, None).expect("write row");
+        }
+        exporter.flush().expect("flush");
+        assert_eq!(exporter.rows(), flows.len() as u64);
+
+        let (exported_header, exported_rows) = read_csv(&out);
+        let (golden_header, golden_rows) = read_csv(&capture.with_extension("csv"));
+        let _ = fs::remove_file(&out);
+
+        assert_eq!(exported_header.first().map(String::as_str), Some("Flow ID"));
+        assert_eq!(exported_header.last().map(String::as_str), Some("Label"));
+        assert_eq!(exported_rows.len(), 1);
+        for column_name in checked {
+            assert!(golden_header.iter().any(|c| c == column_name), "{name}: golden has no {column_name}");
+        }
+
+        for (column_name, expected) in golden_header.iter().zip(&golden_rows[0]) {
+            let actual = column(&exported_header, &exported_rows[0], column_name);
+            match (expected.parse::<f64>(), actual.parse::<f64>()) {
+                (Ok(e), Ok(a)) => assert!(within_tolerance(e, a), "{name} {column_name}: expected {expected}, exported {actual}"),
+                _ => assert_eq!(expected, actual, "{name} {column_name}"),
+            }
+        }
+    }
+}
+
+// Fields are never quoted, a label that would split the row is refused up front
+#[test]
+fn fixed_label_must_fit_one_field() {
+    let out = std::env::temp_dir().join(format!("layton_export_label_{}.csv", std::process::id()));
+    for label in ["DoS,Hulk", "line\nbreak", "\"quoted\"", ""] {
+        let err = CsvExporter::create(&out, ExportLabel::Fixed(label.into())).err();
+        assert!(err.is_some(), "label {label:?} accepted");
+    }
+    assert!(!out.exists());
+}
@@ -0,0 +1,189 @@
+// The manifest-free bundle builds its inputs from L1_FEATURES/L2_FEATURES through the feature
+// registry. They have to produce the same rows as the hand-written extraction the original
+// models were scored with, copied below as it was before the registry replaced it.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use layton_lib::capture::PacketSniffer;
+use layton_lib::classifier::{L1_FEATURES, L2_FEATURES};
+use layton_lib::processor::{FlowConfig, FlowRecord, FlowTable, FeatureVector};
+
+fn as_f32(v: f64) -> f32 {
+    let f = v as f32;
+    if f.is_finite() { f } else { 0.0 }
+}
+
+fn extract_l1_features(flow: &FlowRecord, out: &mut [f32; 48]) {
+    out[0] = flow.flow_duration as f32;
+    out[1] = flow.total_fwd_bytes as f32;
+    out[2] = flow.total_bwd_bytes as f32;
+    out[3] = flow.fwd_packet_len_min as f32;
+    out[4] = as_f32(flow.fwd_packet_len_std);
+    out[5] = flow.bwd_packet_len_max as f32;
+    out[6] = flow.bwd_packet_len_min as f32;
+    out[7] = as_f32(flow.flow_bytes_per_sec);
+    out[8] = as_f32(flow.flow_packets_per_sec);
+    out[9] = as_f32(flow.flow_iat_mean);
+    out[10] = as_f32(flow.flow_iat_std);
+    out[11] = flow.fwd_iat_total as f32;
+    out[12] = as_f32(flow.fwd_iat_mean);
+    out[13] = as_f32(flow.fwd_iat_std);
+    out[14] = flow.fwd_iat_max as f32;
+    out[15] = flow.fwd_iat_min as f32;
+    out[16] = flow.bwd_iat_total as f32;
+    out[17] = as_f32(flow.bwd_iat_mean);
+    out[18] = as_f32(flow.bwd_iat_std);
+    out[19] = flow.fwd_psh_flags as f32;
+    out[20] = flow.fwd_urg_flags as f32;
+    out[21] = flow.bwd_header_len as f32;
+    out[22] = as_f32(flow.bwd_packets_per_sec);
+    out[23] = flow.packet_len_min as f32;
+    out[24] = flow.packet_len_max as f32;
+    out[25] = as_f32(flow.packet_len_mean);
+    out[26] = flow.fin_flag_count as f32;
+    out[27] = flow.syn_flag_count as f32;
+    out[28] = flow.rst_flag_count as f32;
+    out[29] = flow.psh_flag_count as f32;
+    out[30] = flow.urg_flag_count as f32;
+    out[31] = flow.cwr_flag_count as f32;
+    out[32] = flow.ece_flag_count as f32;
+    out[33] = as_f32(flow.down_up_ratio);
+    out[34] = as_f32(flow.bwd_bytes_bulk_avg);
+    out[35] = as_f32(flow.bwd_packet_bulk_avg);
+    out[36] = as_f32(flow.bwd_bulk_rate_avg);
+    out[37] = flow.subflow_fwd_packets() as f32;
+    out[38] = flow.subflow_fwd_bytes() as f32;
+    out[39] = flow.subflow_bwd_packets() as f32;
+    out[40] = flow.fwd_init_win_bytes as f32;
+    out[41] = flow.bwd_init_win_bytes as f32;
+    out[42] = flow.fwd_act_data_packets as f32;
+    out[43] = as_f32(flow.fwd_seg_size_min);
+    out[44] = as_f32(flow.active_mean);
+    out[45] = as_f32(flow.active_std);
+    out[46] = as_f32(flow.idle_std);
+    out[47] = flow.idle_min as f32;
+}
+
+fn extract_l2_features(flow: &FlowRecord, out: &mut [f32; 52]) {
+    out[0] = flow.flow_duration as f32;
+    out[1] = flow.total_fwd_packets as f32;
+    out[2] = flow.fwd_packet_len_max as f32;
+    out[3] = flow.fwd_packet_len_min as f32;
+    out[4] = flow.bwd_packet_len_min as f32;
+    out[5] = as_f32(flow.bwd_packet_len_mean);
+    out[6] = as_f32(flow.bwd_packet_len_std);
+    out[7] = as_f32(flow.flow_bytes_per_sec);
+    out[8] = as_f32(flow.flow_packets_per_sec);
+    out[9] = as_f32(flow.flow_iat_mean);
+    out[10] = as_f32(flow.flow_iat_std);
+    out[11] = flow.flow_iat_max as f32;
+    out[12] = flow.flow_iat_min as f32;
+    out[13] = as_f32(flow.fwd_iat_mean);
+    out[14] = as_f32(flow.fwd_iat_std);
+    out[15] = flow.fwd_iat_min as f32;
+    out[16] = flow.bwd_iat_total as f32;
+    out[17] = as_f32(flow.bwd_iat_mean);
+    out[18] = as_f32(flow.bwd_iat_std);
+    out[19] = flow.bwd_iat_max as f32;
+    out[20] = flow.bwd_iat_min as f32;
+    out[21] = flow.fwd_psh_flags as f32;
+    out[22] = flow.fwd_urg_flags as f32;
+    out[23] = as_f32(flow.bwd_packets_per_sec);
+    out[24] = flow.packet_len_min as f32;
+    out[25] = flow.packet_len_max as f32;
+    out[26] = flow.packet_len_variance as f32;
+    out[27] = flow.fin_flag_count as f32;
+    out[28] = flow.syn_flag_count as f32;
+    out[29] = flow.rst_flag_count as f32;
+    out[30] = flow.psh_flag_count as f32;
+    out[31] = flow.ack_flag_count as f32;
+    out[32] = flow.urg_flag_count as f32;
+    out[33] = flow.cwr_flag_count as f32;
+    out[34] = flow.ece_flag_count as f32;
+    out[35] = as_f32(flow.down_up_ratio);
+    out[36] = flow.avg_packet_size as f32;
+    out[37] = flow.fwd_segment_size_avg as f32;
+    out[38] = as_f32(flow.bwd_bytes_bulk_avg);
+    out[39] = as_f32(flow.bwd_packet_bulk_avg);
+    out[40] = as_f32(flow.bwd_bulk_rate_avg);
+    out[41] = flow.subflow_fwd_packets() as f32;
+    out[42] = flow.subflow_fwd_bytes() as f32;
+    out[43] = flow.subflow_bwd_packets() as f32;
+    out[44] = flow.subflow_bwd_bytes() as f32;
+    out[45] = flow.fwd_init_win_bytes as f32;
+    out[46] = flow.bwd_init_win_bytes as f32;
+    out[47] = flow.fwd_act_data_packets as f32;
+    out[48] = as_f32(flow.fwd_seg_size_min);
+    out[49] = as_f32(flow.active_std);
+    out[50] = flow.active_max as f32;
+    out[51] = as_f32(flow.idle_std);
+}
+// Flows of every reference capture, the bulk/idle one fills the subflow, bulk and idle features
+fn flows() -> Vec<FlowRecord> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cicflowmeter");
+    let mut table = FlowTable::new(Arc::new(FlowConfig::default()));
+    let mut flows = Vec::new();
+    for name in ["tcp_request_response", "tcp_reset", "tcp_client_high_endpoint", "udp_exchange", "tcp_bulk_idle"] {
+        let path = dir.join(format!("{name}.pcap"));
+        let mut cap = pcap::Capture::from_file(&path).unwrap_or_else(|e| panic!("open {}: {e}", path.display()));
+        while let Ok(packet) = cap.next_packet() {
+            if let Ok(parsed) = PacketSniffer::parse_packet(packet.header, packet.data) {
+                table.process(&parsed, &mut flows);
+            }
+        }
+    }
+    flows.extend(table.drain());
+    flows
+}
+
+fn assert_same_row(names: &[&str], legacy: &[f32], registry: &[f32], flow: &FlowRecord) {
+    assert_eq!(legacy.len(), registry.len());
+    for (slot, (name, (a, b))) in names.iter().zip(legacy.iter().zip(registry)).enumerate() {
+        assert_eq!(a.to_bits(), b.to_bits(), "slot {slot} ({name}) of {:?}: legacy {a}, registry {b}", flow.key);
+    }
+}
+
+#[test]
+fn legacy_lists_match_the_old_extraction() {
+    let l1 = FeatureVector::from_names(&L1_FEATURES).expect("L1 names");
+    let l2 = FeatureVector::from_names(&L2_FEATURES).expect("L2 names");
+
+    let mut flows = flows();
+    assert!(flows.len() >= 5);
+    let busiest = flows.iter().max_by_key(|f| f.total_packets).expect("flows").clone();
+    assert!(busiest.idle_min > 0 && busiest.subflow_fwd_packets() > 0 && busiest.bwd_packet_bulk_avg > 0.0,
+        "the bulk/idle capture no longer fills those features");
+
+    // Rates of a zero length flow, both sides have to turn them into 0
+    let mut degenerate = busiest.clone();
+    degenerate.flow_bytes_per_sec = f64::NAN;
+    degenerate.flow_packets_per_sec = f64::INFINITY;
+    degenerate.bwd_packets_per_sec = f64::NEG_INFINITY;
+    flows.push(degenerate);
+
+    for flow in &flows {
+        let mut legacy = [0f32; 48];
+        extract_l1_features(flow, &mut legacy);
+        let mut registry = Vec::new();
+        l1.extend(flow, &mut registry);
+        assert_same_row(&L1_FEATURES, &legacy, &registry, flow);
+
+        let mut legacy = [0f32; 52];
+        extract_l2_features(flow, &mut legacy);
+        let mut registry = Vec::new();
+        l2.extend(flow, &mut registry);
+        assert_same_row(&L2_FEATURES, &legacy, &registry, flow);
+    }
+}
+
+#[test]
+fn unknown_names_are_reported_together() {
+    let err = FeatureVector::from_names(&["Flow Duration", "Flow Duraton", "SYN Flag Count", "Packets Total"])
+        .err()
+        .expect("accepted");
+    assert_eq!(err, "Unknown features \"Flow Duraton\", \"Packets Total\"");
+
+    let names: Vec<String> = L2_FEATURES.iter().map(|n| n.to_string()).collect();
+    assert_eq!(FeatureVector::from_names(&names).expect("owned names").len(), 52);
+}
@@ -0,0 +1,404 @@
+#!/usr/bin/env python3
+"""Writes the synthetic reference captures and their expected CICFlowMeter rows.
+
+Each scenario becomes `<name>.pcap` plus `<name>.csv`. The CSV uses CICFlowMeter-4.0
+column names and carries every feature column, computed by a port of CICFlowMeter's
+BasicFlow bookkeeping (see README.md). Real CICFlowMeter output for a capture can be
+dropped in next to it in place of the generated CSV.
+
+    python3 gen_fixtures.py
+"""
+import csv
+import math
+import os
+import struct
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+BASE_TS = 1_700_000_000  # seconds, start of every capture
+
+FIN, SYN, RST, PSH, ACK, URG, ECE, CWR = 0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80
+
+
+# --- packet building -------------------------------------------------------
+
+def checksum(data):
+    if len(data) % 2:
+        data += b"\0"
+    s = sum(struct.unpack(f"!{len(data) // 2}H", data))
+    while s >> 16:
+        s = (s & 0xFFFF) + (s >> 16)
+    return ~s & 0xFFFF
+
+
+def ip_bytes(ip):
+    return bytes(int(x) for x in ip.split("."))
+
+
+def ipv4(src, dst, proto, payload):
+    total = 20 + len(payload)
+    hdr = struct.pack("!BBHHHBBH4s4s", 0x45, 0, total, 0, 0x4000, 64, proto, 0, ip_bytes(src), ip_bytes(dst))
+    hdr = hdr[:10] + struct.pack("!H", checksum(hdr)) + hdr[12:]
+    return hdr + payload
+
+
+def tcp(src, dst, sport, dport, seq, ack, flags, win, payload):
+    hdr = struct.pack("!HHIIBBHHH", sport, dport, seq, ack, 5 << 4, flags, win, 0, 0)
+    pseudo = ip_bytes(src) + ip_bytes(dst) + struct.pack("!BBH", 0, 6, len(hdr) + len(payload))
+    csum = checksum(pseudo + hdr + payload)
+    return hdr[:16] + struct.pack("!H", csum) + hdr[18:] + payload
+
+
+def udp(src, dst, sport, dport, payload):
+    length = 8 + len(payload)
+    hdr = struct.pack("!HHHH", sport, dport, length, 0)
+    pseudo = ip_bytes(src) + ip_bytes(dst) + struct.pack("!BBH", 0, 17, length)
+    csum = checksum(pseudo + hdr + payload) or 0xFFFF
+    return hdr[:6] + struct.pack("!H", csum) + payload
+
+
+def ethernet(ip_packet):
+    return b"\x02\x00\x00\x00\x00\x01" + b"\x02\x00\x00\x00\x00\x02" + b"\x08\x00" + ip_packet
+
+
+def write_pcap(path, frames):
+    with open(path, "wb") as f:
+        f.write(struct.pack("<IHHiIII", 0xA1B2C3D4, 2, 4, 0, 0, 65535, 1))
+        for ts_us, frame in frames:
+            f.write(struct.pack("<IIII", BASE_TS + ts_us // 1_000_000, ts_us % 1_000_000, len(frame), len(frame)))
+            f.write(frame)
+
+
+# --- CICFlowMeter feature definitions ---------------------------------------
+
+def stats(values):
+    """min, max, mean, sample std (0 with fewer than two values) like commons-math SummaryStatistics"""
+    if not values:
+        return 0, 0, 0.0, 0.0, 0.0
+    n = len(values)
+    mean = sum(values) / n
+    var = sum((v - mean) ** 2 for v in values) / (n - 1) if n > 1 else 0.0
+    return min(values), max(values), mean, math.sqrt(var), var
+
+
+def diffs(ts):
+    return [b - a for a, b in zip(ts, ts[1:])]
+
+
+FLOW_TIMEOUT = 120_000_000
+ACTIVITY_TIMEOUT = 5_000_000
+
+
+def active_idle(pkts, flag_end):
+    """updateActiveIdleTime on every packet after the first, then endActiveIdleTime"""
+    start = end = pkts[0][0]
+    active, idle = [], []
+    for ts, *_ in pkts[1:]:
+        if ts - end > ACTIVITY_TIMEOUT:
+            if end - start > 0:
+                active.append(end - start)
+            idle.append(ts - end)
+            start = ts
+        end = ts
+    if end - start > 0:
+        active.append(end - start)
+    # A flow FIN or RST didn't end counts what is left of the flow timeout as idle
+    if not flag_end and FLOW_TIMEOUT - (end - pkts[0][0]) > 0:
+        idle.append(FLOW_TIMEOUT - (end - pkts[0][0]))
+    return active, idle
+
+
+BULK_THRESHOLD = 4
+BULK_IDLE = 1_000_000
+
+
+def bulks(pkts):
+    """updateForwardBulk/updateBackwardBulk: (count, packets, bytes, duration) per direction"""
+    totals = {d: [0, 0, 0, 0] for d in (True, False)}
+    helper = {d: {"start": 0, "last": 0, "packets": 0, "bytes": 0} for d in (True, False)}
+    for ts, forward, plen, *_ in pkts:
+        h, other, t = helper[forward], helper[not forward], totals[forward]
+        if other["last"] > h["start"]:
+            h["start"] = 0
+        if plen <= 0:
+            continue
+        if h["start"] == 0 or ts - h["last"] > BULK_IDLE:
+            h.update(start=ts, packets=1, bytes=plen)
+        else:
+            h["packets"] += 1
+            h["bytes"] += plen
+            if h["packets"] == BULK_THRESHOLD:
+                t[0] += 1
+                t[1] += h["packets"]
+                t[2] += h["bytes"]
+                t[3] += ts - h["start"]
+            elif h["packets"] > BULK_THRESHOLD:
+                t[1] += 1
+                t[2] += plen
+                t[3] += ts - h["last"]
+        h["last"] = ts
+    return totals[True], totals[False]
+
+
+def bulk_columns(prefix, count, packets, size, duration):
+    """fAvgBytesPerBulk and friends, whole numbers"""
+    return {
+        f"{prefix} Bytes/Bulk Avg": size // count if count else 0,
+        f"{prefix} Packet/Bulk Avg": packets // count if count else 0,
+        f"{prefix} Bulk Rate Avg": int(size / (duration / 1_000_000)) if duration else 0,
+    }
+
+
+def features(pkts, tcp_flow):
+    """pkts: (ts_us, forward, payload_len, flags, window) in capture order"""
+    fwd = [p for p in pkts if p[1]]
+    bwd = [p for p in pkts if not p[1]]
+    duration = pkts[-1][0] - pkts[0][0]
+    secs = duration / 1_000_000
+    total_len = sum(p[2] for p in pkts)
+
+    f_min, f_max, f_mean, f_std, _ = stats([p[2] for p in fwd])
+    b_min, b_max, b_mean, b_std, _ = stats([p[2] for p in bwd])
+    l_min, l_max, l_mean, l_std, l_var = stats([p[2] for p in pkts])
+    fl_iat = diffs([p[0] for p in pkts])
+    fw_iat = diffs([p[0] for p in fwd])
+    bw_iat = diffs([p[0] for p in bwd])
+    fi_min, fi_max, fi_mean, fi_std, _ = stats(fl_iat)
+    fw_min, fw_max, fw_mean, fw_std, _ = stats(fw_iat)
+    bw_min, bw_max, bw_mean, bw_std, _ = stats(bw_iat)
+    # No scenario tears down with FINs, a RST is the only flag that ends one
+    active, idle = active_idle(pkts, any(p[3] & RST for p in pkts))
+    # detectUpdateSubflows: one subflow per gap over 1 s, none before the first gap
+    sf_count = sum(1 for gap in fl_iat if gap > 1_000_000)
+    # Header Length is the transport header alone: 20 byte TCP headers without options, 8 for UDP
+    header = 20 if tcp_flow else 8
+    fwd_bulk, bwd_bulk = bulks(pkts)
+    a_min, a_max, a_mean, a_std, _ = stats(active)
+    i_min, i_max, i_mean, i_std, _ = stats(idle)
+
+    def count(flag, subset=pkts):
+        return sum(1 for p in subset if p[3] & flag)
+
+    row = {
+        "Flow Duration": duration,
+        "Total Fwd Packet": len(fwd),
+        "Total Bwd packets": len(bwd),
+        "Total Length of Fwd Packet": sum(p[2] for p in fwd),
+        "Total Length of Bwd Packet": sum(p[2] for p in bwd),
+        "Fwd Packet Length Max": f_max,
+        "Fwd Packet Length Min": f_min,
+        "Fwd Packet Length Mean": f_mean,
+        "Fwd Packet Length Std": f_std,
+        "Bwd Packet Length Max": b_max,
+        "Bwd Packet Length Min": b_min,
+        "Bwd Packet Length Mean": b_mean,
+        "Bwd Packet Length Std": b_std,
+        "Flow Bytes/s": total_len / secs,
+        "Flow Packets/s": len(pkts) / secs,
+        "Flow IAT Mean": fi_mean,
+        "Flow IAT Std": fi_std,
+        "Flow IAT Max": fi_max,
+        "Flow IAT Min": fi_min,
+        "Fwd IAT Total": sum(fw_iat),
+        "Fwd IAT Mean": fw_mean,
+        "Fwd IAT Std": fw_std,
+        "Fwd IAT Max": fw_max,
+        "Fwd IAT Min": fw_min,
+        "Bwd IAT Total": sum(bw_iat),
+        "Bwd IAT Mean": bw_mean,
+        "Bwd IAT Std": bw_std,
+        "Bwd IAT Max": bw_max,
+        "Bwd IAT Min": bw_min,
+        "Fwd PSH Flags": count(PSH, fwd),
+        "Bwd PSH Flags": count(PSH, bwd),
+        "Fwd URG Flags": count(URG, fwd),
+        "Bwd URG Flags": count(URG, bwd),
+        "Fwd Header Length": header * len(fwd),
+        "Bwd Header Length": header * len(bwd),
+        "Fwd Packets/s": len(fwd) / secs,
+        "Bwd Packets/s": len(bwd) / secs,
+        "Packet Length Min": l_min,
+        "Packet Length Max": l_max,
+        "Packet Length Mean": l_mean,
+        "Packet Length Std": l_std,
+        "Packet Length Variance": l_var,
+        "FIN Flag Count": count(FIN),
+        "SYN Flag Count": count(SYN),
+        "RST Flag Count": count(RST),
+        "PSH Flag Count": count(PSH),
+        "ACK Flag Count": count(ACK),
+        "URG Flag Count": count(URG),
+        "CWR Flag Count": count(CWR),
+        "ECE Flag Count": count(ECE),
+        "Down/Up Ratio": len(bwd) // len(fwd) if fwd else 0,
+        "Average Packet Size": total_len / len(pkts),
+        "Fwd Segment Size Avg": f_mean,
+        "Bwd Segment Size Avg": b_mean,
+        **bulk_columns("Fwd", *fwd_bulk),
+        **bulk_columns("Bwd", *bwd_bulk),
+        # getSflow_*: flow totals over the subflow count, 0 without one
+        "Subflow Fwd Packets": len(fwd) // sf_count if sf_count else 0,
+        "Subflow Fwd Bytes": sum(p[2] for p in fwd) // sf_count if sf_count else 0,
+        "Subflow Bwd Packets": len(bwd) // sf_count if sf_count else 0,
+        "Subflow Bwd Bytes": sum(p[2] for p in bwd) // sf_count if sf_count else 0,
+        "Fwd Act Data Pkts": sum(1 for p in fwd if p[2] > 0),
+        # min_seg_size_forward: smallest forward header, the first packet's included
+        "Fwd Seg Size Min": header if fwd else 0,
+        "Active Mean": a_mean,
+        "Active Std": a_std,
+        "Active Max": a_max,
+        "Active Min": a_min,
+        "Idle Mean": i_mean,
+        "Idle Std": i_std,
+        "Idle Max": i_max,
+        "Idle Min": i_min,
+    }
+    # A UDP packet has no window, its flow reports 0 on both sides
+    row["FWD Init Win Bytes"] = fwd[0][4] if fwd and tcp_flow else 0
+    row["Bwd Init Win Bytes"] = bwd[0][4] if bwd and tcp_flow else 0
+    return row
+
+
+# --- scenarios --------------------------------------------------------------
+
+def tcp_scenario(client, server, steps):
+    """steps: (ts_us, from_client, flags, window, payload_len)"""
+    (cip, cport), (sip, sport) = client, server
+    seq = {True: 1000, False: 5000}
+    frames, pkts = [], []
+    for ts, from_client, flags, win, plen in steps:
+        src, dst, sp, dp = (cip, sip, cport, sport) if from_client else (sip, cip, sport, cport)
+        ack = seq[not from_client] if flags & ACK else 0
+        seg = tcp(src, dst, sp, dp, seq[from_client], ack, flags, win, b"x" * plen)
+        frames.append((ts, ethernet(ipv4(src, dst, 6, seg))))
+        pkts.append((ts, from_client, plen, flags, win))
+        seq[from_client] += plen + (1 if flags & (SYN | FIN) else 0)
+    return frames, pkts
+
+
+def udp_scenario(client, server, steps):
+    """steps: (ts_us, from_client, payload_len)"""
+    (cip, cport), (sip, sport) = client, server
+    frames, pkts = [], []
+    for ts, from_client, plen in steps:
+        src, dst, sp, dp = (cip, sip, cport, sport) if from_client else (sip, cip, sport, cport)
+        frames.append((ts, ethernet(ipv4(src, dst, 17, udp(src, dst, sp, dp, b"q" * plen)))))
+        pkts.append((ts, from_client, plen, 0, 0))
+    return frames, pkts
+
+
+SCENARIOS = {
+    # Handshake, request, two response segments with their ACKs; no teardown, ends with the capture
+    "tcp_request_response": ("tcp", ("10.0.0.1", 40000), ("10.0.0.2", 80), [
+        (0,      True,  SYN,       64240, 0),
+        (1_000,  False, SYN | ACK, 65160, 0),
+        (1_500,  True,  ACK,       502,   0),
+        (3_500,  True,  PSH | ACK, 502,   120),
+        (4_500,  False, ACK,       509,   0),
+        (9_500,  False, PSH | ACK, 509,   1448),
+        (10_000, True,  ACK,       501,   0),
+        (14_500, False, PSH | ACK, 509,   512),
+        (15_000, True,  ACK,       501,   0),
+    ]),
+    # Client sends data and the server resets the connection
+    "tcp_reset": ("tcp", ("10.0.0.1", 41000), ("10.0.0.3", 443), [
+        (0,     True,  SYN,       64240, 0),
+        (800,   False, SYN | ACK, 65160, 0),
+        (1_200, True,  ACK,       502,   0),
+        (2_000, True,  PSH | ACK, 502,   64),
+        (2_600, False, RST | ACK, 0,     0),
+    ]),
+    # Client on the higher address and port, so its side isn't the lower endpoint of the flow key
+    "tcp_client_high_endpoint": ("tcp", ("10.0.0.9", 52000), ("10.0.0.4", 443), [
+        (0,      True,  SYN,       64240, 0),
+        (700,    False, SYN | ACK, 65160, 0),
+        (1_100,  True,  ACK,       502,   0),
+        (2_000,  True,  PSH | ACK, 502,   300),
+        (2_900,  False, ACK,       509,   0),
+        (6_400,  False, PSH | ACK, 509,   900),
+        (7_000,  True,  ACK,       501,   0),
+    ]),
+    # Two DNS style query/response exchanges on the same socket
+    "udp_exchange": ("udp", ("10.0.0.1", 53000), ("10.0.0.53", 53), [
+        (0,       True,  40),
+        (2_300,   False, 120),
+        (500_000, True,  45),
+        (503_000, False, 200),
+    ]),
+    # Gaps of 1.5 s and 2.5 s between exchanges, two subflows by CICFlowMeter's count
+    "udp_subflows": ("udp", ("10.0.0.1", 53100), ("10.0.0.53", 5353), [
+        (0,         True,  60),
+        (20_000,    False, 300),
+        (1_520_000, True,  60),
+        (1_540_000, False, 300),
+        (1_700_000, True,  50),
+        (4_200_000, False, 500),
+        (4_210_000, True,  40),
+    ]),
+    # Three rounds: a 5 segment bulk from the server, a new subflow 1.5 s later with a
+    # 4 segment bulk, then 6 s idle before a last request/response
+    "tcp_bulk_idle": ("tcp", ("10.0.0.1", 42000), ("10.0.0.5", 8080), [
+        (0,           True,  SYN,       64240, 0),
+        (600,         False, SYN | ACK, 65160, 0),
+        (1_000,       True,  ACK,       502,   0),
+        (1_500,       True,  PSH | ACK, 502,   200),
+        (2_500,       False, ACK,       509,   1448),
+        (2_900,       False, ACK,       509,   1448),
+        (3_300,       False, ACK,       509,   1448),
+        (3_700,       False, ACK,       509,   1448),
+        (4_100,       False, PSH | ACK, 509,   700),
+        (4_600,       True,  ACK,       501,   0),
+        (1_504_600,   True,  PSH | ACK, 501,   180),
+        (1_505_500,   False, ACK,       509,   1448),
+        (1_505_900,   False, ACK,       509,   1448),
+        (1_506_300,   False, ACK,       509,   1448),
+        (1_506_700,   False, PSH | ACK, 509,   300),
+        (1_507_200,   True,  ACK,       501,   0),
+        (7_507_200,   True,  PSH | ACK, 501,   90),
+        (7_508_000,   False, PSH | ACK, 509,   400),
+        (7_508_500,   True,  ACK,       501,   0),
+    ]),
+}
+
+ID_COLUMNS = ["Flow ID", "Src IP", "Src Port", "Dst IP", "Dst Port", "Protocol"]
+
+
+def fmt(v):
+    return str(v) if isinstance(v, int) else repr(float(v))
+
+
+def from_cicflowmeter(path):
+    """CICFlowMeter's rows carry a Timestamp column, the generated ones don't"""
+    if not os.path.exists(path):
+        return False
+    with open(path, newline="") as f:
+        return "Timestamp" in next(csv.reader(f), [])
+
+
+def main():
+    for name, (kind, client, server, steps) in SCENARIOS.items():
+        if kind == "tcp":
+            frames, pkts = tcp_scenario(client, server, steps)
+            proto = 6
+        else:
+            frames, pkts = udp_scenario(client, server, steps)
+            proto = 17
+        write_pcap(os.path.join(HERE, f"{name}.pcap"), frames)
+        golden = os.path.join(HERE, f"{name}.csv")
+        if from_cicflowmeter(golden):
+            continue
+
+        (cip, cport), (sip, sport) = client, server
+        row = {
+            "Flow ID": f"{cip}-{sip}-{cport}-{sport}-{proto}",
+            "Src IP": cip, "Src Port": cport, "Dst IP": sip, "Dst Port": sport, "Protocol": proto,
+        }
+        row.update(features(pkts, kind == "tcp"))
+        row["Label"] = "BENIGN"
+        with open(golden, "w", newline="") as f:
+            w = csv.writer(f)
+            w.writerow(list(row))
+            w.writerow([fmt(v) if not isinstance(v, str) else v for v in row.values()])
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,21 @@
+#!/bin/sh
+# Replaces the CSV of every capture here with CICFlowMeter-4.0's own output.
+#
+#   CFM=/path/to/CICFlowMeter-4.0/bin/cfm ./regen_goldens.sh
+#
+# cfm comes from the CICFlowMeter-4.0 distribution (`gradle distZip` in its repository)
+# and needs jnetpcap's native library on the library path.
+set -eu
+
+: "${CFM:?set CFM to CICFlowMeter-4.0's bin/cfm}"
+HERE=$(cd "$(dirname "$0")" && pwd)
+OUT=$(mktemp -d)
+trap 'rm -rf "$OUT"' EXIT
+
+for pcap in "$HERE"/*.pcap; do
+    name=$(basename "$pcap" .pcap)
+    "$CFM" "$pcap" "$OUT" >/dev/null
+    # cfm names its output after the whole capture file name
+    mv "$OUT/$name.pcap_Flow.csv" "$HERE/$name.csv"
+    echo "$name.csv"
+done
@@ -0,0 +1,91 @@
+// Packet-driven time for flow expiry: replays expire by capture time, a quiet input falls back to wall time.
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use layton_lib::capture::{ParsedPacket, TransportInfo};
+use layton_lib::processor::{FlowClock, FlowConfig, FlowKey, FlowTable};
+
+const SECOND: u64 = 1_000_000;
+// 2017-07-03, when CIC-IDS2017 was captured
+const CAPTURE_START: u64 = 1_499_040_000 * SECOND;
+
+fn wall_micros() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).expect("after 1970").as_micros() as u64
+}
+
+fn udp(port: u16, timestamp: u64) -> ParsedPacket {
+    let (src_ip, dst_ip) = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 53)));
+    ParsedPacket {
+        timestamp,
+        flow_key: FlowKey::new(src_ip, dst_ip, port, 53, 17),
+        src_ip,
+        dst_ip,
+        src_port: port,
+        dst_port: 53,
+        packet_len: 82,
+        payload_len: 40,
+        transport: TransportInfo::Udp,
+        header_len: 8,
+    }
+}
+
+#[test]
+fn out_of_order_packets_do_not_move_the_clock_back() {
+    let mut clock = FlowClock::new();
+    clock.observe(CAPTURE_START + 10 * SECOND);
+    clock.observe(CAPTURE_START + 5 * SECOND);
+    assert_eq!(clock.now(), CAPTURE_START + 10 * SECOND);
+
+    clock.observe(CAPTURE_START + 12 * SECOND);
+    assert_eq!(clock.now(), CAPTURE_START + 12 * SECOND);
+}
+
+#[test]
+fn quiet_input_falls_back_to_wall_time_after_a_second() {
+    // Before any packet there is only wall time
+    let before = wall_micros();
+    let now = FlowClock::new().now();
+    assert!((before..=wall_micros()).contains(&now));
+
+    let mut clock = FlowClock::new();
+    clock.observe(CAPTURE_START);
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(clock.now(), CAPTURE_START, "moved on its own before the fallback");
+
+    // Past 1 s without packets the time since the last one is added on top
+    thread::sleep(Duration::from_millis(900));
+    let now = clock.now();
+    assert!(now >= CAPTURE_START + 1_200_000, "{}", now - CAPTURE_START);
+    assert!(now < CAPTURE_START + 60 * SECOND, "jumped to wall time");
+
+    // A new packet takes over again
+    clock.observe(CAPTURE_START + 2 * SECOND);
+    assert_eq!(clock.now(), CAPTURE_START + 2 * SECOND);
+}
+
+// A replay read at full speed expires flows by capture time, however long ago it was captured
+#[test]
+fn replay_expires_flows_by_capture_time() {
+    let replay = |packets: &[(u16, u64)]| {
+        let mut table = FlowTable::new(Arc::new(FlowConfig::default()));
+        let mut clock = FlowClock::new();
+        let (mut ended, mut expired_at) = (Vec::new(), Vec::new());
+        for (port, offset) in packets {
+            let pkt = udp(*port, CAPTURE_START + offset);
+            clock.observe(pkt.timestamp);
+            table.process(&pkt, &mut ended);
+            table.expire(clock.now(), &mut ended);
+            expired_at.push(ended.iter().map(|f| f.initiator.1).collect::<Vec<_>>());
+        }
+        expired_at
+    };
+
+    // UDP flows expire 30 s after their last packet
+    let packets = [(1, 0), (2, 10 * SECOND), (1, 20 * SECOND), (3, 45 * SECOND), (3, 55 * SECOND)];
+    let expected = vec![vec![], vec![], vec![], vec![2], vec![2, 1]];
+    assert_eq!(replay(&packets), expected);
+    assert_eq!(replay(&packets), expected, "a second run ended other flows");
+}
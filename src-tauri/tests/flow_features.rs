@@ -0,0 +1,249 @@
+// Feature bookkeeping of single flows fed packet by packet: active/idle periods, subflows, bulks
+// and ICMP pseudo-flows.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+
+use layton_lib::capture::{ParsedPacket, TransportInfo};
+use layton_lib::processor::{FlowConfig, FlowKey, FlowRecord, FlowStatus, FlowTable};
+
+const SECOND: u64 = 1_000_000;
+
+fn client() -> (IpAddr, u16) {
+    (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 53000)
+}
+
+fn server() -> (IpAddr, u16) {
+    (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 53)), 53)
+}
+
+// A UDP flow the client opened, then one packet per timestamp, alternating sides
+fn udp_flow(timestamps: &[u64]) -> FlowRecord {
+    let ((cip, cport), (sip, sport)) = (client(), server());
+    let key = FlowKey::new(cip, sip, cport, sport, 17);
+    let mut flow = FlowRecord::new(key, timestamps[0], client(), Arc::new(FlowConfig::default()));
+    for (i, ts) in timestamps.iter().enumerate() {
+        let (ip, port) = if i % 2 == 0 { client() } else { server() };
+        flow.update_udp_flow(*ts, ip, port, Some(100), 8);
+    }
+    flow
+}
+
+// (count, min, mean, max) of a flow's active or idle periods, the mean in whole microseconds
+type Periods = (u64, u64, u64, u64);
+
+fn active(flow: &FlowRecord) -> Periods {
+    (flow.active_counts, flow.active_min, flow.active_mean.round() as u64, flow.active_max)
+}
+
+fn idle(flow: &FlowRecord) -> Periods {
+    (flow.idle_counts, flow.idle_min, flow.idle_mean.round() as u64, flow.idle_max)
+}
+
+#[test]
+fn gaps_over_the_activity_timeout_split_active_and_idle() {
+    let mut flow = udp_flow(&[0, SECOND, 2 * SECOND, 9 * SECOND, 9 * SECOND + SECOND / 2, 15 * SECOND + SECOND / 2]);
+
+    // While packets arrive: two active periods ended by the 7 s and 6 s gaps
+    assert_eq!(active(&flow), (2, 500_000, 1_250_000, 2 * SECOND));
+    assert_eq!(idle(&flow), (2, 6 * SECOND, 6_500_000, 7 * SECOND));
+    assert_eq!((flow.start_active_time, flow.end_active_time), (15_500_000, 15_500_000));
+
+    // The last period is a single packet and isn't kept, the rest of the 120 s
+    // flow timeout after it is one more idle period
+    flow.finalize();
+    assert_eq!(flow.status, FlowStatus::Expired);
+    assert_eq!(active(&flow), (2, 500_000, 1_250_000, 2 * SECOND));
+    assert_eq!(idle(&flow), (3, 6 * SECOND, 39_166_667, 104_500_000));
+}
+
+#[test]
+fn single_packet_periods_are_not_active_time() {
+    let mut flow = udp_flow(&[0, 6 * SECOND, 12 * SECOND]);
+    assert_eq!(active(&flow).0, 0);
+    assert_eq!(idle(&flow), (2, 6 * SECOND, 6 * SECOND, 6 * SECOND));
+
+    flow.finalize();
+    assert_eq!(active(&flow), (0, 0, 0, 0));
+    assert_eq!(idle(&flow), (3, 6 * SECOND, 40 * SECOND, 108 * SECOND));
+}
+
+#[test]
+fn gap_at_the_activity_timeout_stays_active() {
+    let mut flow = udp_flow(&[0, 5 * SECOND, 10 * SECOND]);
+    assert_eq!(idle(&flow).0, 0);
+
+    flow.finalize();
+    assert_eq!(active(&flow), (1, 10 * SECOND, 10 * SECOND, 10 * SECOND));
+    assert_eq!(idle(&flow), (1, 110 * SECOND, 110 * SECOND, 110 * SECOND));
+}
+
+#[test]
+fn flow_as_long_as_the_flow_timeout_has_no_trailing_idle() {
+    let mut flow = udp_flow(&[0, 60 * SECOND, 120 * SECOND]);
+    flow.finalize();
+    assert_eq!(active(&flow).0, 0);
+    assert_eq!(idle(&flow), (2, 60 * SECOND, 60 * SECOND, 60 * SECOND));
+}
+
+#[test]
+fn flow_ended_by_a_reset_has_no_trailing_idle() {
+    let (c, s) = (client(), server());
+    let mut flow = FlowRecord::new(FlowKey::new(c.0, s.0, c.1, s.1, 6), 0, c, Arc::new(FlowConfig::default()));
+    flow.update_tcp_flow(0, c.0, c.1, Some(0), 0x02, 64240, 1000, 0, 20);
+    flow.update_tcp_flow(1_000, s.0, s.1, Some(0), 0x12, 65160, 5000, 1001, 20);
+    flow.update_tcp_flow(3_000, c.0, c.1, Some(0), 0x04, 0, 1001, 0, 20);
+
+    flow.finalize();
+    assert_eq!(flow.status, FlowStatus::Closed);
+    assert_eq!(active(&flow), (1, 3_000, 3_000, 3_000));
+    assert_eq!(idle(&flow), (0, 0, 0, 0));
+}
+
+#[test]
+fn subflows_count_gaps_in_either_direction() {
+    // The 0.9 s gap doesn't open a subflow, the 1.5 s and 2 s gaps do
+    let flow = udp_flow(&[0, 900_000, 2_400_000, 4_400_000, 4_500_000]);
+    assert_eq!(flow.sf_count, 2);
+    assert_eq!((flow.total_fwd_packets, flow.total_bwd_packets), (3, 2));
+    assert_eq!((flow.subflow_fwd_packets(), flow.subflow_fwd_bytes()), (1, 150));
+    assert_eq!((flow.subflow_bwd_packets(), flow.subflow_bwd_bytes()), (1, 100));
+
+    // No gap, no subflow and the columns stay 0
+    let flow = udp_flow(&[0, SECOND, 2 * SECOND]);
+    assert_eq!(flow.sf_count, 0);
+    assert_eq!((flow.subflow_fwd_packets(), flow.subflow_bwd_bytes()), (0, 0));
+}
+
+#[test]
+fn bulks_are_four_data_packets_the_other_side_does_not_interrupt() {
+    let (c, s) = (client(), server());
+    let mut flow = FlowRecord::new(FlowKey::new(c.0, s.0, c.1, s.1, 17), SECOND, c, Arc::new(FlowConfig::default()));
+    let ms = SECOND / 1000;
+    let packets = [
+        (1000, true, 10),
+        // Three server packets, then the client sends data before a fourth
+        (1100, false, 100), (1200, false, 100), (1300, false, 100),
+        (1400, true, 10),
+        // First bulk: 4 packets in 300 ms
+        (1500, false, 100), (1600, false, 100), (1700, false, 100), (1800, false, 100),
+        // The 1.7 s gap starts a new run, a payload-less ACK from the client doesn't break it
+        (3500, false, 100), (3600, false, 100), (3650, true, 0), (3700, false, 100), (3800, false, 100),
+        // A fifth packet grows the second bulk
+        (3900, false, 100),
+    ];
+    for (at, from_client, payload) in packets {
+        let (ip, port) = if from_client { c } else { s };
+        flow.update_udp_flow(at * ms, ip, port, Some(payload), 8);
+    }
+    flow.finalize();
+
+    assert_eq!(flow.num_bwd_bulk_transmissions, 2);
+    assert_eq!((flow.bwd_packet_bulk_tot, flow.bwd_bytes_bulk_tot, flow.bwd_bulk_duration), (9, 900, 700 * ms));
+    // Averages and rate are whole numbers: 900 / 2, 9 / 2, 900 B / 0.7 s
+    assert_eq!((flow.bwd_bytes_bulk_avg, flow.bwd_packet_bulk_avg, flow.bwd_bulk_rate_avg), (450.0, 4.0, 1285.0));
+    assert_eq!((flow.num_fwd_bulk_transmissions, flow.fwd_bulk_rate_avg), (0, 0.0));
+}
+
+// An ICMP/ICMPv6 query message with 56 bytes of data, as ping sends
+fn icmp(src_ip: IpAddr, dst_ip: IpAddr, icmp_type: u8, identifier: u16, timestamp: u64) -> ParsedPacket {
+    let protocol = if src_ip.is_ipv4() { 1 } else { 58 };
+    ParsedPacket {
+        timestamp,
+        flow_key: FlowKey::icmp(src_ip, dst_ip, icmp_type, 0, identifier, protocol),
+        src_ip,
+        dst_ip,
+        src_port: 0,
+        dst_port: 0,
+        packet_len: 98,
+        payload_len: 56,
+        transport: TransportInfo::Icmp { icmp_type, code: 0, identifier },
+        header_len: 8,
+    }
+}
+
+// Every flow of the packets once the table is drained, ordered by identifier
+fn icmp_flows(packets: &[ParsedPacket]) -> Vec<FlowRecord> {
+    let mut table = FlowTable::new(Arc::new(FlowConfig::default()));
+    let mut ended = Vec::new();
+    for pkt in packets {
+        table.process(pkt, &mut ended);
+    }
+    assert!(ended.is_empty(), "no ICMP flow ends on a packet");
+    let mut flows = table.drain();
+    flows.sort_by_key(|f| f.icmp.as_ref().map(|i| i.identifier));
+    flows
+}
+
+#[test]
+fn echo_replies_fold_into_the_request_flow() {
+    let (v4_client, v4_server) = (client().0, server().0);
+    let (v6_client, v6_server) = (IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)), IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2)));
+
+    for (client, server, request, reply) in [(v4_client, v4_server, 8, 0), (v6_client, v6_server, 128, 129)] {
+        let flows = icmp_flows(&[
+            icmp(client, server, request, 7, 0),
+            icmp(server, client, reply, 7, 1_000),
+            icmp(client, server, request, 7, SECOND),
+            icmp(server, client, reply, 7, SECOND + 1_000),
+        ]);
+
+        assert_eq!(flows.len(), 1, "type {request}: the replies opened a flow of their own");
+        let flow = &flows[0];
+        assert_eq!(flow.initiator, (client, 0));
+        assert_eq!((flow.total_fwd_packets, flow.total_bwd_packets), (2, 2));
+
+        // Keyed by the request type whichever message came first
+        let counters = flow.icmp.as_ref().expect("ICMP flow has counters");
+        assert_eq!((counters.icmp_type, counters.icmp_code, counters.identifier), (request, 0, 7));
+        assert_eq!((counters.echo_requests, counters.echo_replies, counters.other), (2, 2, 0));
+    }
+}
+
+#[test]
+fn identifier_separates_concurrent_pings() {
+    let (client, server) = (client().0, server().0);
+    // Two pings to the same host at once, their messages interleaved
+    let mut packets = Vec::new();
+    for i in 0..3 {
+        let ts = i * SECOND;
+        packets.extend([
+            icmp(client, server, 8, 100, ts),
+            icmp(client, server, 8, 200, ts + 100),
+            icmp(server, client, 0, 100, ts + 500),
+            icmp(server, client, 0, 200, ts + 600),
+        ]);
+    }
+    // The second one's last request goes unanswered
+    packets.pop();
+
+    let flows = icmp_flows(&packets);
+    let per_ping: Vec<_> = flows.iter()
+        .map(|f| {
+            let counters = f.icmp.as_ref().expect("ICMP flow has counters");
+            (counters.identifier, counters.echo_requests, counters.echo_replies)
+        })
+        .collect();
+    assert_eq!(per_ping, vec![(100, 3, 3), (200, 3, 2)]);
+}
+
+#[test]
+fn icmp_rates_are_per_second_of_flow_duration() {
+    let (client, server) = (client().0, server().0);
+    // Four requests, two replies and a 2 second flow
+    let flows = icmp_flows(&[
+        icmp(client, server, 8, 1, 0),
+        icmp(server, client, 0, 1, 500_000),
+        icmp(client, server, 8, 1, SECOND),
+        icmp(client, server, 8, 1, SECOND + 500_000),
+        icmp(client, server, 8, 1, 2 * SECOND - 1_000),
+        icmp(server, client, 0, 1, 2 * SECOND),
+    ]);
+
+    let flow = &flows[0];
+    assert_eq!(flow.flow_duration, 2 * SECOND);
+    let counters = flow.icmp.as_ref().expect("ICMP flow has counters");
+    assert_eq!((counters.echo_request_rate, counters.echo_reply_rate), (2.0, 1.0));
+    assert_eq!((counters.dest_unreachable_rate, counters.time_exceeded_rate, counters.other_rate), (0.0, 0.0, 0.0));
+    assert_eq!(flow.flow_packets_per_sec, 3.0);
+}
@@ -0,0 +1,206 @@
+// Filters, paging and retention of the SQLite flow history, each test on its own database file.
+
+use std::path::PathBuf;
+
+use layton_lib::store::{FlowQuery, FlowSort, FlowStore, StoreConfig};
+use layton_lib::types::{ClassifiedFlowEvent, FlowKeyDTO};
+
+// Removes the database and its WAL files when the test ends
+struct TempDb(PathBuf);
+
+impl TempDb {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("layton_store_{name}_{}.db", std::process::id()));
+        let db = Self(path);
+        db.remove();
+        db
+    }
+
+    fn remove(&self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", self.0.display()));
+        }
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+fn keep_all() -> StoreConfig {
+    StoreConfig { enabled: true, path: None, max_age_days: 0, max_rows: 0 }
+}
+
+fn flow(ip_a: &str, port_b: u16, protocol: u8, end_us: u64, label: Option<&str>, p_attack: f32) -> ClassifiedFlowEvent {
+    let is_attack = label.is_some_and(|l| l != "BENIGN");
+    ClassifiedFlowEvent {
+        key: FlowKeyDTO {
+            ip_a: ip_a.into(),
+            ip_b: "10.0.0.100".into(),
+            port_a: 40000,
+            port_b,
+            protocol,
+            ip_version: if ip_a.contains(':') { 6 } else { 4 },
+        },
+        start_us: end_us - 1_000,
+        end_us,
+        duration_us: 1_000,
+        total_packets: end_us / 1_000,
+        total_bytes: end_us,
+        scored: label.is_some(),
+        is_attack,
+        p_attack,
+        multi_class: is_attack.then_some(0),
+        multi_label: label.filter(|_| is_attack).map(String::from),
+        multi_probs: None,
+        attack_threshold: None,
+        class_threshold: None,
+        shadow_label: None,
+        shadow_p_attack: None,
+        tcp_state: None,
+        icmp: None,
+    }
+}
+
+// Ten flows ending 10 ms apart: even ones benign over TCP/80, odd ones DoS over UDP/53,
+// the last one an unscored ICMP pseudo-flow from an IPv6 host
+fn fill(store: &mut FlowStore) {
+    for i in 0..10u64 {
+        let end = (i + 1) * 10_000;
+        let event = match i {
+            9 => flow("::1", 0, 58, end, None, 0.0),
+            _ if i % 2 == 0 => flow(&format!("10.0.0.{i}"), 80, 6, end, Some("BENIGN"), 0.1),
+            _ => flow(&format!("10.0.0.{i}"), 53, 17, end, Some("DoS"), 0.5 + i as f32 / 20.0),
+        };
+        store.insert(&event).expect("insert");
+    }
+}
+
+fn ends(store: &FlowStore, query: &FlowQuery) -> (u64, Vec<u64>) {
+    let page = store.query(query).expect("query");
+    (page.total, page.flows.iter().map(|f| f.flow.end_us).collect())
+}
+
+#[test]
+fn filters_combine() {
+    let db = TempDb::new("filters");
+    let mut store = FlowStore::open(&db.0, keep_all()).expect("open");
+    fill(&mut store);
+
+    let all = FlowQuery::default();
+    assert_eq!(ends(&store, &all).0, 10);
+
+    let q = FlowQuery { attacks_only: true, ..FlowQuery::default() };
+    assert_eq!(ends(&store, &q).0, 4);
+
+    let q = FlowQuery { label: Some("BENIGN".into()), ..FlowQuery::default() };
+    assert_eq!(ends(&store, &q).0, 5);
+
+    let q = FlowQuery { protocol: Some(17), port: Some(53), ..FlowQuery::default() };
+    assert_eq!(ends(&store, &q).0, 4);
+
+    // Either endpoint, in any text form of the address
+    let q = FlowQuery { ip: Some("10.0.0.100".into()), ..FlowQuery::default() };
+    assert_eq!(ends(&store, &q).0, 10);
+    let q = FlowQuery { ip: Some("0:0:0:0:0:0:0:1".into()), ..FlowQuery::default() };
+    assert_eq!(ends(&store, &q), (1, vec![100_000]));
+
+    let q = FlowQuery { min_p_attack: Some(0.7), max_p_attack: Some(0.9), ..FlowQuery::default() };
+    assert_eq!(ends(&store, &q), (2, vec![80_000, 60_000]));
+
+    // Flows overlapping the window: still alive at from_us, started by to_us
+    let q = FlowQuery { from_us: Some(30_000), to_us: Some(50_000), ..FlowQuery::default() };
+    assert_eq!(ends(&store, &q), (3, vec![50_000, 40_000, 30_000]));
+
+    let q = FlowQuery { attacks_only: true, from_us: Some(50_000), label: Some("DoS".into()), ..FlowQuery::default() };
+    assert_eq!(ends(&store, &q), (2, vec![80_000, 60_000]));
+}
+
+#[test]
+fn pages_are_sorted_and_stable() {
+    let db = TempDb::new("pages");
+    let mut store = FlowStore::open(&db.0, keep_all()).expect("open");
+    fill(&mut store);
+
+    let page = |offset, limit| FlowQuery { offset, limit, ..FlowQuery::default() };
+    assert_eq!(ends(&store, &page(0, 4)), (10, vec![100_000, 90_000, 80_000, 70_000]));
+    assert_eq!(ends(&store, &page(4, 4)), (10, vec![60_000, 50_000, 40_000, 30_000]));
+    assert_eq!(ends(&store, &page(8, 4)), (10, vec![20_000, 10_000]));
+    assert_eq!(ends(&store, &page(12, 4)), (10, vec![]));
+
+    // The limit is capped, not rejected
+    assert_eq!(ends(&store, &page(0, 0)).1.len(), 1);
+    assert_eq!(ends(&store, &page(0, u64::MAX)).1.len(), 10);
+    assert_eq!(ends(&store, &page(u64::MAX, 10)).1.len(), 0);
+
+    let q = FlowQuery { sort: FlowSort::PAttack, descending: false, limit: 3, ..FlowQuery::default() };
+    let p_attack: Vec<f32> = store.query(&q).expect("query").flows.iter().map(|f| f.flow.p_attack).collect();
+    assert_eq!(p_attack, vec![0.0, 0.1, 0.1]);
+}
+
+#[test]
+fn row_limit_drops_the_oldest() {
+    let db = TempDb::new("max_rows");
+    let mut store = FlowStore::open(&db.0, keep_all()).expect("open");
+    fill(&mut store);
+    drop(store);
+
+    // Retention applies on open
+    let mut store = FlowStore::open(&db.0, StoreConfig { max_rows: 4, ..keep_all() }).expect("reopen");
+    assert_eq!(ends(&store, &FlowQuery::default()), (4, vec![100_000, 90_000, 80_000, 70_000]));
+    assert_eq!(store.prune().expect("prune"), 0);
+}
+
+#[test]
+fn age_limit_drops_rows_stored_too_long_ago() {
+    let db = TempDb::new("max_age");
+    let mut store = FlowStore::open(&db.0, keep_all()).expect("open");
+    fill(&mut store);
+    drop(store);
+
+    // Backdate the first six rows by ten days
+    let conn = rusqlite::Connection::open(&db.0).expect("raw open");
+    conn.execute("UPDATE flows SET stored_at_us = stored_at_us - 864000000000 WHERE end_us <= 60000", [])
+        .expect("backdate");
+    drop(conn);
+
+    let store = FlowStore::open(&db.0, StoreConfig { max_age_days: 30, ..keep_all() }).expect("reopen");
+    assert_eq!(ends(&store, &FlowQuery::default()).0, 10);
+    drop(store);
+
+    let store = FlowStore::open(&db.0, StoreConfig { max_age_days: 7, ..keep_all() }).expect("reopen");
+    assert_eq!(ends(&store, &FlowQuery::default()), (4, vec![100_000, 90_000, 80_000, 70_000]));
+}
+
+#[test]
+fn huge_age_limit_keeps_everything() {
+    let db = TempDb::new("huge_age");
+    let config = StoreConfig { max_age_days: u64::MAX, ..keep_all() };
+    assert!(config.validate().is_err());
+    assert!(StoreConfig { max_age_days: 36_500, ..keep_all() }.validate().is_ok());
+
+    // Even unvalidated it must not overflow
+    let mut store = FlowStore::open(&db.0, config).expect("open");
+    fill(&mut store);
+    assert_eq!(store.prune().expect("prune"), 0);
+    assert_eq!(ends(&store, &FlowQuery::default()).0, 10);
+}
+
+#[test]
+fn batches_are_stored_whole_and_count_towards_pruning() {
+    let db = TempDb::new("batch");
+    let mut store = FlowStore::open(&db.0, StoreConfig { max_rows: 500, ..keep_all() }).expect("open");
+
+    let batch: Vec<_> = (1..=400u64).map(|i| flow("10.0.0.1", 80, 6, i * 10_000, Some("BENIGN"), 0.1)).collect();
+    store.insert_batch(&batch).expect("insert");
+    store.insert_batch(&[]).expect("empty batch");
+    assert_eq!(ends(&store, &FlowQuery::default()).0, 400);
+
+    // Retention runs once 1000 rows went in since the last prune, whatever the batch sizes
+    store.insert_batch(&batch).expect("insert");
+    assert_eq!(ends(&store, &FlowQuery::default()).0, 800);
+    store.insert_batch(&batch[..200]).expect("insert");
+    assert_eq!(ends(&store, &FlowQuery::default()).0, 500);
+}
@@ -0,0 +1,119 @@
+// Flow table limits: which flows make room once it is full, how the engine sizes it, and expiry.
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+
+use layton_lib::capture::{ParsedPacket, TransportInfo};
+use layton_lib::processor::{EngineConfig, EvictionPolicy, FlowConfig, FlowKey, FlowRecord, FlowStatus, FlowTable, FLOW_ENTRY_BYTES};
+
+const SECOND: u64 = 1_000_000;
+
+// One UDP packet from 10.0.0.1:`port` to the DNS server, each port its own flow
+fn udp(port: u16, timestamp: u64) -> ParsedPacket {
+    let (src_ip, dst_ip) = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 53)));
+    ParsedPacket {
+        timestamp,
+        flow_key: FlowKey::new(src_ip, dst_ip, port, 53, 17),
+        src_ip,
+        dst_ip,
+        src_port: port,
+        dst_port: 53,
+        packet_len: 82,
+        payload_len: 40,
+        transport: TransportInfo::Udp,
+        header_len: 8,
+    }
+}
+
+fn ports(flows: &[FlowRecord]) -> Vec<u16> {
+    let mut ports: Vec<u16> = flows.iter().map(|f| f.initiator.1).collect();
+    ports.sort();
+    ports
+}
+
+// A full table of `max` flows opened one millisecond apart, port 1 first
+fn full_table(max: usize, eviction: EvictionPolicy) -> FlowTable {
+    let mut table = FlowTable::with_limit(Arc::new(FlowConfig::default()), Some(max), eviction);
+    let mut ended = Vec::new();
+    for port in 1..=max as u16 {
+        table.process(&udp(port, port as u64 * 1_000), &mut ended);
+    }
+    assert!(ended.is_empty());
+    assert_eq!(table.len(), max);
+    table
+}
+
+#[test]
+fn full_table_evicts_a_batch_to_make_room() {
+    // 1/64 of the limit goes at once
+    let mut table = full_table(128, EvictionPolicy::OldestFirst);
+    let mut ended = Vec::new();
+    table.process(&udp(500, SECOND), &mut ended);
+
+    assert_eq!(ports(&ended), vec![1, 2]);
+    assert!(ended.iter().all(|f| f.status == FlowStatus::Expired), "evicted flows are finalized");
+    assert_eq!(table.len(), 127);
+    assert_eq!(table.evicted(), 2);
+
+    // Packets of flows already in the table never evict
+    table.process(&udp(3, 2 * SECOND), &mut ended);
+    assert_eq!((table.len(), table.evicted()), (127, 2));
+}
+
+#[test]
+fn lru_spares_a_flow_seen_recently() {
+    for (eviction, victim) in [(EvictionPolicy::Lru, 2), (EvictionPolicy::OldestFirst, 1)] {
+        let mut table = full_table(64, eviction);
+        let mut ended = Vec::new();
+        // The oldest flow sends again, it is still the one that started first
+        table.process(&udp(1, SECOND), &mut ended);
+        table.process(&udp(500, 2 * SECOND), &mut ended);
+
+        assert_eq!(ports(&ended), vec![victim], "{eviction:?}");
+        assert_eq!(table.evicted(), 1);
+    }
+}
+
+#[test]
+fn memory_limit_caps_the_table_like_a_flow_count() {
+    let engine = EngineConfig { max_flows: 0, max_memory_mb: 1, ..EngineConfig::default() };
+    let max = engine.flows_per_worker().expect("limited");
+    assert_eq!(max, (1 << 20) / FLOW_ENTRY_BYTES);
+
+    let mut table = full_table(max, engine.eviction);
+    let mut ended = Vec::new();
+    table.process(&udp(60_000, SECOND), &mut ended);
+    assert_eq!(table.evicted(), (max / 64).max(1) as u64);
+    assert!(table.len() <= max);
+}
+
+#[test]
+fn flows_per_worker_is_the_tighter_limit_split_evenly() {
+    let by_memory = (64 << 20) / FLOW_ENTRY_BYTES;
+    let config = |workers, max_flows, max_memory_mb| EngineConfig { workers, max_flows, max_memory_mb, ..EngineConfig::default() };
+
+    assert_eq!(config(1, 0, 0).flows_per_worker(), None);
+    assert_eq!(config(1, 1000, 0).flows_per_worker(), Some(1000));
+    assert_eq!(config(1, 0, 64).flows_per_worker(), Some(by_memory));
+    assert_eq!(config(1, 10, 64).flows_per_worker(), Some(10));
+    assert_eq!(config(1, usize::MAX, 64).flows_per_worker(), Some(by_memory));
+    // Rounded up, every worker gets its share
+    assert_eq!(config(3, 10, 0).flows_per_worker(), Some(4));
+}
+
+#[test]
+fn expire_ends_only_the_idle_flows() {
+    let mut table = FlowTable::new(Arc::new(FlowConfig::default()));
+    let mut ended = Vec::new();
+    table.process(&udp(1, 0), &mut ended);
+    table.process(&udp(2, 20 * SECOND), &mut ended);
+
+    // UDP flows expire once 30 s passed without packets
+    table.expire(30 * SECOND - 1, &mut ended);
+    assert!(ended.is_empty());
+    table.expire(30 * SECOND, &mut ended);
+    assert_eq!(ports(&ended), vec![1]);
+    assert_eq!(ended[0].status, FlowStatus::Expired);
+    assert_eq!(table.keys().map(|k| k.port_a.min(k.port_b)).collect::<Vec<_>>(), vec![2]);
+    assert_eq!(table.evicted(), 0, "expiry isn't eviction");
+}
@@ -0,0 +1,62 @@
+// Which models a classifier worker scores each flow with around a reload. Named stand-ins
+// take the place of the ONNX sessions, the routing doesn't look inside them.
+
+use std::collections::BTreeMap;
+
+use layton_lib::classifier::ModelGenerations;
+
+// Flow names grouped by the model that scores them, as a worker's batch loop does
+fn scored_by(models: &ModelGenerations<&'static str>, batch: &[(&'static str, u64)]) -> BTreeMap<&'static str, Vec<&'static str>> {
+    models
+        .route(batch.iter().copied())
+        .into_iter()
+        .map(|(generation, flows)| (*models.get(generation).expect("routed to a held generation"), flows))
+        .collect()
+}
+
+#[test]
+fn flows_queued_before_a_reload_keep_the_old_model() {
+    let mut models = ModelGenerations::new("startup");
+    assert_eq!(models.newest(), 0);
+
+    // Two flows were queued before the reload, one after
+    models.insert(1, "reloaded");
+    let batch = [("before-1", 0), ("before-2", 0), ("after", 1)];
+    assert_eq!(scored_by(&models, &batch), BTreeMap::from([
+        ("startup", vec!["before-1", "before-2"]),
+        ("reloaded", vec!["after"]),
+    ]));
+
+    // The old generation stays while the batch still had its flows
+    models.retire_before(0);
+    assert_eq!(models.generations(), vec![0, 1]);
+
+    // A batch with only new flows retires it
+    let batch = [("later", 1)];
+    assert_eq!(scored_by(&models, &batch), BTreeMap::from([("reloaded", vec!["later"])]));
+    models.retire_before(1);
+    assert_eq!(models.generations(), vec![1]);
+    assert_eq!(models.newest(), 1);
+}
+
+#[test]
+fn two_reloads_between_batches_keep_both() {
+    let mut models = ModelGenerations::new("v0");
+    models.insert(1, "v1");
+    models.insert(2, "v2");
+
+    let batch = [("a", 0), ("b", 1), ("c", 2)];
+    assert_eq!(scored_by(&models, &batch), BTreeMap::from([("v0", vec!["a"]), ("v1", vec!["b"]), ("v2", vec!["c"])]));
+    assert_eq!(models.newest(), 2);
+}
+
+#[test]
+fn flow_tagged_with_a_generation_the_worker_lacks_takes_the_closest() {
+    let mut models = ModelGenerations::new("v0");
+    models.insert(2, "v2");
+    models.retire_before(2);
+
+    // Generation 3 isn't here yet, the newest before it scores; one already retired takes the oldest held
+    let batch = [("ahead", 3), ("stale", 0)];
+    assert_eq!(scored_by(&models, &batch), BTreeMap::from([("v2", vec!["ahead", "stale"])]));
+}
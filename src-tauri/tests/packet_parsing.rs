@@ -0,0 +1,259 @@
+// Hand-built frames written to a capture file and parsed back the way a replay reads them.
+
+use std::path::PathBuf;
+
+use layton_lib::capture::{LinkLayer, PacketSniffer, ParseError, ParsedPacket, TransportInfo};
+
+const ETHERNET: u32 = 1;
+const NULL: u32 = 0;
+const RAW: u32 = 101;
+const LINUX_SLL: u32 = 113;
+const LINUX_SLL2: u32 = 276;
+const ACK: u8 = 0x10;
+const FIN: u8 = 0x01;
+const PSH: u8 = 0x08;
+
+const CLIENT_V4: [u8; 4] = [10, 0, 0, 1];
+const SERVER_V4: [u8; 4] = [10, 0, 0, 2];
+const CLIENT_V6: [u8; 16] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+const SERVER_V6: [u8; 16] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+fn tcp(src_port: u16, dst_port: u16, flags: u8, payload: &[u8]) -> Vec<u8> {
+    let mut segment = Vec::new();
+    segment.extend(src_port.to_be_bytes());
+    segment.extend(dst_port.to_be_bytes());
+    segment.extend(1000u32.to_be_bytes());
+    segment.extend(5000u32.to_be_bytes());
+    segment.extend([5 << 4, flags]);
+    segment.extend(502u16.to_be_bytes());
+    segment.extend([0; 4]);
+    segment.extend(payload);
+    segment
+}
+
+fn udp(src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+    let mut datagram = Vec::new();
+    datagram.extend(src_port.to_be_bytes());
+    datagram.extend(dst_port.to_be_bytes());
+    datagram.extend((8 + payload.len() as u16).to_be_bytes());
+    datagram.extend([0; 2]);
+    datagram.extend(payload);
+    datagram
+}
+
+fn ipv4(src: [u8; 4], dst: [u8; 4], protocol: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x45, 0];
+    packet.extend((20 + body.len() as u16).to_be_bytes());
+    packet.extend([0, 0, 0x40, 0, 64, protocol, 0, 0]);
+    packet.extend(src);
+    packet.extend(dst);
+    packet.extend(body);
+    packet
+}
+
+fn ipv6(src: [u8; 16], dst: [u8; 16], next_header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x60, 0, 0, 0];
+    packet.extend((body.len() as u16).to_be_bytes());
+    packet.extend([next_header, 64]);
+    packet.extend(src);
+    packet.extend(dst);
+    packet.extend(body);
+    packet
+}
+
+// 8 byte hop-by-hop header holding a single PadN option
+fn hop_by_hop(next_header: u8, body: &[u8]) -> Vec<u8> {
+    let mut header = vec![next_header, 0, 1, 4, 0, 0, 0, 0];
+    header.extend(body);
+    header
+}
+
+// 8 byte fragment header, `offset` in 8 byte units
+fn fragment(next_header: u8, offset: u16, more: bool, body: &[u8]) -> Vec<u8> {
+    let mut header = vec![next_header, 0];
+    header.extend((offset << 3 | more as u16).to_be_bytes());
+    header.extend(0x1234_5678u32.to_be_bytes());
+    header.extend(body);
+    header
+}
+
+fn ethernet(ether_type: u16, packet: &[u8]) -> Vec<u8> {
+    let mut frame = vec![2, 0, 0, 0, 0, 1, 2, 0, 0, 0, 0, 2];
+    frame.extend(ether_type.to_be_bytes());
+    frame.extend(packet);
+    frame
+}
+
+// Ethernet pads every frame to its 60 byte minimum
+fn padded(mut frame: Vec<u8>) -> Vec<u8> {
+    frame.resize(frame.len().max(60), 0);
+    frame
+}
+
+// Linux cooked header of a received packet, the protocol last
+fn linux_sll(ether_type: u16, packet: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0, 0, 0, 1, 0, 6, 2, 0, 0, 0, 0, 1, 0, 0];
+    frame.extend(ether_type.to_be_bytes());
+    frame.extend(packet);
+    frame
+}
+
+// Version 2 puts the protocol first and the interface index in
+fn linux_sll2(ether_type: u16, packet: &[u8]) -> Vec<u8> {
+    let mut frame = ether_type.to_be_bytes().to_vec();
+    frame.extend([0, 0, 0, 0, 0, 3, 0, 1, 0, 6, 2, 0, 0, 0, 0, 1, 0, 0]);
+    frame.extend(packet);
+    frame
+}
+
+// BSD loopback, the address family in the writer's (here little endian) byte order
+fn loopback(family: u32, packet: &[u8]) -> Vec<u8> {
+    let mut frame = family.to_le_bytes().to_vec();
+    frame.extend(packet);
+    frame
+}
+
+// Writes the frames as a classic pcap file and parses every record back with the file's framing
+fn parse_all(name: &str, link_type: u32, frames: &[Vec<u8>]) -> Vec<Result<ParsedPacket, ParseError>> {
+    let path: PathBuf = std::env::temp_dir().join(format!("layton_parse_{name}_{}.pcap", std::process::id()));
+    let mut file = Vec::new();
+    for word in [0xa1b2_c3d4u32, 0x0004_0002, 0, 0, 65535, link_type] {
+        file.extend(word.to_le_bytes());
+    }
+    for (i, frame) in frames.iter().enumerate() {
+        let len = frame.len() as u32;
+        for word in [1_700_000_000, i as u32, len, len] {
+            file.extend(word.to_le_bytes());
+        }
+        file.extend(frame);
+    }
+    std::fs::write(&path, file).expect("write capture");
+
+    let mut cap = pcap::Capture::from_file(&path).expect("open capture");
+    let link = LinkLayer::from_datalink(cap.get_datalink()).expect("supported link type");
+    let mut parsed = Vec::new();
+    while let Ok(packet) = cap.next_packet() {
+        parsed.push(PacketSniffer::parse_frame(link, packet.header, packet.data));
+    }
+    let _ = std::fs::remove_file(&path);
+    parsed
+}
+
+fn parse(name: &str, link_type: u32, frames: &[Vec<u8>]) -> Vec<ParsedPacket> {
+    parse_all(name, link_type, frames).into_iter().map(|p| p.expect("parse")).collect()
+}
+
+#[test]
+fn ethernet_padding_is_not_payload() {
+    let fin = ethernet(0x0800, &ipv4(CLIENT_V4, SERVER_V4, 6, &tcp(40000, 80, FIN | ACK, &[])));
+    assert_eq!(fin.len(), 54);
+    let data = ethernet(0x0800, &ipv4(CLIENT_V4, SERVER_V4, 6, &tcp(40000, 80, PSH | ACK, b"hi")));
+
+    let parsed = parse("padding", ETHERNET, &[padded(fin), padded(data)]);
+    // A FIN is acknowledged with seq + payload + 1, six bytes of padding would push that off
+    assert_eq!(parsed[0].packet_len, 60);
+    assert_eq!(parsed[0].payload_len, 0);
+    assert_eq!(parsed[1].payload_len, 2);
+}
+
+#[test]
+fn payload_is_what_the_ip_length_leaves_after_the_headers() {
+    let v4 = ethernet(0x0800, &ipv4(CLIENT_V4, SERVER_V4, 6, &tcp(40000, 80, PSH | ACK, &[7; 100])));
+    // Extension headers sit between the fixed IPv6 header and TCP, inside the payload length
+    let v6 = ethernet(0x86dd, &ipv6(CLIENT_V6, SERVER_V6, 0, &hop_by_hop(6, &tcp(40000, 80, PSH | ACK, &[7; 10]))));
+
+    let parsed = parse("ip_length", ETHERNET, &[v4, padded(v6)]);
+    assert_eq!(parsed[0].payload_len, 100);
+    assert_eq!(parsed[1].payload_len, 10);
+    // Header Length is CICFlowMeter's, the TCP header alone
+    assert_eq!((parsed[0].header_len, parsed[1].header_len), (20, 20));
+}
+
+#[test]
+fn ipv6_directions_share_one_flow_key() {
+    let request = ethernet(0x86dd, &ipv6(CLIENT_V6, SERVER_V6, 6, &tcp(40000, 443, PSH | ACK, b"hello")));
+    let response = ethernet(0x86dd, &ipv6(SERVER_V6, CLIENT_V6, 6, &tcp(443, 40000, PSH | ACK, b"world!")));
+    let query = ethernet(0x86dd, &ipv6(CLIENT_V6, SERVER_V6, 17, &udp(53000, 53, &[1; 30])));
+    let answer = ethernet(0x86dd, &ipv6(SERVER_V6, CLIENT_V6, 17, &udp(53, 53000, &[2; 90])));
+
+    let parsed = parse("ipv6_keys", ETHERNET, &[request, response, query, answer]);
+    let (client, server) = (CLIENT_V6.into(), SERVER_V6.into());
+    let endpoints: Vec<_> = parsed.iter().map(|p| (p.src_ip, p.src_port, p.dst_ip, p.dst_port)).collect();
+    assert_eq!(endpoints, vec![
+        (client, 40000, server, 443),
+        (server, 443, client, 40000),
+        (client, 53000, server, 53),
+        (server, 53, client, 53000),
+    ]);
+
+    assert_eq!(parsed[0].flow_key, parsed[1].flow_key);
+    assert_eq!(parsed[2].flow_key, parsed[3].flow_key);
+    assert_ne!(parsed[0].flow_key, parsed[2].flow_key);
+    assert_eq!((parsed[0].flow_key.protocol, parsed[2].flow_key.protocol), (6, 17));
+    assert!(matches!(parsed[2].transport, TransportInfo::Udp));
+
+    let payloads: Vec<_> = parsed.iter().map(|p| (p.payload_len, p.header_len)).collect();
+    assert_eq!(payloads, vec![(5, 20), (6, 20), (30, 8), (90, 8)]);
+}
+
+#[test]
+fn ipv6_extension_headers_are_not_payload() {
+    // Hop-by-hop then a fragment header that doesn't fragment anything (offset 0, no more fragments)
+    let atomic = ethernet(0x86dd, &ipv6(CLIENT_V6, SERVER_V6, 0, &hop_by_hop(44, &fragment(17, 0, false, &udp(53000, 53, &[1; 30])))));
+    let tcp_behind_both = ethernet(0x86dd, &ipv6(CLIENT_V6, SERVER_V6, 0, &hop_by_hop(44, &fragment(6, 0, false, &tcp(40000, 80, PSH | ACK, &[7; 12])))));
+
+    let parsed = parse("ipv6_ext", ETHERNET, &[atomic, tcp_behind_both]);
+    assert_eq!((parsed[0].payload_len, parsed[0].header_len), (30, 8));
+    assert_eq!((parsed[1].payload_len, parsed[1].header_len), (12, 20));
+    assert_eq!((parsed[0].src_port, parsed[0].dst_port), (53000, 53));
+}
+
+#[test]
+fn ipv6_fragments_are_not_parsed_as_transport() {
+    // First and second half of a fragmented UDP datagram, neither is counted into a flow
+    let datagram = udp(53000, 53, &[1; 40]);
+    let first = ethernet(0x86dd, &ipv6(CLIENT_V6, SERVER_V6, 44, &fragment(17, 0, true, &datagram[..24])));
+    let second = ethernet(0x86dd, &ipv6(CLIENT_V6, SERVER_V6, 44, &fragment(17, 3, false, &datagram[24..])));
+
+    let parsed = parse_all("ipv6_frag", ETHERNET, &[first, second]);
+    assert!(parsed.iter().all(|p| matches!(p, Err(ParseError::UnsupportedTransport))), "{parsed:?}");
+}
+
+#[test]
+fn every_supported_link_layer_parses_the_same_packet() {
+    let v4 = ipv4(CLIENT_V4, SERVER_V4, 6, &tcp(40000, 80, PSH | ACK, &[7; 25]));
+    let v6 = ipv6(CLIENT_V6, SERVER_V6, 17, &udp(53000, 53, &[1; 30]));
+    let expected = |p: &ParsedPacket| (p.flow_key, p.src_port, p.dst_port, p.payload_len, p.header_len);
+
+    let reference = parse("link_ethernet", ETHERNET, &[ethernet(0x0800, &v4), ethernet(0x86dd, &v6)]);
+    for (name, link_type, frames) in [
+        ("link_raw", RAW, vec![v4.clone(), v6.clone()]),
+        ("link_sll", LINUX_SLL, vec![linux_sll(0x0800, &v4), linux_sll(0x86dd, &v6)]),
+        ("link_sll2", LINUX_SLL2, vec![linux_sll2(0x0800, &v4), linux_sll2(0x86dd, &v6)]),
+        // AF_INET6 is 30 on macOS, 24 or 28 on the other BSDs
+        ("link_null", NULL, vec![loopback(2, &v4), loopback(30, &v6)]),
+    ] {
+        let parsed = parse(name, link_type, &frames);
+        assert_eq!(expected(&parsed[0]), expected(&reference[0]), "{name}");
+        assert_eq!(expected(&parsed[1]), expected(&reference[1]), "{name}");
+    }
+}
+
+#[test]
+fn cooked_frames_without_ip_are_not_ip() {
+    let arp = linux_sll(0x0806, &[0; 28]);
+    let truncated = vec![0; 10];
+
+    let parsed = parse_all("link_sll_other", LINUX_SLL, &[arp, truncated]);
+    assert!(matches!(parsed[0], Err(ParseError::NotIp)), "{:?}", parsed[0]);
+    assert!(matches!(parsed[1], Err(ParseError::Malformed(_))), "{:?}", parsed[1]);
+}
+
+#[test]
+fn unsupported_link_types_are_refused() {
+    // 802.11 radio frames: parsing them as Ethernet would only count garbage as malformed
+    let err = LinkLayer::from_datalink(pcap::Linktype(105)).expect_err("802.11 accepted");
+    assert!(err.contains("105"), "{err}");
+    assert_eq!(LinkLayer::from_datalink(pcap::Linktype(1)), Ok(LinkLayer::Ethernet));
+    assert_eq!(LinkLayer::from_datalink(pcap::Linktype(12)), Ok(LinkLayer::RawIp));
+}
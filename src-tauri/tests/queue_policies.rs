@@ -0,0 +1,113 @@
+// Overload policies of the stage queues and the counters they report.
+
+use std::thread;
+use std::time::Duration;
+
+use crossbeam_channel::Receiver;
+use layton_lib::queue::{queue, OverloadPolicy, QueueConfig};
+
+fn config(capacity: usize, overload: OverloadPolicy) -> QueueConfig {
+    QueueConfig { capacity, overload }
+}
+
+fn contents(rx: &Receiver<u32>) -> Vec<u32> {
+    rx.try_iter().collect()
+}
+
+#[test]
+fn drop_newest_keeps_the_first_items() {
+    let (tx, rx) = queue::<u32>("test", &config(3, OverloadPolicy::DropNewest));
+    for i in 0..5 {
+        tx.push(i).expect("open");
+    }
+    let monitor = tx.monitor();
+    assert_eq!(monitor.dropped(), 2);
+    assert_eq!(contents(&rx), vec![0, 1, 2]);
+}
+
+#[test]
+fn drop_oldest_keeps_the_newest_items() {
+    let (tx, rx) = queue::<u32>("test", &config(3, OverloadPolicy::DropOldest));
+    for i in 0..7 {
+        tx.push(i).expect("open");
+    }
+    assert_eq!(tx.monitor().dropped(), 4);
+    assert_eq!(contents(&rx), vec![4, 5, 6]);
+
+    // Room again, nothing more is dropped
+    tx.push(7).expect("open");
+    assert_eq!(tx.monitor().dropped(), 4);
+    assert_eq!(contents(&rx), vec![7]);
+}
+
+#[test]
+fn sample_keeps_one_in_n_past_half_capacity() {
+    let (tx, rx) = queue::<u32>("test", &config(8, OverloadPolicy::Sample { keep_one_in: 2 }));
+    for i in 0..10 {
+        tx.push(i).expect("open");
+    }
+    // 0..4 fill half the queue, then every other item gets in
+    assert_eq!(tx.monitor().dropped(), 3);
+    assert_eq!(contents(&rx), vec![0, 1, 2, 3, 4, 6, 8]);
+}
+
+#[test]
+fn sample_drops_when_full() {
+    let (tx, rx) = queue::<u32>("test", &config(2, OverloadPolicy::Sample { keep_one_in: 1 }));
+    for i in 0..4 {
+        tx.push(i).expect("open");
+    }
+    assert_eq!(tx.monitor().dropped(), 2);
+    assert_eq!(contents(&rx), vec![0, 1]);
+}
+
+#[test]
+fn high_watermark_is_the_longest_the_queue_got() {
+    let (tx, rx) = queue::<u32>("packets-0", &config(4, OverloadPolicy::DropNewest));
+    for i in 0..3 {
+        tx.push(i).expect("open");
+    }
+    assert_eq!(contents(&rx).len(), 3);
+    tx.push(3).expect("open");
+
+    let occupancy = tx.monitor().occupancy();
+    assert_eq!(occupancy.stage, "packets-0");
+    assert_eq!(occupancy.len, 1);
+    assert_eq!(occupancy.capacity, 4);
+    assert_eq!(occupancy.high_watermark, 3);
+    assert_eq!(occupancy.dropped, 0);
+
+    // Dropped items never count towards it
+    for i in 0..10 {
+        tx.push(i).expect("open");
+    }
+    let occupancy = tx.monitor().occupancy();
+    assert_eq!(occupancy.high_watermark, 4);
+    assert_eq!(occupancy.dropped, 7);
+}
+
+#[test]
+fn block_waits_for_room_instead_of_dropping() {
+    let (tx, rx) = queue::<u32>("test", &config(1, OverloadPolicy::Block));
+    tx.push(0).expect("open");
+
+    let producer = {
+        let tx = tx.clone();
+        thread::spawn(move || tx.push(1).expect("open"))
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert!(!producer.is_finished());
+    assert_eq!(tx.len(), 1);
+
+    assert_eq!(rx.recv().ok(), Some(0));
+    producer.join().expect("producer");
+    assert_eq!(rx.recv().ok(), Some(1));
+    assert_eq!(tx.monitor().dropped(), 0);
+}
+
+#[test]
+fn invalid_configs_are_rejected() {
+    assert!(config(0, OverloadPolicy::DropNewest).validate().is_err());
+    assert!(config(1, OverloadPolicy::Sample { keep_one_in: 0 }).validate().is_err());
+    assert!(config(1, OverloadPolicy::Sample { keep_one_in: 1 }).validate().is_ok());
+}
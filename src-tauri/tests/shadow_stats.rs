@@ -0,0 +1,90 @@
+// Champion/challenger comparison: what counts as agreement and what the report keeps.
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+
+use layton_lib::classifier::{Inference, MultiResult, ShadowStats, MAX_RECENT_DISAGREEMENTS};
+use layton_lib::processor::{FlowConfig, FlowKey, FlowRecord};
+
+fn flows(n: u16) -> Vec<FlowRecord> {
+    let (client, server) = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)));
+    (1..=n)
+        .map(|port| FlowRecord::new(FlowKey::new(client, server, port, 80, 6), port as u64, (client, port), Arc::new(FlowConfig::default())))
+        .collect()
+}
+
+fn inference(pred_label: u8, label: Option<&str>) -> Inference {
+    Inference { pred_label: Some(pred_label), probs: Vec::new(), micros: 0, threshold: None, label: label.map(String::from) }
+}
+
+fn benign() -> MultiResult {
+    MultiResult { bin: Some(inference(0, None)), multi: None, shadow: None }
+}
+
+// An attack, with the class the multiclass model picked if any
+fn attack(class: Option<&str>) -> MultiResult {
+    MultiResult { bin: Some(inference(1, None)), multi: Some(inference(0, class)), shadow: None }
+}
+
+fn counts(entries: &[(&str, u64)]) -> BTreeMap<String, u64> {
+    entries.iter().map(|(label, n)| (label.to_string(), *n)).collect()
+}
+
+#[test]
+fn report_compares_the_flows_both_scored() {
+    let stats = ShadowStats::new("challenger".into());
+    let champion = [benign(), attack(Some("DoS")), attack(Some("DoS")), benign(), MultiResult::unscored(), attack(Some("PortScan"))];
+    let challenger = [benign(), attack(Some("DoS")), attack(None), attack(Some("DoS")), benign(), MultiResult::unscored()];
+    stats.record(&flows(6), &champion, &challenger);
+
+    let report = stats.report();
+    assert_eq!(report.models, "challenger");
+    // The last two flows went unscored by one side and aren't compared
+    assert_eq!(report.flows, 4);
+    assert_eq!(report.agreed, 2);
+    assert_eq!(report.agreement, 0.5);
+    // An attack without a class still agrees with one that has a class on the attack call
+    assert_eq!(report.attack_agreed, 3);
+
+    assert_eq!(report.confusion, BTreeMap::from([
+        ("BENIGN".to_string(), counts(&[("BENIGN", 1), ("DoS", 1)])),
+        ("DoS".to_string(), counts(&[("Attack", 1), ("DoS", 1)])),
+    ]));
+    assert_eq!(report.disagreements, counts(&[("BENIGN", 1), ("DoS", 1)]));
+
+    let recent: Vec<_> = report.recent_disagreements.iter()
+        .map(|d| (d.key.port_a.min(d.key.port_b), d.champion.as_str(), d.challenger.as_str()))
+        .collect();
+    assert_eq!(recent, vec![(3, "DoS", "Attack"), (4, "BENIGN", "DoS")]);
+}
+
+#[test]
+fn empty_report_has_no_agreement() {
+    let stats = ShadowStats::new("challenger".into());
+    stats.failed();
+    stats.record(&flows(1), &[MultiResult::unscored()], &[benign()]);
+
+    let report = stats.report();
+    assert_eq!((report.flows, report.agreement, report.failed_batches), (0, 0.0, 1));
+    assert!(report.confusion.is_empty() && report.recent_disagreements.is_empty());
+}
+
+#[test]
+fn recent_disagreements_keep_only_the_latest() {
+    let stats = ShadowStats::new("challenger".into());
+    let batch = flows(20);
+    let champion = vec![benign(); 20];
+    let challenger = vec![attack(Some("Bot")); 20];
+    for _ in 0..3 {
+        stats.record(&batch, &champion, &challenger);
+    }
+
+    let report = stats.report();
+    // Every disagreement is counted, only the latest ones are listed
+    assert_eq!(report.disagreements, counts(&[("BENIGN", 60)]));
+    assert_eq!(report.recent_disagreements.len(), MAX_RECENT_DISAGREEMENTS);
+    // 60 went in, the oldest listed is the 11th: flow 11 of the first batch
+    let oldest = report.recent_disagreements.front().expect("listed");
+    assert_eq!(oldest.key.port_a.min(oldest.key.port_b), 11);
+}
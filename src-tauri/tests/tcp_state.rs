@@ -0,0 +1,245 @@
+// Walks TcpConnection through handshakes and teardowns the way the flow table feeds it,
+// plus the table splitting a reused 4-tuple into two flows.
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+
+use layton_lib::capture::{ParsedPacket, TransportInfo};
+use layton_lib::processor::{
+    FlowCloseState, FlowConfig, FlowDirection, FlowKey, FlowTable, TcpConnection, TcpHandshake, TcpTermination,
+};
+
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const PSH: u8 = 0x08;
+const ACK: u8 = 0x10;
+
+const CLIENT: FlowDirection = FlowDirection::Forward;
+const SERVER: FlowDirection = FlowDirection::Backward;
+
+// Keeps each side's sequence number, so tests only say who sends what
+struct Conn {
+    tcp: TcpConnection,
+    seq: [u32; 2],
+    now: u64,
+}
+
+impl Conn {
+    fn new() -> Self {
+        Self { tcp: TcpConnection::new(CLIENT), seq: [1000, 5000], now: 0 }
+    }
+
+    fn send(&mut self, from: FlowDirection, flags: u8, payload: u32) -> &mut Self {
+        let (this, other) = match from {
+            FlowDirection::Forward => (0, 1),
+            FlowDirection::Backward => (1, 0),
+        };
+        self.now += 1_000;
+        let ack = if flags & ACK != 0 { self.seq[other] } else { 0 };
+        self.tcp.update(from, flags, self.seq[this], ack, payload, self.now);
+        self.seq[this] = self.seq[this].wrapping_add(payload + u32::from(flags & (SYN | FIN) != 0));
+        self
+    }
+
+    fn handshake(&mut self) -> &mut Self {
+        self.send(CLIENT, SYN, 0).send(SERVER, SYN | ACK, 0).send(CLIENT, ACK, 0)
+    }
+}
+
+#[test]
+fn handshake_establishes_the_connection() {
+    let mut c = Conn::new();
+    c.send(CLIENT, SYN, 0);
+    assert_eq!(c.tcp.handshake, TcpHandshake::SynSent);
+    assert_eq!(c.tcp.termination(), TcpTermination::HandshakeIncomplete);
+    c.send(SERVER, SYN | ACK, 0);
+    assert_eq!(c.tcp.handshake, TcpHandshake::SynReceived);
+    c.send(CLIENT, ACK, 0);
+    assert_eq!(c.tcp.handshake, TcpHandshake::Established);
+    assert_eq!(c.tcp.close_state, FlowCloseState::NonClosing);
+    assert_eq!(c.tcp.termination(), TcpTermination::Open);
+}
+
+#[test]
+fn connection_picked_up_mid_stream_has_no_handshake() {
+    let mut c = Conn::new();
+    c.send(CLIENT, PSH | ACK, 100).send(SERVER, ACK, 0);
+    assert_eq!(c.tcp.handshake, TcpHandshake::NotSeen);
+    assert_eq!(c.tcp.termination(), TcpTermination::Open);
+}
+
+#[test]
+fn client_fin_first_closes_gracefully() {
+    let mut c = Conn::new();
+    c.handshake().send(CLIENT, PSH | ACK, 200).send(SERVER, ACK, 0);
+    c.send(CLIENT, FIN | ACK, 0);
+    assert_eq!(c.tcp.close_state, FlowCloseState::FinCli);
+    c.send(SERVER, ACK, 0).send(SERVER, FIN | ACK, 0);
+    assert_eq!(c.tcp.close_state, FlowCloseState::FinBoth);
+    c.send(CLIENT, ACK, 0);
+    assert_eq!(c.tcp.close_state, FlowCloseState::TimeWait);
+    assert_eq!(c.tcp.closed_at, Some(c.now));
+    assert_eq!(c.tcp.termination(), TcpTermination::GracefulClose);
+}
+
+#[test]
+fn server_fin_first_closes_gracefully() {
+    let mut c = Conn::new();
+    c.handshake().send(SERVER, PSH | ACK, 700);
+    c.send(SERVER, FIN | ACK, 0);
+    assert_eq!(c.tcp.close_state, FlowCloseState::FinSv);
+    // The client's FIN acknowledges the server's, only the last ACK is missing
+    c.send(CLIENT, FIN | ACK, 0);
+    assert_eq!(c.tcp.close_state, FlowCloseState::FinBoth);
+    c.send(SERVER, ACK, 0);
+    assert_eq!(c.tcp.close_state, FlowCloseState::TimeWait);
+    assert_eq!(c.tcp.termination(), TcpTermination::GracefulClose);
+}
+
+#[test]
+fn fin_carrying_data_is_acknowledged_after_its_payload() {
+    let mut c = Conn::new();
+    c.handshake().send(CLIENT, FIN | PSH | ACK, 300).send(SERVER, FIN | ACK, 0).send(CLIENT, ACK, 0);
+    assert_eq!(c.tcp.close_state, FlowCloseState::TimeWait);
+}
+
+#[test]
+fn half_closed_connection_keeps_carrying_data() {
+    let mut c = Conn::new();
+    c.handshake().send(CLIENT, FIN | ACK, 0).send(SERVER, ACK, 0);
+    c.send(SERVER, PSH | ACK, 1448).send(CLIENT, ACK, 0).send(SERVER, PSH | ACK, 1448);
+    assert_eq!(c.tcp.close_state, FlowCloseState::FinCli);
+    assert_eq!(c.tcp.closed_at, None);
+    assert_eq!(c.tcp.termination(), TcpTermination::HalfClosed);
+}
+
+#[test]
+fn both_fins_without_the_last_ack_stay_half_closed() {
+    let mut c = Conn::new();
+    c.handshake().send(CLIENT, FIN | ACK, 0).send(SERVER, FIN | ACK, 0);
+    assert_eq!(c.tcp.close_state, FlowCloseState::FinBoth);
+    assert_eq!(c.tcp.termination(), TcpTermination::HalfClosed);
+}
+
+#[test]
+fn time_wait_ends_after_the_grace_period() {
+    let time_wait = 2_000_000;
+    let mut c = Conn::new();
+    c.handshake().send(CLIENT, FIN | ACK, 0).send(SERVER, FIN | ACK, 0).send(CLIENT, ACK, 0);
+    let closed = c.tcp.closed_at.expect("closed");
+    assert!(!c.tcp.time_wait_over(closed, time_wait));
+    assert!(!c.tcp.time_wait_over(closed + time_wait - 1, time_wait));
+    assert!(c.tcp.time_wait_over(closed + time_wait, time_wait));
+
+    // A retransmitted FIN/ACK inside the grace period doesn't restart it
+    c.send(SERVER, FIN | ACK, 0);
+    assert_eq!(c.tcp.closed_at, Some(closed));
+}
+
+#[test]
+fn time_wait_never_ends_an_open_connection() {
+    let mut c = Conn::new();
+    c.handshake().send(CLIENT, FIN | ACK, 0);
+    assert!(!c.tcp.time_wait_over(u64::MAX, 0));
+}
+
+#[test]
+fn reset_is_attributed_to_its_sender() {
+    let mut c = Conn::new();
+    c.handshake().send(CLIENT, RST, 0);
+    assert_eq!(c.tcp.close_state, FlowCloseState::Reset(CLIENT));
+    assert_eq!(c.tcp.termination(), TcpTermination::ResetByClient);
+    assert!(c.tcp.is_reset());
+
+    let mut c = Conn::new();
+    c.handshake().send(CLIENT, PSH | ACK, 64).send(SERVER, RST | ACK, 0);
+    assert_eq!(c.tcp.close_state, FlowCloseState::Reset(SERVER));
+    assert_eq!(c.tcp.termination(), TcpTermination::ResetByServer);
+    assert_eq!(c.tcp.closed_at, Some(c.now));
+}
+
+#[test]
+fn reset_is_final() {
+    let mut c = Conn::new();
+    c.handshake().send(SERVER, RST, 0);
+    let closed = c.tcp.closed_at;
+    c.send(CLIENT, RST, 0).send(CLIENT, FIN | ACK, 0);
+    assert_eq!(c.tcp.close_state, FlowCloseState::Reset(SERVER));
+    assert_eq!(c.tcp.closed_at, closed);
+}
+
+#[test]
+fn server_rejecting_the_syn_resets_by_server() {
+    let mut c = Conn::new();
+    c.send(CLIENT, SYN, 0).send(SERVER, RST | ACK, 0);
+    assert_eq!(c.tcp.termination(), TcpTermination::ResetByServer);
+}
+
+#[test]
+fn only_a_new_syn_on_a_closed_connection_reuses_it() {
+    let mut open = Conn::new();
+    open.handshake();
+    assert!(!open.tcp.is_reused_by(SYN));
+
+    let mut closed = Conn::new();
+    closed.handshake().send(CLIENT, FIN | ACK, 0).send(SERVER, FIN | ACK, 0).send(CLIENT, ACK, 0);
+    assert!(closed.tcp.is_reused_by(SYN));
+    assert!(!closed.tcp.is_reused_by(SYN | ACK));
+    assert!(!closed.tcp.is_reused_by(ACK));
+
+    let mut reset = Conn::new();
+    reset.handshake().send(SERVER, RST, 0);
+    assert!(reset.tcp.is_reused_by(SYN));
+
+    let mut half_closed = Conn::new();
+    half_closed.handshake().send(CLIENT, FIN | ACK, 0);
+    assert!(!half_closed.tcp.is_reused_by(SYN));
+}
+
+fn packet(timestamp: u64, from_client: bool, flags: u8, seq: u32, ack: u32) -> ParsedPacket {
+    let client = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 40000);
+    let server = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 80);
+    let ((src_ip, src_port), (dst_ip, dst_port)) = if from_client { (client, server) } else { (server, client) };
+    ParsedPacket {
+        timestamp,
+        flow_key: FlowKey::new(src_ip, dst_ip, src_port, dst_port, 6),
+        src_ip,
+        dst_ip,
+        src_port,
+        dst_port,
+        packet_len: 54,
+        payload_len: 0,
+        transport: TransportInfo::Tcp { flags, window_size: 502, seq, ack },
+        header_len: 54,
+    }
+}
+
+#[test]
+fn syn_on_a_closed_four_tuple_starts_a_new_flow() {
+    let mut table = FlowTable::new(Arc::new(FlowConfig::default()));
+    let mut ended = Vec::new();
+    let first = [
+        packet(0, true, SYN, 1000, 0),
+        packet(1_000, false, SYN | ACK, 5000, 1001),
+        packet(2_000, true, ACK, 1001, 5001),
+        packet(3_000, true, FIN | ACK, 1001, 5001),
+        packet(4_000, false, FIN | ACK, 5001, 1002),
+        packet(5_000, true, ACK, 1002, 5002),
+    ];
+    for pkt in &first {
+        table.process(pkt, &mut ended);
+    }
+    // Still in TIME_WAIT, the sweep hasn't ended it
+    assert!(ended.is_empty());
+
+    table.process(&packet(6_000, true, SYN, 9000, 0), &mut ended);
+    assert_eq!(ended.len(), 1);
+    assert_eq!(ended[0].total_packets, 6);
+    assert_eq!(ended[0].tcp_termination(), Some(TcpTermination::GracefulClose));
+
+    let rest = table.drain();
+    assert_eq!(rest.len(), 1);
+    assert_eq!(rest[0].total_packets, 1);
+    assert_eq!(rest[0].flow_start_time, 6_000);
+}
@@ -0,0 +1,83 @@
+// Runtime thresholds: which minimum applies to a verdict and which changes are refused.
+
+use std::collections::BTreeMap;
+
+use layton_lib::classifier::{SharedThresholds, Thresholds};
+
+fn labels(names: &[&str]) -> Vec<String> {
+    names.iter().map(|n| n.to_string()).collect()
+}
+
+fn with_classes(classes: &[(&str, f32)]) -> Thresholds {
+    Thresholds {
+        attack: None,
+        classes: classes.iter().map(|(c, p)| (c.to_string(), *p)).collect::<BTreeMap<_, _>>(),
+    }
+}
+
+#[test]
+fn attack_override_replaces_the_bundle_threshold() {
+    let bundle = 0.85;
+    assert_eq!(Thresholds::default().attack_or(bundle), bundle);
+
+    let stricter = Thresholds { attack: Some(0.95), ..Thresholds::default() };
+    assert_eq!(stricter.attack_or(bundle), 0.95);
+
+    // Zero is an override too, not "unset"
+    let everything = Thresholds { attack: Some(0.0), ..Thresholds::default() };
+    assert_eq!(everything.attack_or(bundle), 0.0);
+}
+
+#[test]
+fn class_below_its_minimum_has_no_label() {
+    let names = labels(&["DoS", "PortScan", "Bot"]);
+    let t = with_classes(&[("DoS", 0.9)]);
+
+    assert_eq!(t.pick_class(&names, &[0.8, 0.1, 0.1]), (None, Some(0.9)));
+    assert_eq!(t.pick_class(&names, &[0.9, 0.05, 0.05]), (Some(0), Some(0.9)));
+
+    // A class without a minimum takes the argmax however low it is
+    assert_eq!(t.pick_class(&names, &[0.2, 0.3, 0.5]), (Some(2), None));
+    assert_eq!(t.pick_class(&names, &[]), (None, None));
+}
+
+#[test]
+fn out_of_range_thresholds_are_rejected() {
+    assert!(Thresholds { attack: Some(1.5), ..Thresholds::default() }.validate().is_err());
+    assert!(Thresholds { attack: Some(-0.1), ..Thresholds::default() }.validate().is_err());
+    assert!(with_classes(&[("DoS", 2.0)]).validate().is_err());
+    assert!(with_classes(&[("DoS", 1.0)]).validate().is_ok());
+}
+
+#[test]
+fn unknown_class_is_rejected() {
+    let names = labels(&["DoS", "PortScan"]);
+    let err = SharedThresholds::new(names.clone(), &with_classes(&[("Botnet", 0.5)])).err().expect("accepted");
+    assert!(err.contains("Unknown class Botnet"), "{err}");
+
+    let shared = SharedThresholds::new(names, &with_classes(&[("DoS", 0.7)])).expect("valid");
+    let err = shared.set(&with_classes(&[("DoS", 0.8), ("Botnet", 0.5)])).expect_err("accepted");
+    assert!(err.contains("Unknown class Botnet"), "{err}");
+    // Refused as a whole, the known class didn't change either
+    assert_eq!(*shared.current(), with_classes(&[("DoS", 0.7)]));
+
+    shared.set(&with_classes(&[("PortScan", 0.6)])).expect("known class");
+    assert_eq!(*shared.current(), with_classes(&[("PortScan", 0.6)]));
+}
+
+#[test]
+fn rebind_that_drops_a_class_with_a_minimum_is_refused() {
+    let shared = SharedThresholds::new(labels(&["DoS", "Bot"]), &with_classes(&[("Bot", 0.6)])).expect("valid");
+
+    let mut swapped = false;
+    let err = shared.rebind(labels(&["DoS", "PortScan"]), || swapped = true).expect_err("accepted");
+    assert!(err.contains("Unknown class Bot"), "{err}");
+    assert!(!swapped, "models swapped on a refused rebind");
+    // Still bound to the old class map
+    shared.set(&with_classes(&[("Bot", 0.7)])).expect("old class map");
+
+    // Dropping a class without a minimum is fine, and the new map applies from then on
+    assert_eq!(shared.rebind(labels(&["Bot", "PortScan"]), || 7), Ok(7));
+    shared.set(&with_classes(&[("PortScan", 0.5)])).expect("new class map");
+    assert!(shared.set(&with_classes(&[("DoS", 0.5)])).is_err());
+}
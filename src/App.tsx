@@ -5,6 +5,7 @@ import { InterfaceModal } from "./components/InterfaceModal";
 import { NetworkDashboard } from "./components/NetworkDashboard";
 import { useInterfaces } from "./hooks/useInterfaces";
 import { useCapture } from "./hooks/useCapture";
+import { usePipelineHealth } from "./hooks/usePipelineHealth";
 
 import "./styles/global.css";
 import "./styles/components.css"; 
@@ -14,6 +15,8 @@ export default function App() {
   const { list, selected, loading, error, refresh, select, setError } = useInterfaces();
   const { isCapturing, toggle } = useCapture();
   const [showModal, setShowModal] = useState(false);
+  // Errors raised while running, e.g. the capture died or the classifier fails
+  usePipelineHealth((ev) => setError(`${ev.component}: ${ev.message}`));
 
   const onToggleModal = () => setShowModal(prev => {
     const next = !prev;
@@ -1,6 +1,6 @@
 // api/network.ts
 import { invoke } from "@tauri-apps/api/core";
-import type { NetworkInterface } from "../types/network.types";
+import type { ClassifierConfig, EngineConfig, FlowConfig, FlowPage, FlowQuery, NetworkInterface, QueuesConfig, ShadowReport, Thresholds } from "../types/network.types";
 
 function normalize(err: unknown): string {
   if (err instanceof Error) return err.message;
@@ -23,4 +23,35 @@ export async function getInterfaceInfo(interfaceName: string): Promise<NetworkIn
 }
 
 export async function startSystem(iface: string) { return invoke("start_system", { interface: iface }); }
-export async function stopSystem() { return invoke("stop_system"); }
+// Resolves with the number of in-flight flows classified during shutdown
+export async function stopSystem() { return invoke<number>("stop_system"); }
+
+export type ReplayMode = "as_fast_as_possible" | "original_timestamps";
+export async function startReplay(path: string, mode: ReplayMode) { return invoke("start_replay", { path, mode }); }
+
+// Applied on the next start_system/start_replay
+export async function getFlowConfig() { return invoke<FlowConfig>("get_flow_config"); }
+export async function setFlowConfig(config: FlowConfig) { return invoke("set_flow_config", { config }); }
+export async function getEngineConfig() { return invoke<EngineConfig>("get_engine_config"); }
+export async function setEngineConfig(config: EngineConfig) { return invoke("set_engine_config", { config }); }
+export async function getClassifierConfig() { return invoke<ClassifierConfig>("get_classifier_config"); }
+export async function setClassifierConfig(config: ClassifierConfig) { return invoke("set_classifier_config", { config }); }
+export async function getThresholds() { return invoke<Thresholds>("get_thresholds"); }
+export async function setThresholds(thresholds: Thresholds) { return invoke("set_thresholds", { thresholds }); }
+// Swaps in the models on disk while capturing, resolves to the new model generation
+export async function reloadModels() { return invoke<number>("reload_models"); }
+// null unless the classifier runs shadow models
+export async function getShadowStats() { return invoke<ShadowReport | null>("get_shadow_stats"); }
+export async function getQueueConfig() { return invoke<QueuesConfig>("get_queue_config"); }
+export async function setQueueConfig(config: QueuesConfig) { return invoke("set_queue_config", { config }); }
+
+// CICFlowMeter CSV export of every classified flow
+export type ExportLabel =
+  | { mode: "none" }
+  | { mode: "fixed"; value: string }
+  | { mode: "classifier" };
+export async function startExport(path: string, label: ExportLabel) { return invoke("start_export", { path, label }); }
+export async function stopExport() { return invoke<number>("stop_export"); }
+
+// Classified flows kept in the local store
+export async function queryFlows(query: FlowQuery = {}) { return invoke<FlowPage>("query_flows", { query }); }
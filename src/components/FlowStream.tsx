@@ -2,7 +2,7 @@ import { useEffect, useRef, useState } from "react";
 import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 
 type FlowKeyDTO = {
-  ip_a: number; ip_b: number; port_a: number; port_b: number; protocol: number;
+  ip_a: string; ip_b: string; port_a: number; port_b: number; protocol: number; ip_version: 4 | 6;
 };
 
 export type ClassifiedFlowEvent = {
@@ -12,11 +12,29 @@ export type ClassifiedFlowEvent = {
   duration_us: number;
   total_packets: number;
   total_bytes: number;
+  scored: boolean;
   is_attack: boolean;
   p_attack: number;
   multi_class?: number;
   multi_label?: string;
   multi_probs?: number[];
+  // Thresholds the verdict was judged by, no multi_label means the class fell short of its own
+  attack_threshold?: number;
+  class_threshold?: number;
+  // Shadow models' verdict, never alerted on
+  shadow_label?: string;
+  shadow_p_attack?: number;
+  tcp_state?: TcpTermination;
+  icmp?: IcmpCounters;
+};
+
+type TcpTermination =
+  | "open" | "handshake_incomplete" | "half_closed" | "graceful_close" | "reset_by_client" | "reset_by_server";
+
+type IcmpCounters = {
+  icmp_type: number; icmp_code: number; identifier: number;
+  echo_requests: number; echo_replies: number; dest_unreachable: number; time_exceeded: number; other: number;
+  echo_request_rate: number; echo_reply_rate: number; dest_unreachable_rate: number; time_exceeded_rate: number; other_rate: number;
 };
 
 function flowId(ev: ClassifiedFlowEvent) {
@@ -24,14 +42,9 @@ function flowId(ev: ClassifiedFlowEvent) {
   return `${k.ip_a}:${k.port_a}->${k.ip_b}:${k.port_b}/${k.protocol}/${ev.start_us}`;
 }
 
-// IPv4 from u32 (network order)
-function ipv4(u: number) {
-  return [
-    (u >>> 24) & 255,
-    (u >>> 16) & 255,
-    (u >>>  8) & 255,
-    (u >>>  0) & 255
-  ].join(".");
+// IPv6 endpoints need brackets so the port stays readable
+function endpoint(ip: string, port: number, version: 4 | 6) {
+  return version === 6 ? `[${ip}]:${port}` : `${ip}:${port}`;
 }
 
 function fmtBytes(b: number) {
@@ -144,17 +157,26 @@ export function FlowStream({ active, maxRows = 2000 }: { active: boolean; maxRow
             return (
               <div className="trow" key={id}>
                 <div className={`badge ${r.is_attack ? "danger" : "ok"}`}>
-                  {r.multi_label ?? (r.is_attack ? "Attack" : "Benign")}
+                  {r.scored ? (r.multi_label ?? (r.is_attack ? "Attack" : "Benign")) : "ICMP"}
                 </div>
-                <div>{r.p_attack.toFixed(2)}</div>
+                <div>{r.scored ? r.p_attack.toFixed(2) : "–"}</div>
                 <div>{r.total_packets}</div>
                 <div>{fmtBytes(r.total_bytes)}</div>
                 <div>{fmtTimeUs(r.duration_us)}</div>
                 <div className="flowtext">
-                  {ipv4(r.key.ip_a)}:{r.key.port_a}
-                  &nbsp;→&nbsp;
-                  {ipv4(r.key.ip_b)}:{r.key.port_b}
-                  &nbsp;(<span className="text-muted">proto {r.key.protocol}</span>)
+                  {r.icmp ? (
+                    <>
+                      {r.key.ip_a}&nbsp;↔&nbsp;{r.key.ip_b}
+                      &nbsp;(<span className="text-muted">type {r.icmp.icmp_type}/{r.icmp.icmp_code} id {r.icmp.identifier}</span>)
+                    </>
+                  ) : (
+                    <>
+                      {endpoint(r.key.ip_a, r.key.port_a, r.key.ip_version)}
+                      &nbsp;→&nbsp;
+                      {endpoint(r.key.ip_b, r.key.port_b, r.key.ip_version)}
+                      &nbsp;(<span className="text-muted">proto {r.key.protocol}{r.tcp_state ? `, ${r.tcp_state.replace(/_/g, " ")}` : ""}</span>)
+                    </>
+                  )}
                 </div>
               </div>
             );
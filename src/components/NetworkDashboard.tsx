@@ -1,7 +1,7 @@
 import React, { useEffect, useState } from 'react';
 import { listen } from '@tauri-apps/api/event';
 import type { Event, UnlistenFn } from '@tauri-apps/api/event';
-import type { NetworkStats } from '../types/network.types';
+import { EMPTY_STATS, type NetworkStats } from '../types/network.types';
 import { FlowStream } from "./FlowStream";
 
 interface NetworkDashboardProps {
@@ -9,14 +9,7 @@ interface NetworkDashboardProps {
 }
 
 export function NetworkDashboard({ isCapturing }: NetworkDashboardProps) {
-  const [stats, setStats] = useState<NetworkStats>({
-    flow_count: 0,
-    packets_per_second: 0,
-    bytes_per_second: 0,
-    total_packets: 0,
-    total_bytes: 0,
-    uptime_seconds: 0,
-  });
+  const [stats, setStats] = useState<NetworkStats>(EMPTY_STATS);
 
   useEffect(() => {
     let unlisten: UnlistenFn | undefined;
@@ -35,14 +28,7 @@ export function NetworkDashboard({ isCapturing }: NetworkDashboardProps) {
       setupListener();
     } else {
       // Reset all stats when not capturing
-      setStats({
-        flow_count: 0,
-        packets_per_second: 0,
-        bytes_per_second: 0,
-        total_packets: 0,
-        total_bytes: 0,
-        uptime_seconds: 0,
-      });
+      setStats(EMPTY_STATS);
     }
 
     return () => {
@@ -1,16 +1,13 @@
 // hooks/useNetworkStats.ts
 import { useEffect, useState } from "react";
 import { listen, type Event, type UnlistenFn } from "@tauri-apps/api/event";
-import type { NetworkStats } from "../types/network.types";
+import { EMPTY_STATS, type NetworkStats } from "../types/network.types";
 
 export function useNetworkStats(active: boolean) {
-  const [stats, setStats] = useState<NetworkStats>({
-    flow_count: 0, packets_per_second: 0, bytes_per_second: 0,
-    total_packets: 0, total_bytes: 0, uptime_seconds: 0,
-  });
+  const [stats, setStats] = useState<NetworkStats>(EMPTY_STATS);
 
   useEffect(() => {
-    if (!active) { setStats({ flow_count:0, packets_per_second:0, bytes_per_second:0, total_packets:0, total_bytes:0, uptime_seconds:0 }); return; }
+    if (!active) { setStats(EMPTY_STATS); return; }
     let unlisten: UnlistenFn | undefined;
     const latest = { current: stats };
     let tick: number | undefined;
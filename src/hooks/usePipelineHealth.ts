@@ -0,0 +1,23 @@
+// hooks/usePipelineHealth.ts
+import { useEffect, useState } from "react";
+import { listen, type Event, type UnlistenFn } from "@tauri-apps/api/event";
+import type { HealthEvent } from "../types/network.types";
+
+// Keeps the latest pipeline-health events and hands errors to onError
+export function usePipelineHealth(onError: (ev: HealthEvent) => void, keep = 50) {
+  const [events, setEvents] = useState<HealthEvent[]>([]);
+
+  useEffect(() => {
+    let unlisten: UnlistenFn | undefined;
+    (async () => {
+      unlisten = await listen<HealthEvent>("pipeline-health", (e: Event<HealthEvent>) => {
+        setEvents(prev => [e.payload, ...prev].slice(0, keep));
+        if (e.payload.severity === "error") onError(e.payload);
+      });
+    })();
+    return () => { if (unlisten) unlisten(); };
+  // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, [keep]);
+
+  return events;
+}
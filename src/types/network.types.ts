@@ -1,3 +1,5 @@
+import type { ClassifiedFlowEvent } from "../components/FlowStream";
+
 export interface NetworkInterface{
     name: string;
     description: string;
@@ -13,4 +15,161 @@ export interface NetworkStats{
     total_packets: number,
     total_bytes: number,
     uptime_seconds: number,
-}
\ No newline at end of file
+    tcp_flows: number,
+    udp_flows: number,
+    icmp_flows: number,
+    icmp_packets: number,
+    // Flows ended early because the flow table was full
+    evicted_flows: number,
+    // Packets lost before the engine, totals since start
+    kernel_drops: number,
+    interface_drops: number,
+    parse_errors_not_ip: number,
+    parse_errors_unsupported_transport: number,
+    parse_errors_malformed: number,
+    channel_full_drops: number,
+    classifier_queue_depth: number,
+    // Model runs, each scoring a batch of flows
+    inference_batches: number,
+    inference_last_batch_size: number,
+    inference_last_batch_us: number,
+    inference_avg_batch_us: number,
+    queues: QueueOccupancy[],
+}
+
+export interface QueueOccupancy{
+    stage: string,  // "packets" ("packets.N" with several engine workers), "flows" or "results"
+    len: number,
+    capacity: number,
+    high_watermark: number,
+    dropped: number,
+}
+
+export const EMPTY_STATS: NetworkStats = {
+    flow_count: 0,
+    packets_per_second: 0,
+    bytes_per_second: 0,
+    total_packets: 0,
+    total_bytes: 0,
+    uptime_seconds: 0,
+    tcp_flows: 0,
+    udp_flows: 0,
+    icmp_flows: 0,
+    icmp_packets: 0,
+    evicted_flows: 0,
+    kernel_drops: 0,
+    interface_drops: 0,
+    parse_errors_not_ip: 0,
+    parse_errors_unsupported_transport: 0,
+    parse_errors_malformed: 0,
+    channel_full_drops: 0,
+    classifier_queue_depth: 0,
+    inference_batches: 0,
+    inference_last_batch_size: 0,
+    inference_last_batch_us: 0,
+    inference_avg_batch_us: 0,
+    queues: [],
+};
+// Timeouts in microseconds, mirrors FlowConfig on the backend
+export interface FlowConfig{
+    flow_timeout_us: number,
+    tcp_idle_timeout_us: number,
+    udp_idle_timeout_us: number,
+    icmp_idle_timeout_us: number,
+    time_wait_us: number,
+    subflow_timeout_us: number,
+    activity_timeout_us: number,
+    bulk_threshold: number,
+}
+
+export type EvictionPolicy = "lru" | "oldest_first";
+
+// Flow engine threads and flow table limits, mirrors EngineConfig on the backend
+export interface EngineConfig{
+    workers: number,
+    max_flows: number,      // 0 for no limit
+    max_memory_mb: number,  // 0 for no limit
+    eviction: EvictionPolicy,
+}
+
+// Inference batching, mirrors ClassifierConfig on the backend
+export interface ClassifierConfig{
+    batch_size: number,
+    batch_wait_us: number,
+    workers: number,        // Classifier threads, each with its own model sessions
+    watch_models_secs: number,  // Reloads the models when their files change, checked this often. 0 disables it
+    shadow_models?: string | null,  // Challenger bundle scored next to the models in use, never alerted on
+}
+
+// Champion/challenger comparison, mirrors ShadowReport. Also comes as the `shadow-stats` event
+export interface ShadowReport{
+    models: string,
+    flows: number,                  // Scored by both
+    agreed: number,                 // Same label
+    agreement: number,              // agreed / flows
+    attack_agreed: number,          // Same attack or benign call
+    failed_batches: number,
+    confusion: Record<string, Record<string, number>>,  // Champion label -> challenger label -> flows
+    disagreements: Record<string, number>,              // Per champion label
+    recent_disagreements: ShadowDisagreement[],         // Latest 50, oldest first
+}
+
+export interface ShadowDisagreement{
+    key: { ip_a: string, ip_b: string, port_a: number, port_b: number, protocol: number, ip_version: 4 | 6 },
+    end_us: number,
+    champion: string,               // Label of the models in use
+    challenger: string,
+}
+
+// Decision thresholds, mirrors Thresholds on the backend. Changes reach a running capture
+export interface Thresholds{
+    attack?: number,                    // Minimum P(attack), the model bundle's threshold when missing
+    classes: Record<string, number>,    // Minimum probability per multiclass label
+}
+
+// Filters of query_flows, mirrors FlowQuery on the backend. Missing fields don't filter
+export interface FlowQuery{
+    from_us?: number,
+    to_us?: number,
+    ip?: string,
+    port?: number,
+    protocol?: number,
+    label?: string,
+    attacks_only?: boolean,
+    min_p_attack?: number,
+    max_p_attack?: number,
+    sort?: "end_time" | "start_time" | "duration" | "p_attack" | "packets" | "bytes",
+    descending?: boolean,
+    offset?: number,
+    limit?: number,
+}
+
+export type StoredFlow = ClassifiedFlowEvent & { id: number };
+
+export interface FlowPage{
+    total: number,
+    flows: StoredFlow[],
+}
+
+// Payload of the pipeline-health event
+export interface HealthEvent{
+    component: "sniffer" | "processor" | "classifier" | "export" | "store",
+    severity: "info" | "warning" | "error",
+    message: string,
+    timestamp_us: number,
+}
+
+// Stage queues, mirrors QueuesConfig on the backend
+export type OverloadPolicy =
+    | { policy: "drop_newest" }
+    | { policy: "drop_oldest" }
+    | { policy: "sample"; keep_one_in: number }
+    | { policy: "block" };
+
+export type QueueConfig = { capacity: number } & OverloadPolicy;
+
+export interface QueuesConfig{
+    packets: QueueConfig,
+    flows: QueueConfig,
+    results: QueueConfig,
+}